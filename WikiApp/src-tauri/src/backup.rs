@@ -0,0 +1,491 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Local};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::postgres::PgConnectOptions;
+
+use crate::fsutil::{copy_dir_recursive, write_atomic};
+use crate::pg_admin;
+use crate::postgres_manager::PgTools;
+
+const DUMP_FILE: &str = "wiki.dump";
+const DATA_DIR: &str = "data";
+const CONFIG_FILE: &str = "config.yml";
+const MANIFEST_FILE: &str = "manifest.json";
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Réglages des sauvegardes automatiques (section `backup` de `launcher.json`).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct BackupSettings {
+    pub enabled: bool,
+    /// Intervalle minimal entre deux sauvegardes planifiées.
+    pub interval_hours: u32,
+    /// Nombre de jours (une sauvegarde par jour) conservés.
+    pub keep_daily: usize,
+    /// Nombre de semaines (une sauvegarde par semaine) conservées.
+    pub keep_weekly: usize,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self { enabled: true, interval_hours: 24, keep_daily: 7, keep_weekly: 4 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupKind {
+    Manual,
+    Scheduled,
+    /// Sauvegarde de sécurité prise automatiquement avant une restauration.
+    PreRestore,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BackupFile {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BackupManifest {
+    pub id: String,
+    pub kind: BackupKind,
+    pub created_at: DateTime<Local>,
+    pub db_name: String,
+    pub files: Vec<BackupFile>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BackupInfo {
+    pub id: String,
+    pub kind: BackupKind,
+    pub created_at: DateTime<Local>,
+    pub size_bytes: u64,
+    pub path: PathBuf,
+}
+
+/// Emplacements des éléments sauvegardés.
+#[derive(Clone)]
+pub struct BackupPaths {
+    pub backups_dir: PathBuf,
+    pub wiki_data_dir: PathBuf,
+    pub wiki_config_path: PathBuf,
+}
+
+/// Base à sauvegarder / restaurer.
+#[derive(Clone)]
+pub struct BackupTarget {
+    pub tools: PgTools,
+    /// Connexion superutilisateur à la base de maintenance `postgres`.
+    pub admin_options: PgConnectOptions,
+    pub db_name: String,
+    pub owner: String,
+}
+
+/// Crée une sauvegarde complète : dump PostgreSQL (format custom), dossier `data/`
+/// de Wiki.js et `config.yml`, puis vérifie son intégrité.
+pub async fn create_backup(target: &BackupTarget, paths: &BackupPaths, kind: BackupKind) -> Result<BackupInfo, String> {
+    let created_at = Local::now();
+    let id = created_at.format("%Y%m%d-%H%M%S").to_string();
+    let final_dir = paths.backups_dir.join(&id);
+    let partial_dir = paths.backups_dir.join(format!("{}{}", id, PARTIAL_SUFFIX));
+    if final_dir.exists() {
+        return Err(format!("Une sauvegarde {} existe déjà", id));
+    }
+
+    fs::create_dir_all(&partial_dir).map_err(|e| format!("Impossible de créer {:?}: {}", partial_dir, e))?;
+    let result = write_backup(target, paths, &partial_dir, &id, kind, created_at).await;
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&partial_dir);
+        return Err(e);
+    }
+    fs::rename(&partial_dir, &final_dir).map_err(|e| format!("Finalisation de la sauvegarde impossible: {}", e))?;
+
+    println!("💾 Sauvegarde {} terminée : {:?}", id, final_dir);
+    read_info(&final_dir)
+}
+
+async fn write_backup(
+    target: &BackupTarget,
+    paths: &BackupPaths,
+    dir: &Path,
+    id: &str,
+    kind: BackupKind,
+    created_at: DateTime<Local>,
+) -> Result<(), String> {
+    let dump_path = dir.join(DUMP_FILE);
+    let mut cmd = tokio::process::Command::from(target.tools.command("pg_dump"));
+    cmd.arg("--format=custom")
+        .arg("--file").arg(&dump_path)
+        .arg("--dbname").arg(&target.db_name);
+    run_tool(cmd, "pg_dump").await?;
+
+    let (paths, backup) = (paths.clone(), dir.to_path_buf());
+    let files = blocking(move || {
+        if paths.wiki_data_dir.exists() {
+            copy_dir_recursive(&paths.wiki_data_dir, &backup.join(DATA_DIR))?;
+        }
+        if paths.wiki_config_path.exists() {
+            fs::copy(&paths.wiki_config_path, backup.join(CONFIG_FILE))
+                .map_err(|e| format!("Copie de config.yml impossible: {}", e))?;
+        }
+        hash_tree(&backup)
+    })
+    .await?;
+
+    let manifest = BackupManifest {
+        id: id.to_string(),
+        kind,
+        created_at,
+        db_name: target.db_name.clone(),
+        files,
+    };
+    let content = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    fs::write(dir.join(MANIFEST_FILE), content).map_err(|e| format!("Ecriture du manifeste impossible: {}", e))?;
+
+    verify_backup(target, dir).await
+}
+
+/// Contrôle d'intégrité : sommes SHA-256 du manifeste et lisibilité du dump par `pg_restore --list`.
+pub async fn verify_backup(target: &BackupTarget, dir: &Path) -> Result<(), String> {
+    let backup = dir.to_path_buf();
+    let manifest = blocking(move || {
+        let manifest = read_manifest(&backup)?;
+        for file in &manifest.files {
+            let (size, sha256) = hash_file(&backup.join(&file.path))?;
+            if size != file.size || sha256 != file.sha256 {
+                return Err(format!("Sauvegarde {} corrompue : {} ne correspond pas au manifeste", manifest.id, file.path));
+            }
+        }
+        Ok(manifest)
+    })
+    .await?;
+
+    let mut cmd = tokio::process::Command::from(target.tools.command("pg_restore"));
+    cmd.arg("--list").arg(dir.join(DUMP_FILE));
+    let listing = run_tool(cmd, "pg_restore --list").await?;
+    if !listing.lines().any(|line| !line.starts_with(';') && !line.trim().is_empty()) {
+        return Err(format!("Sauvegarde {} : le dump ne contient aucun objet", manifest.id));
+    }
+    Ok(())
+}
+
+/// Restaure une sauvegarde. Wiki.js doit être arrêté par l'appelant.
+/// Le dump est restauré dans une base temporaire, qui ne remplace la base actuelle qu'une
+/// fois `pg_restore` réussi ; `data/` et `config.yml` sont ensuite remis en place.
+pub async fn restore_backup(target: &BackupTarget, paths: &BackupPaths, id: &str) -> Result<(), String> {
+    let dir = backup_dir(paths, id)?;
+    verify_backup(target, &dir).await?;
+
+    let staging_db = format!("{}_restore", target.db_name);
+    let restored = restore_into(target, &dir, &staging_db).await;
+    if let Err(e) = restored {
+        if let Ok(mut conn) = pg_admin::connect(&target.admin_options).await {
+            let _ = pg_admin::drop_database(&mut conn, &staging_db).await;
+            pg_admin::close(conn).await;
+        }
+        return Err(format!("Restauration annulée, la base actuelle est intacte: {}", e));
+    }
+    swap_databases(target, &staging_db).await?;
+
+    let saved_data = dir.join(DATA_DIR);
+    let saved_config = dir.join(CONFIG_FILE);
+    let paths = paths.clone();
+    blocking(move || {
+        if saved_data.exists() {
+            // Copie à côté, puis remplacement : une copie interrompue ne vide pas `data/`
+            let mut staging_name = paths.wiki_data_dir.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+            staging_name.push(PARTIAL_SUFFIX);
+            let staging = paths.wiki_data_dir.with_file_name(staging_name);
+            let _ = fs::remove_dir_all(&staging);
+            copy_dir_recursive(&saved_data, &staging)?;
+            if paths.wiki_data_dir.exists() {
+                fs::remove_dir_all(&paths.wiki_data_dir)
+                    .map_err(|e| format!("Impossible de vider {:?}: {}", paths.wiki_data_dir, e))?;
+            }
+            fs::rename(&staging, &paths.wiki_data_dir)
+                .map_err(|e| format!("Remise en place de {:?} impossible: {}", paths.wiki_data_dir, e))?;
+        }
+        if saved_config.exists() {
+            let content = fs::read(&saved_config).map_err(|e| format!("Lecture de {:?} impossible: {}", saved_config, e))?;
+            write_atomic(&paths.wiki_config_path, &content)?;
+        }
+        Ok(())
+    })
+    .await?;
+
+    println!("♻️ Sauvegarde {} restaurée.", id);
+    Ok(())
+}
+
+/// Recrée `db_name` (vide, propriétaire applicatif) et y restaure le dump de `dir`.
+async fn restore_into(target: &BackupTarget, dir: &Path, db_name: &str) -> Result<(), String> {
+    let mut conn = pg_admin::connect(&target.admin_options).await?;
+    let result = async {
+        pg_admin::terminate_connections(&mut conn, db_name).await?;
+        pg_admin::drop_database(&mut conn, db_name).await?;
+        pg_admin::ensure_database(&mut conn, db_name, &target.owner).await
+    }.await;
+    pg_admin::close(conn).await;
+    result?;
+
+    let mut cmd = tokio::process::Command::from(target.tools.command("pg_restore"));
+    cmd.arg("--exit-on-error")
+        .arg("--dbname").arg(db_name)
+        .arg(dir.join(DUMP_FILE));
+    run_tool(cmd, "pg_restore").await?;
+
+    let mut conn = pg_admin::connect(&target.admin_options.clone().database(db_name)).await?;
+    let result = pg_admin::grant_database_ownership(&mut conn, db_name, &target.owner).await;
+    pg_admin::close(conn).await;
+    result
+}
+
+/// Remplace la base actuelle par `staging_db`. L'ancienne base est mise de côté le temps de
+/// la bascule, remise en place si le renommage échoue, puis supprimée.
+async fn swap_databases(target: &BackupTarget, staging_db: &str) -> Result<(), String> {
+    let db_name = &target.db_name;
+    let retired_db = format!("{}_replaced", db_name);
+    let mut conn = pg_admin::connect(&target.admin_options).await?;
+    let result = async {
+        pg_admin::drop_database(&mut conn, &retired_db).await?;
+        let had_live = pg_admin::database_exists(&mut conn, db_name).await?;
+        if had_live {
+            pg_admin::terminate_connections(&mut conn, db_name).await?;
+            pg_admin::rename_database(&mut conn, db_name, &retired_db).await?;
+        }
+        pg_admin::terminate_connections(&mut conn, staging_db).await?;
+        if let Err(e) = pg_admin::rename_database(&mut conn, staging_db, db_name).await {
+            if had_live {
+                let _ = pg_admin::rename_database(&mut conn, &retired_db, db_name).await;
+            }
+            return Err(e);
+        }
+        if let Err(e) = pg_admin::drop_database(&mut conn, &retired_db).await {
+            eprintln!("⚠️ Ancienne base {} conservée: {}", retired_db, e);
+        }
+        Ok(())
+    }.await;
+    pg_admin::close(conn).await;
+    result
+}
+
+/// Sauvegardes disponibles, de la plus récente à la plus ancienne.
+pub fn list_backups(paths: &BackupPaths) -> Result<Vec<BackupInfo>, String> {
+    if !paths.backups_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let entries = fs::read_dir(&paths.backups_dir)
+        .map_err(|e| format!("Lecture de {:?} impossible: {}", paths.backups_dir, e))?;
+    let mut backups: Vec<BackupInfo> = entries
+        .flatten()
+        .filter(|entry| !entry.file_name().to_string_lossy().ends_with(PARTIAL_SUFFIX))
+        .filter_map(|entry| read_info(&entry.path()).ok())
+        .collect();
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    Ok(backups)
+}
+
+/// Supprime les sauvegardes planifiées hors politique de rétention (N quotidiennes, M hebdomadaires).
+/// Les sauvegardes manuelles et de sécurité ne sont jamais supprimées automatiquement, pas plus
+/// que celles de `protected`. Renvoie les identifiants supprimés.
+pub fn apply_retention(paths: &BackupPaths, settings: &BackupSettings, protected: &[&str]) -> Result<Vec<String>, String> {
+    let backups = list_backups(paths)?;
+    let mut removed = Vec::new();
+    for backup in expired(&backups, settings, protected) {
+        fs::remove_dir_all(&backup.path)
+            .map_err(|e| format!("Suppression de la sauvegarde {} impossible: {}", backup.id, e))?;
+        removed.push(backup.id.clone());
+    }
+    Ok(removed)
+}
+
+/// Sauvegardes planifiées à supprimer ; `backups` va de la plus récente à la plus ancienne.
+/// La plus récente est toujours conservée.
+fn expired<'a>(backups: &'a [BackupInfo], settings: &BackupSettings, protected: &[&str]) -> Vec<&'a BackupInfo> {
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    let mut expired = Vec::new();
+
+    let scheduled = backups.iter().filter(|backup| backup.kind == BackupKind::Scheduled);
+    for (index, backup) in scheduled.enumerate() {
+        let day = backup.created_at.date_naive();
+        let week = day.iso_week();
+        let mut keep = index == 0 || protected.contains(&backup.id.as_str());
+        if !days.contains(&day) && days.len() < settings.keep_daily {
+            days.insert(day);
+            keep = true;
+        }
+        if !weeks.contains(&(week.year(), week.week())) && weeks.len() < settings.keep_weekly {
+            weeks.insert((week.year(), week.week()));
+            keep = true;
+        }
+        if !keep {
+            expired.push(backup);
+        }
+    }
+    expired
+}
+
+fn backup_dir(paths: &BackupPaths, id: &str) -> Result<PathBuf, String> {
+    // L'identifiant vient de l'UI : refuser tout ce qui pourrait sortir du dossier des sauvegardes
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(format!("Identifiant de sauvegarde invalide: {:?}", id));
+    }
+    let dir = paths.backups_dir.join(id);
+    if !dir.join(MANIFEST_FILE).exists() {
+        return Err(format!("Sauvegarde {} introuvable", id));
+    }
+    Ok(dir)
+}
+
+fn read_manifest(dir: &Path) -> Result<BackupManifest, String> {
+    let content = fs::read_to_string(dir.join(MANIFEST_FILE))
+        .map_err(|e| format!("Manifeste illisible dans {:?}: {}", dir, e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Manifeste invalide dans {:?}: {}", dir, e))
+}
+
+fn read_info(dir: &Path) -> Result<BackupInfo, String> {
+    let manifest = read_manifest(dir)?;
+    Ok(BackupInfo {
+        size_bytes: manifest.files.iter().map(|f| f.size).sum(),
+        id: manifest.id,
+        kind: manifest.kind,
+        created_at: manifest.created_at,
+        path: dir.to_path_buf(),
+    })
+}
+
+/// Exécute une opération de fichiers lourde hors des threads du runtime async.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T, String> + Send + 'static) -> Result<T, String> {
+    tokio::task::spawn_blocking(f).await.map_err(|e| format!("Tâche interrompue: {}", e))?
+}
+
+async fn run_tool(mut cmd: tokio::process::Command, name: &str) -> Result<String, String> {
+    let output = cmd.output().await.map_err(|e| format!("Lancement de {} impossible: {}", name, e))?;
+    if !output.status.success() {
+        return Err(format!("{} a échoué: {}", name, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Empreintes de tous les fichiers de `root` (chemins relatifs, séparateur `/`).
+fn hash_tree(root: &Path) -> Result<Vec<BackupFile>, String> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = fs::read_dir(&dir).map_err(|e| format!("Lecture de {:?} impossible: {}", dir, e))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let relative = path.strip_prefix(root).map_err(|e| e.to_string())?;
+            if relative == Path::new(MANIFEST_FILE) {
+                continue;
+            }
+            let (size, sha256) = hash_file(&path)?;
+            let parts: Vec<String> = relative.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+            files.push(BackupFile { path: parts.join("/"), size, sha256 });
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+fn hash_file(path: &Path) -> Result<(u64, String), String> {
+    let mut file = fs::File::open(path).map_err(|e| format!("Lecture de {:?} impossible: {}", path, e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    let mut size = 0u64;
+    loop {
+        let n = file.read(&mut buffer).map_err(|e| format!("Lecture de {:?} impossible: {}", path, e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        size += n as u64;
+    }
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn backup(id: &str, kind: BackupKind, day: u32, hour: u32) -> BackupInfo {
+        BackupInfo {
+            id: id.to_string(),
+            kind,
+            created_at: Local.with_ymd_and_hms(2026, 3, day, hour, 0, 0).unwrap(),
+            size_bytes: 0,
+            path: PathBuf::from(id),
+        }
+    }
+
+    fn expired_ids(backups: &[BackupInfo], keep_daily: usize, keep_weekly: usize, protected: &[&str]) -> Vec<String> {
+        let settings = BackupSettings { enabled: true, interval_hours: 24, keep_daily, keep_weekly };
+        expired(backups, &settings, protected).into_iter().map(|b| b.id.clone()).collect()
+    }
+
+    #[test]
+    fn manual_and_pre_restore_backups_are_never_expired() {
+        // Du plus récent au plus ancien, tous le même jour
+        let backups = vec![
+            backup("m2", BackupKind::Manual, 10, 15),
+            backup("pre", BackupKind::PreRestore, 10, 14),
+            backup("m1", BackupKind::Manual, 10, 9),
+            backup("s1", BackupKind::Scheduled, 10, 2),
+        ];
+        assert!(expired_ids(&backups, 1, 1, &[]).is_empty());
+    }
+
+    #[test]
+    fn keeps_one_scheduled_backup_per_day_and_per_week() {
+        // Dimanche 15 mars 2026 -> lundi 2 mars 2026
+        let backups: Vec<BackupInfo> = (2..=15)
+            .rev()
+            .map(|day| backup(&format!("s{}", day), BackupKind::Scheduled, day, 2))
+            .collect();
+        let expired = expired_ids(&backups, 3, 2, &[]);
+        // 15, 14, 13 (quotidiennes) ; semaine du 9 au 15 déjà couverte, 8 ouvre la semaine du 2 au 8
+        let kept: Vec<String> = backups.iter().map(|b| b.id.clone()).filter(|id| !expired.contains(id)).collect();
+        assert_eq!(kept, vec!["s15", "s14", "s13", "s8"]);
+    }
+
+    #[test]
+    fn extra_scheduled_backups_of_a_kept_day_are_expired() {
+        let backups = vec![
+            backup("late", BackupKind::Scheduled, 10, 20),
+            backup("early", BackupKind::Scheduled, 10, 2),
+        ];
+        assert_eq!(expired_ids(&backups, 7, 4, &[]), vec!["early"]);
+    }
+
+    #[test]
+    fn protected_backup_is_kept() {
+        let backups = vec![
+            backup("late", BackupKind::Scheduled, 10, 20),
+            backup("early", BackupKind::Scheduled, 10, 2),
+        ];
+        assert!(expired_ids(&backups, 7, 4, &["early"]).is_empty());
+    }
+
+    #[test]
+    fn most_recent_scheduled_backup_survives_zero_retention() {
+        let backups = vec![
+            backup("new", BackupKind::Scheduled, 11, 2),
+            backup("old", BackupKind::Scheduled, 10, 2),
+        ];
+        assert_eq!(expired_ids(&backups, 0, 0, &[]), vec!["old"]);
+    }
+}
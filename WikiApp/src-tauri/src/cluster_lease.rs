@@ -0,0 +1,285 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::fsutil::{write_atomic, FileLock};
+
+/// Identifiant de WikiTools dans le registre (CollabTools utilise le sien).
+pub const APP_ID: &str = "wikitools";
+/// Fréquence à laquelle un consommateur doit rafraîchir son bail.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// Au-delà, un bail non rafraîchi est considéré comme abandonné (application plantée).
+const LEASE_TTL: Duration = Duration::from_secs(120);
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+/// Présent tant que le cluster a été démarré par un consommateur inscrit au registre.
+const STARTED_MARKER: &str = "started_by_consumer";
+
+/// Bail d'une application sur le cluster : un fichier `<app>-<pid>.json` par processus.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Lease {
+    pub app: String,
+    pub pid: u32,
+    pub started_at: DateTime<Local>,
+    pub heartbeat_at: DateTime<Local>,
+}
+
+/// Registre des applications utilisant un cluster PostgreSQL partagé
+/// (`<cluster>/consumers/`). Le cluster n'est arrêté que lorsque le dernier consommateur
+/// le quitte, et seulement s'il a été démarré par l'un d'eux.
+#[derive(Clone)]
+pub struct LeaseRegistry {
+    dir: PathBuf,
+    app: String,
+    pid: u32,
+}
+
+impl LeaseRegistry {
+    pub fn new(cluster_root: &Path, app: &str) -> Self {
+        Self {
+            dir: cluster_root.join("consumers"),
+            app: app.to_string(),
+            pid: std::process::id(),
+        }
+    }
+
+    fn own_path(&self) -> PathBuf {
+        self.dir.join(format!("{}-{}.json", self.app, self.pid))
+    }
+
+    /// Verrou du registre : à tenir pendant « s'inscrire + démarrer » et « vérifier + arrêter ».
+    pub fn lock(&self) -> Result<FileLock, String> {
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Impossible de créer {:?}: {}", self.dir, e))?;
+        FileLock::acquire(&self.dir.join(".lock"), LOCK_TIMEOUT)
+    }
+
+    /// Inscrit (ou rafraîchit) le bail de ce processus. Sert aussi de battement de cœur : un
+    /// bail supprimé entre-temps (jugé expiré après une mise en veille, par exemple) est recréé.
+    pub fn acquire(&self) -> Result<(), String> {
+        let now = Local::now();
+        let started_at = self.read(&self.own_path()).map(|lease| lease.started_at).unwrap_or(now);
+        let lease = Lease { app: self.app.clone(), pid: self.pid, started_at, heartbeat_at: now };
+        let content = serde_json::to_string_pretty(&lease).map_err(|e| e.to_string())?;
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Impossible de créer {:?}: {}", self.dir, e))?;
+        write_atomic(&self.own_path(), content.as_bytes())
+    }
+
+    /// Battement de cœur : rafraîchit notre bail sous le verrou du registre, puis supprime les
+    /// baux expirés des autres. Bloquant (attente du verrou).
+    pub fn heartbeat(&self) -> Result<(), String> {
+        let lock = self.lock()?;
+        self.acquire()?;
+        self.prune_expired(&lock);
+        Ok(())
+    }
+
+    pub fn release(&self) {
+        let _ = fs::remove_file(self.own_path());
+    }
+
+    /// Baux actifs, y compris le nôtre. Un bail illisible (en cours d'écriture, ou écrit dans un
+    /// format plus récent par CollabTools) compte tant que son fichier a moins de `LEASE_TTL`.
+    pub fn active(&self) -> Vec<Lease> {
+        let now = Local::now();
+        let mut leases: Vec<Lease> = self.entries()
+            .into_iter()
+            .filter_map(|(path, lease)| lease.or_else(|| unreadable_lease(&path)))
+            .filter(|lease| !is_expired(lease, now))
+            .collect();
+        leases.sort_by_key(|lease| lease.started_at);
+        leases
+    }
+
+    /// Supprime les baux expirés. `_lock` (verrou du registre) garantit qu'aucun consommateur
+    /// n'est en train de s'inscrire ; les baux illisibles ne sont jamais supprimés.
+    pub fn prune_expired(&self, _lock: &FileLock) {
+        let now = Local::now();
+        for (path, lease) in self.entries() {
+            if lease.is_some_and(|lease| is_expired(&lease, now)) {
+                println!("🧹 Bail expiré supprimé : {:?}", path);
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+
+    /// Fichiers de bail du registre, avec leur contenu s'il est lisible.
+    fn entries(&self) -> Vec<(PathBuf, Option<Lease>)> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .map(|path| {
+                let lease = self.read(&path);
+                (path, lease)
+            })
+            .collect()
+    }
+
+    /// Baux actifs des autres processus.
+    pub fn others(&self) -> Vec<Lease> {
+        self.active()
+            .into_iter()
+            .filter(|lease| !(lease.app == self.app && lease.pid == self.pid))
+            .collect()
+    }
+
+    pub fn is_self(&self, lease: &Lease) -> bool {
+        lease.app == self.app && lease.pid == self.pid
+    }
+
+    pub fn mark_started(&self) -> Result<(), String> {
+        write_atomic(&self.dir.join(STARTED_MARKER), format!("{}-{}", self.app, self.pid).as_bytes())
+    }
+
+    pub fn clear_started(&self) {
+        let _ = fs::remove_file(self.dir.join(STARTED_MARKER));
+    }
+
+    /// Le cluster en cours a-t-il été démarré via le registre ? Sinon (démarrage manuel, ancienne
+    /// version de CollabTools), personne d'inscrit n'est autorisé à l'arrêter.
+    pub fn started_by_consumer(&self) -> bool {
+        self.dir.join(STARTED_MARKER).exists()
+    }
+
+    fn read(&self, path: &Path) -> Option<Lease> {
+        let content = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+}
+
+fn is_expired(lease: &Lease, now: DateTime<Local>) -> bool {
+    (now - lease.heartbeat_at).to_std().unwrap_or_default() > LEASE_TTL
+}
+
+/// Bail déduit du nom (`<app>-<pid>.json`) et de la date de modification d'un fichier illisible.
+fn unreadable_lease(path: &Path) -> Option<Lease> {
+    let modified: DateTime<Local> = fs::metadata(path).and_then(|meta| meta.modified()).ok()?.into();
+    let stem = path.file_stem()?.to_string_lossy();
+    let (app, pid) = match stem.rsplit_once('-') {
+        Some((app, pid)) => (app.to_string(), pid.parse().unwrap_or(0)),
+        None => (stem.to_string(), 0),
+    };
+    Some(Lease { app, pid, started_at: modified, heartbeat_at: modified })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(root: &Path, app: &str, pid: u32) -> LeaseRegistry {
+        LeaseRegistry { dir: root.join("consumers"), app: app.to_string(), pid }
+    }
+
+    #[test]
+    fn acquire_registers_and_release_removes_our_lease() {
+        let root = tempfile::tempdir().unwrap();
+        let ours = registry(root.path(), APP_ID, 100);
+        let collab = registry(root.path(), "collabtools", 200);
+        ours.acquire().unwrap();
+        collab.acquire().unwrap();
+
+        assert_eq!(ours.active().len(), 2);
+        let others = ours.others();
+        assert_eq!(others.len(), 1);
+        assert_eq!((others[0].app.as_str(), others[0].pid), ("collabtools", 200));
+        assert!(ours.is_self(&ours.active().into_iter().find(|lease| lease.pid == 100).unwrap()));
+
+        ours.release();
+        assert!(collab.others().is_empty());
+        assert_eq!(ours.active().len(), 1);
+    }
+
+    #[test]
+    fn heartbeat_keeps_start_time_and_recreates_a_removed_lease() {
+        let root = tempfile::tempdir().unwrap();
+        let ours = registry(root.path(), APP_ID, 100);
+        ours.acquire().unwrap();
+        let first = ours.read(&ours.own_path()).unwrap();
+
+        ours.acquire().unwrap();
+        let refreshed = ours.read(&ours.own_path()).unwrap();
+        assert_eq!(refreshed.started_at, first.started_at);
+        assert!(refreshed.heartbeat_at >= first.heartbeat_at);
+
+        // Bail supprimé par un autre consommateur (jugé expiré pendant une mise en veille)
+        fs::remove_file(ours.own_path()).unwrap();
+        ours.acquire().unwrap();
+        assert_eq!(ours.active().len(), 1);
+    }
+
+    #[test]
+    fn heartbeat_refreshes_a_stale_lease_under_the_lock() {
+        let root = tempfile::tempdir().unwrap();
+        let ours = registry(root.path(), APP_ID, 100);
+        let collab = registry(root.path(), "collabtools", 200);
+        let stale_at = Local::now() - chrono::Duration::seconds(LEASE_TTL.as_secs() as i64 + 60);
+        for registry in [&ours, &collab] {
+            let stale = Lease { app: registry.app.clone(), pid: registry.pid, started_at: stale_at, heartbeat_at: stale_at };
+            fs::create_dir_all(&registry.dir).unwrap();
+            fs::write(registry.own_path(), serde_json::to_string(&stale).unwrap()).unwrap();
+        }
+
+        // Registre verrouillé par un autre consommateur : le battement attend sa libération
+        let lock = collab.lock().unwrap();
+        let heartbeat = std::thread::spawn({
+            let ours = ours.clone();
+            move || ours.heartbeat()
+        });
+        std::thread::sleep(Duration::from_millis(300));
+        assert_eq!(ours.read(&ours.own_path()).unwrap().heartbeat_at, stale_at);
+        drop(lock);
+        heartbeat.join().unwrap().unwrap();
+
+        let refreshed = ours.read(&ours.own_path()).unwrap();
+        assert_eq!(refreshed.started_at, stale_at);
+        assert!(!is_expired(&refreshed, Local::now()));
+        // Le bail expiré de l'autre consommateur a été supprimé au passage
+        assert!(!collab.own_path().exists());
+        assert_eq!(ours.active().len(), 1);
+    }
+
+    #[test]
+    fn only_expired_readable_leases_are_pruned() {
+        let root = tempfile::tempdir().unwrap();
+        let ours = registry(root.path(), APP_ID, 100);
+        ours.acquire().unwrap();
+
+        let stale_at = Local::now() - chrono::Duration::seconds(LEASE_TTL.as_secs() as i64 + 60);
+        let stale = Lease { app: "collabtools".into(), pid: 200, started_at: stale_at, heartbeat_at: stale_at };
+        let stale_path = ours.dir.join("collabtools-200.json");
+        fs::write(&stale_path, serde_json::to_string(&stale).unwrap()).unwrap();
+        // Bail en cours d'écriture ou d'un autre format : il compte, et n'est jamais supprimé
+        let partial_path = ours.dir.join("collabtools-300.json");
+        fs::write(&partial_path, "{").unwrap();
+
+        let others = ours.others();
+        assert_eq!(others.len(), 1);
+        assert_eq!((others[0].app.as_str(), others[0].pid), ("collabtools", 300));
+        assert!(stale_path.exists());
+
+        let lock = ours.lock().unwrap();
+        ours.prune_expired(&lock);
+        assert!(!stale_path.exists());
+        assert!(partial_path.exists());
+        assert_eq!(ours.active().len(), 2);
+    }
+
+    #[test]
+    fn started_marker_is_shared_by_consumers() {
+        let root = tempfile::tempdir().unwrap();
+        let ours = registry(root.path(), APP_ID, 100);
+        let collab = registry(root.path(), "collabtools", 200);
+        ours.acquire().unwrap();
+
+        assert!(!collab.started_by_consumer());
+        ours.mark_started().unwrap();
+        assert!(collab.started_by_consumer());
+        collab.clear_started();
+        assert!(!ours.started_by_consumer());
+    }
+}
@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use serde::Serialize;
+
+/// Premier OID attribué aux objets utilisateur (`FirstNormalObjectId`) : sous `base/`, les
+/// dossiers d'OID inférieur sont les bases modèles créées par initdb.
+const FIRST_USER_OID: u64 = 16384;
+
+/// Contenu d'un dossier `data` avant initialisation.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum DataDirState {
+    Missing,
+    Empty,
+    /// Cluster complet (`PG_VERSION` présent).
+    Initialized,
+    /// Reste d'un initdb interrompu : uniquement les fichiers créés par initdb.
+    PartialInit { entries: usize },
+    /// Fichiers qui ne peuvent pas venir d'initdb seul (bases utilisateur, tablespaces, inconnus).
+    UserData { evidence: Vec<String> },
+}
+
+/// Dossiers et fichiers qu'initdb crée dans un cluster vide.
+const INITDB_ENTRIES: &[&str] = &[
+    "base", "global", "pg_commit_ts", "pg_dynshmem", "pg_logical", "pg_multixact", "pg_notify",
+    "pg_replslot", "pg_serial", "pg_snapshots", "pg_stat", "pg_stat_tmp", "pg_subtrans",
+    "pg_tblspc", "pg_twophase", "pg_wal", "pg_xact", "pg_xlog", "pg_clog", "pg_hba.conf",
+    "pg_ident.conf", "postgresql.conf", "postgresql.auto.conf", "postmaster.opts", "postmaster.pid",
+];
+
+pub fn inspect(data_dir: &Path) -> DataDirState {
+    if !data_dir.exists() {
+        return DataDirState::Missing;
+    }
+    if data_dir.join("PG_VERSION").exists() {
+        return DataDirState::Initialized;
+    }
+    let entries: Vec<PathBuf> = match fs::read_dir(data_dir) {
+        Ok(entries) => entries.flatten().map(|e| e.path()).collect(),
+        // Illisible : on ne peut rien affirmer, donc on ne touche à rien
+        Err(e) => return DataDirState::UserData { evidence: vec![format!("dossier illisible: {}", e)] },
+    };
+    if entries.is_empty() {
+        return DataDirState::Empty;
+    }
+
+    let mut evidence = Vec::new();
+    for path in &entries {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if !INITDB_ENTRIES.contains(&name.as_str()) {
+            evidence.push(format!("élément inattendu '{}'", name));
+        }
+    }
+    for oid in subdirs(&data_dir.join("base")).into_iter().filter_map(|n| n.parse::<u64>().ok()) {
+        if oid >= FIRST_USER_OID {
+            evidence.push(format!("base utilisateur base/{}", oid));
+        }
+    }
+    let tablespaces = subdirs(&data_dir.join("pg_tblspc"));
+    if !tablespaces.is_empty() {
+        evidence.push(format!("{} tablespace(s) dans pg_tblspc", tablespaces.len()));
+    }
+
+    if evidence.is_empty() {
+        DataDirState::PartialInit { entries: entries.len() }
+    } else {
+        DataDirState::UserData { evidence }
+    }
+}
+
+fn subdirs(dir: &Path) -> Vec<String> {
+    fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|e| e.file_name().to_string_lossy().to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// Déplace `data_dir` vers `<cluster>/quarantine/data-<horodatage>` (jamais de suppression).
+pub fn quarantine(data_dir: &Path) -> Result<PathBuf, String> {
+    let root = data_dir.parent().ok_or("Dossier data invalide")?;
+    let quarantine_dir = root.join("quarantine");
+    fs::create_dir_all(&quarantine_dir).map_err(|e| format!("Impossible de créer {:?}: {}", quarantine_dir, e))?;
+    let target = quarantine_dir.join(format!("data-{}", Local::now().format("%Y%m%d-%H%M%S")));
+    fs::rename(data_dir, &target).map_err(|e| format!("Mise en quarantaine de {:?} impossible: {}", data_dir, e))?;
+    println!("📦 {:?} mis en quarantaine dans {:?}", data_dir, target);
+    Ok(target)
+}
+
+/// Dossiers mis en quarantaine pour ce cluster, du plus récent au plus ancien.
+pub fn list_quarantined(cluster_root: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(cluster_root.join("quarantine"))
+        .map(|entries| entries.flatten().map(|e| e.path()).filter(|p| p.is_dir()).collect())
+        .unwrap_or_default();
+    dirs.sort_by(|a, b| b.cmp(a));
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Dossier `data` temporaire contenant `entries` (un `/` final désigne un dossier),
+    /// supprimé avec le `TempDir`.
+    fn data_dir(entries: &[&str]) -> (tempfile::TempDir, PathBuf) {
+        let root = tempfile::tempdir().unwrap();
+        let data = root.path().join("data");
+        fs::create_dir_all(&data).unwrap();
+        for entry in entries {
+            let path = data.join(entry.trim_end_matches('/'));
+            if entry.ends_with('/') {
+                fs::create_dir_all(path).unwrap();
+            } else {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "").unwrap();
+            }
+        }
+        (root, data)
+    }
+
+    #[test]
+    fn missing_empty_and_initialized() {
+        let (_root, data) = data_dir(&[]);
+        assert_eq!(inspect(&data.join("absent")), DataDirState::Missing);
+        assert_eq!(inspect(&data), DataDirState::Empty);
+        fs::write(data.join("PG_VERSION"), "16\n").unwrap();
+        fs::create_dir(data.join("base")).unwrap();
+        assert_eq!(inspect(&data), DataDirState::Initialized);
+    }
+
+    #[test]
+    fn interrupted_initdb_is_a_partial_init() {
+        let (_root, data) = data_dir(&["base/1/", "base/4/", "base/5/", "global/", "pg_wal/", "pg_tblspc/", "postgresql.conf"]);
+        assert_eq!(inspect(&data), DataDirState::PartialInit { entries: 5 });
+    }
+
+    #[test]
+    fn user_databases_unknown_files_and_tablespaces_are_user_data() {
+        let (_root, data) = data_dir(&["base/1/", "base/16384/", "global/", "notes.txt", "pg_tblspc/16500/"]);
+        let DataDirState::UserData { evidence } = inspect(&data) else {
+            panic!("UserData attendu");
+        };
+        assert_eq!(evidence.len(), 3, "{:?}", evidence);
+        assert!(evidence.contains(&"élément inattendu 'notes.txt'".to_string()));
+        assert!(evidence.contains(&"base utilisateur base/16384".to_string()));
+        assert!(evidence.contains(&"1 tablespace(s) dans pg_tblspc".to_string()));
+    }
+}
@@ -0,0 +1,657 @@
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::postgres::{PgConnectOptions, PgSslMode};
+
+use crate::fsutil::{write_atomic, FileLock};
+use crate::secrets::{self, SecretBackend, SecretStore};
+
+pub const APP_USER: &str = "app_user";
+
+/// Version du format de `db_config.json` écrite par ce launcher.
+///
+/// - 1 : format d'origine (`mode`, `host`, `port`, mots de passe en clair), sans champ `version`
+/// - 2 : comptes nommés (`admin_user`, `app_user`), TLS, mots de passe hors du fichier
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Champs gérés par [`DbConfig`] ; les autres clés du fichier appartiennent à CollabTools.
+const KNOWN_FIELDS: [&str; 12] = [
+    "version", "mode", "host", "port", "admin_user", "postgres_password",
+    "app_user", "app_password", "credentials", "ssl_mode", "ca_file", "provision",
+];
+/// Attente maximale du verrou tenu par un autre processus.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const MODES: [&str; 2] = ["Embedded", "Network"];
+const SSL_MODES: [&str; 4] = ["disable", "require", "verify-ca", "verify-full"];
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub enum DatabaseMode {
+    #[default]
+    Embedded,
+    Network,
+}
+
+/// Chiffrement de la connexion à un serveur distant (mêmes noms que `sslmode` de libpq).
+/// Ignoré en mode embarqué : le cluster local n'écoute que sur 127.0.0.1, sans TLS.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SslMode {
+    Disable,
+    #[default]
+    Require,
+    VerifyCa,
+    VerifyFull,
+}
+
+impl SslMode {
+    pub fn pg_ssl_mode(self) -> PgSslMode {
+        match self {
+            SslMode::Disable => PgSslMode::Disable,
+            SslMode::Require => PgSslMode::Require,
+            SslMode::VerifyCa => PgSslMode::VerifyCa,
+            SslMode::VerifyFull => PgSslMode::VerifyFull,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SslMode::Disable => "disable",
+            SslMode::Require => "require",
+            SslMode::VerifyCa => "verify-ca",
+            SslMode::VerifyFull => "verify-full",
+        }
+    }
+}
+
+fn default_version() -> u32 {
+    1
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+pub fn default_admin_user() -> String {
+    "postgres".to_string()
+}
+
+pub fn default_app_user() -> String {
+    APP_USER.to_string()
+}
+
+/// Contenu de `db_config.json`, partagé avec CollabTools.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DbConfig {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub mode: DatabaseMode,
+    #[serde(default = "default_host")]
+    pub host: String,
+    pub port: u16,
+    /// Compte d'administration (superutilisateur en mode embarqué). En mode réseau il peut
+    /// être laissé vide si la base et le rôle applicatif sont créés par l'administrateur du serveur.
+    #[serde(default = "default_admin_user")]
+    pub admin_user: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub postgres_password: String,
+    #[serde(default = "default_app_user")]
+    pub app_user: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub app_password: String,
+    /// Emplacement des mots de passe lorsqu'ils ne sont pas en clair dans le fichier.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<CredentialsRef>,
+    #[serde(default)]
+    pub ssl_mode: SslMode,
+    /// Certificat de l'autorité (PEM) pour `verify-ca` / `verify-full`.
+    #[serde(default)]
+    pub ca_file: Option<PathBuf>,
+    /// Mode réseau : créer la base et le rôle applicatif s'ils n'existent pas (compte d'administration requis).
+    #[serde(default)]
+    pub provision: bool,
+    /// Champs inconnus (écrits par une version plus récente de CollabTools), réécrits tels quels.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Référence vers les mots de passe rangés dans un [`secrets::SecretStore`].
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CredentialsRef {
+    pub store: SecretBackend,
+    pub id: String,
+}
+
+impl CredentialsRef {
+    fn secret_name(&self, field: &str) -> String {
+        format!("db.{}.{}", self.id, field)
+    }
+}
+
+impl DbConfig {
+    /// Nouvelle configuration de cluster embarqué.
+    pub fn embedded(port: u16, postgres_password: String, app_password: String) -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            mode: DatabaseMode::Embedded,
+            host: default_host(),
+            port,
+            admin_user: default_admin_user(),
+            postgres_password,
+            app_user: default_app_user(),
+            app_password,
+            credentials: None,
+            ssl_mode: SslMode::default(),
+            ca_file: None,
+            provision: false,
+            extra: Map::new(),
+        }
+    }
+
+    /// Lit `db_config.json`, migré en mémoire vers [`CONFIG_VERSION`] puis validé. Les mots de passe
+    /// sont récupérés dans le stockage de secrets si le fichier n'en contient qu'une référence ;
+    /// les fichiers en clair (CollabTools) restent lisibles.
+    ///
+    /// Renvoie aussi la version d'origine si une migration a eu lieu (le fichier n'est pas réécrit ici).
+    pub fn load(path: &Path) -> Result<(Self, Option<u32>), String> {
+        Self::load_with(path, secrets::open)
+    }
+
+    /// Comme [`load`](Self::load), les stockages de secrets étant ouverts par `open_store`.
+    fn load_with(path: &Path, open_store: impl Fn(SecretBackend, &Path) -> Result<Box<dyn SecretStore>, String>) -> Result<(Self, Option<u32>), String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Lecture de {:?} impossible: {}", path, e))?;
+        let value: Value = serde_json::from_str(&content).map_err(|e| format!(
+            "{:?} n'est pas un JSON valide (ligne {}, colonne {}) : {}. Corrigez le fichier puis relancez WikiTools.",
+            path, e.line(), e.column(), e
+        ))?;
+        let Value::Object(mut fields) = value else {
+            return Err(format!("{:?} invalide : un objet JSON {{ ... }} est attendu.", path));
+        };
+
+        let migrated_from = migrate(&mut fields);
+        let issues = check_fields(&fields);
+        if !issues.is_empty() {
+            return Err(report(path, &issues));
+        }
+        let mut config: DbConfig = serde_json::from_value(Value::Object(fields))
+            .map_err(|e| report(path, &[e.to_string()]))?;
+
+        if let Some(credentials) = config.credentials.clone() {
+            let store = open_store(credentials.store, path.parent().unwrap_or(Path::new(".")))?;
+            let read = |field: &str| -> Result<String, String> {
+                store.get(&credentials.secret_name(field))?
+                    .ok_or_else(|| format!("Secret '{}' introuvable ({:?})", field, credentials.store))
+            };
+            config.postgres_password = read("postgres_password")?;
+            config.app_password = read("app_password")?;
+        }
+        let issues = config.check();
+        if !issues.is_empty() {
+            return Err(report(path, &issues));
+        }
+        Ok((config, migrated_from))
+    }
+
+    /// Ecrit `db_config.json`. Avec `secrets`, les mots de passe partent dans ce stockage
+    /// (trousseau ou fichier chiffré, voir [`secret_backend`](Self::secret_backend)) et le
+    /// fichier n'en garde qu'une référence ; des mots de passe déjà rangés restent dans le leur.
+    ///
+    /// Le fichier pouvant être partagé avec CollabTools, l'écriture se fait sous verrou
+    /// (`db_config.json.lock`, à prendre aussi côté CollabTools) : le fichier est relu, les champs inconnus présents sur le disque sont
+    /// conservés, puis le résultat est écrit dans un fichier temporaire renommé par-dessus l'original.
+    pub fn save(&mut self, path: &Path, secrets: Option<SecretBackend>) -> Result<(), String> {
+        self.save_with(path, secrets, secrets::open)
+    }
+
+    /// Comme [`save`](Self::save), les stockages de secrets étant ouverts par `open_store`.
+    fn save_with(
+        &mut self,
+        path: &Path,
+        secrets: Option<SecretBackend>,
+        open_store: impl Fn(SecretBackend, &Path) -> Result<Box<dyn SecretStore>, String>,
+    ) -> Result<(), String> {
+        let issues = self.check();
+        if !issues.is_empty() {
+            return Err(report(path, &issues));
+        }
+        // Un fichier écrit par une version plus récente garde son numéro de version
+        self.version = self.version.max(CONFIG_VERSION);
+
+        let mut stored = self.clone();
+        if let Some(backend) = secrets {
+            let dir = path.parent().ok_or("Chemin de configuration invalide")?;
+            let credentials = stored.credentials.clone().unwrap_or_else(|| CredentialsRef {
+                store: backend,
+                id: rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(12)
+                    .map(|c| char::from(c).to_ascii_lowercase())
+                    .collect(),
+            });
+            let store = open_store(credentials.store, dir)?;
+            store.set(&credentials.secret_name("postgres_password"), &stored.postgres_password)?;
+            store.set(&credentials.secret_name("app_password"), &stored.app_password)?;
+            stored.postgres_password.clear();
+            stored.app_password.clear();
+            stored.credentials = Some(credentials.clone());
+            self.credentials = Some(credentials);
+        }
+        let Value::Object(ours) = serde_json::to_value(&stored).map_err(|e| e.to_string())? else {
+            return Err("Sérialisation de la configuration impossible".into());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Impossible de créer {:?}: {}", parent, e))?;
+        }
+        // On verrouille un fichier voisin et non le fichier lui-même, remplacé à chaque écriture
+        let _lock = FileLock::acquire(&path.with_extension("json.lock"), LOCK_TIMEOUT)?;
+        let mut merged = read_raw(path);
+        merged.retain(|key, _| !KNOWN_FIELDS.contains(&key.as_str()));
+        for (key, value) in ours {
+            // Champ inconnu : la version sur le disque, potentiellement plus récente, l'emporte
+            if KNOWN_FIELDS.contains(&key.as_str()) || !merged.contains_key(&key) {
+                merged.insert(key, value);
+            }
+        }
+        let content = serde_json::to_string_pretty(&Value::Object(merged)).map_err(|e| e.to_string())?;
+        write_atomic(path, content.as_bytes())
+    }
+
+    /// Ecrit dans le fichier partagé de CollabTools sans en changer la forme : seules les clés
+    /// déjà présentes sur le disque sont mises à jour, le fichier garde son numéro de version
+    /// et `mode` sa casse d'origine. Les mots de passe restent en clair (lus par CollabTools).
+    pub fn save_shared(&self, path: &Path) -> Result<(), String> {
+        let issues = self.check();
+        if !issues.is_empty() {
+            return Err(report(path, &issues));
+        }
+        let Value::Object(ours) = serde_json::to_value(self).map_err(|e| e.to_string())? else {
+            return Err("Sérialisation de la configuration impossible".into());
+        };
+
+        let _lock = FileLock::acquire(&path.with_extension("json.lock"), LOCK_TIMEOUT)?;
+        let mut disk = read_raw(path);
+        if disk.is_empty() {
+            return Err(format!("{:?} introuvable ou illisible : configuration partagée laissée telle quelle", path));
+        }
+        for (key, value) in ours {
+            if key == "version" || !KNOWN_FIELDS.contains(&key.as_str()) {
+                continue;
+            }
+            let Some(current) = disk.get_mut(&key) else {
+                continue;
+            };
+            let same_mode = key == "mode"
+                && current.as_str().zip(value.as_str()).is_some_and(|(disk, ours)| disk.eq_ignore_ascii_case(ours));
+            if !same_mode {
+                *current = value;
+            }
+        }
+        let content = serde_json::to_string_pretty(&Value::Object(disk)).map_err(|e| e.to_string())?;
+        write_atomic(path, content.as_bytes())
+    }
+
+    /// Contrôles portant sur les valeurs une fois le fichier chargé.
+    fn check(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+        if self.port == 0 {
+            issues.push("port : 0 n'est pas un port valide (1-65535)".to_string());
+        }
+        if !is_valid_host(&self.host) {
+            issues.push(format!("host : {:?} n'est pas un nom d'hôte ou une adresse IP valide", self.host));
+        }
+        if self.mode == DatabaseMode::Embedded && !is_loopback(&self.host) {
+            issues.push(format!(
+                "host : le mode \"Embedded\" n'écoute que sur 127.0.0.1 ({:?} trouvé) ; utilisez \"mode\": \"Network\" pour un serveur distant",
+                self.host
+            ));
+        }
+        if self.app_user.trim().is_empty() {
+            issues.push("app_user : nom de l'utilisateur applicatif vide".to_string());
+        }
+        if self.app_password.is_empty() {
+            issues.push("app_password : mot de passe de l'utilisateur applicatif manquant".to_string());
+        }
+        if self.mode == DatabaseMode::Embedded && self.postgres_password.is_empty() {
+            issues.push("postgres_password : mot de passe du superutilisateur manquant (requis en mode \"Embedded\")".to_string());
+        }
+        if let Some(ca_file) = self.ca_file.as_ref().filter(|_| self.mode == DatabaseMode::Network) {
+            if !ca_file.is_file() {
+                issues.push(format!("ca_file : certificat {:?} introuvable", ca_file));
+            }
+        }
+        issues
+    }
+
+    /// Copie affichable (diagnostics) : mots de passe masqués, y compris dans les champs
+    /// inconnus (à toute profondeur) dont le nom évoque un secret.
+    pub fn redacted(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or(Value::Null);
+        redact(&mut value);
+        value
+    }
+
+    /// Stockage des mots de passe pour [`save`](Self::save) : celui déjà référencé, sinon le
+    /// préféré du poste (le trousseau n'est sondé que dans ce cas).
+    pub fn secret_backend(&self) -> SecretBackend {
+        self.credentials.as_ref().map_or_else(secrets::preferred_backend, |credentials| credentials.store)
+    }
+
+    pub fn has_admin_credentials(&self) -> bool {
+        !self.admin_user.is_empty() && !self.postgres_password.is_empty()
+    }
+
+    fn connect_options(&self, user: &str, password: &str, database: &str) -> PgConnectOptions {
+        let options = PgConnectOptions::new()
+            .host(&self.host)
+            .port(self.port)
+            .username(user)
+            .password(password)
+            .database(database);
+        if self.mode == DatabaseMode::Embedded {
+            return options.ssl_mode(PgSslMode::Disable);
+        }
+        let options = options.ssl_mode(self.ssl_mode.pg_ssl_mode());
+        match &self.ca_file {
+            Some(ca_file) if self.ssl_mode != SslMode::Disable => options.ssl_root_cert(ca_file),
+            _ => options,
+        }
+    }
+
+    pub fn admin_options(&self, database: &str) -> PgConnectOptions {
+        self.connect_options(&self.admin_user, &self.postgres_password, database)
+    }
+
+    pub fn app_options(&self, database: &str) -> PgConnectOptions {
+        self.connect_options(&self.app_user, &self.app_password, database)
+    }
+}
+
+/// Masque les valeurs des clés évoquant un secret, dans les objets comme dans les tableaux.
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                let key = key.to_lowercase();
+                let secret = ["password", "secret", "token", "key"].iter().any(|word| key.contains(word));
+                if secret && !matches!(field, Value::Null) {
+                    *field = Value::String("********".into());
+                } else {
+                    redact(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Contenu brut actuel du fichier (vide s'il est absent ou illisible : il va être remplacé).
+fn read_raw(path: &Path) -> Map<String, Value> {
+    match fs::read_to_string(path).ok().and_then(|content| serde_json::from_str(&content).ok()) {
+        Some(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+/// Amène les champs bruts à [`CONFIG_VERSION`]. Renvoie la version d'origine si elle a changé.
+/// Un fichier plus récent que ce launcher est lu tel quel.
+fn migrate(fields: &mut Map<String, Value>) -> Option<u32> {
+    let version = fields.get("version").and_then(Value::as_u64).unwrap_or(1) as u32;
+    if version > CONFIG_VERSION {
+        println!("ℹ️ db_config.json en version {} (plus récente que {}), lu sans migration.", version, CONFIG_VERSION);
+        return None;
+    }
+    if version == CONFIG_VERSION {
+        return None;
+    }
+
+    if version < 2 {
+        // 1 -> 2 : casse du mode tolérée par les premières versions de CollabTools, comptes explicites
+        if let Some(Value::String(mode)) = fields.get_mut("mode") {
+            if let Some(canonical) = MODES.iter().find(|m| m.eq_ignore_ascii_case(mode)) {
+                *mode = canonical.to_string();
+            }
+        }
+        fields.entry("admin_user").or_insert_with(|| default_admin_user().into());
+        fields.entry("app_user").or_insert_with(|| default_app_user().into());
+    }
+
+    fields.insert("version".into(), CONFIG_VERSION.into());
+    println!("🔄 db_config.json migré de la version {} vers {}.", version, CONFIG_VERSION);
+    Some(version)
+}
+
+/// Contrôles de type et de syntaxe sur les champs bruts, pour des messages plus parlants que serde.
+fn check_fields(fields: &Map<String, Value>) -> Vec<String> {
+    let mut issues = Vec::new();
+    match fields.get("port") {
+        None => issues.push("port : champ manquant (port du serveur PostgreSQL, 1-65535)".to_string()),
+        Some(port) if !port.as_u64().is_some_and(|p| (1..=65535).contains(&p)) => {
+            issues.push(format!("port : {} invalide, un entier entre 1 et 65535 est attendu", port));
+        }
+        _ => {}
+    }
+    for (key, allowed) in [("mode", &MODES[..]), ("ssl_mode", &SSL_MODES[..])] {
+        if let Some(value) = fields.get(key) {
+            if !value.as_str().is_some_and(|v| allowed.contains(&v)) {
+                issues.push(format!("{} : {} inconnu, valeurs possibles : {}", key, value, allowed.join(", ")));
+            }
+        }
+    }
+    for key in ["host", "admin_user", "postgres_password", "app_user", "app_password"] {
+        if let Some(value) = fields.get(key).filter(|v| !v.is_string()) {
+            issues.push(format!("{} : {} invalide, une chaîne de caractères est attendue", key, value));
+        }
+    }
+    if let Some(host) = fields.get("host").and_then(Value::as_str).filter(|h| !is_valid_host(h)) {
+        issues.push(format!("host : {:?} n'est pas un nom d'hôte ou une adresse IP valide", host));
+    }
+    if let Some(value) = fields.get("provision").filter(|v| !v.is_boolean()) {
+        issues.push(format!("provision : {} invalide, true ou false est attendu", value));
+    }
+    issues
+}
+
+fn report(path: &Path, issues: &[impl AsRef<str>]) -> String {
+    let lines: Vec<String> = issues.iter().map(|issue| format!("  - {}", issue.as_ref())).collect();
+    format!(
+        "Configuration PostgreSQL invalide ({:?}) :\n{}\nCorrigez ces valeurs dans le fichier puis relancez WikiTools.",
+        path,
+        lines.join("\n")
+    )
+}
+
+/// Adresse IP, ou nom d'hôte DNS (étiquettes alphanumériques et tirets, 253 caractères au plus).
+fn is_valid_host(host: &str) -> bool {
+    if host.parse::<IpAddr>().is_ok() {
+        return true;
+    }
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+fn is_loopback(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost") || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(fields) => fields,
+            _ => unreachable!(),
+        }
+    }
+
+    /// `db_config.json` dans un dossier temporaire, supprimé avec le `TempDir`.
+    fn temp_config() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db_config.json");
+        (dir, path)
+    }
+
+    #[test]
+    fn migrates_a_version_1_file() {
+        let mut v1 = fields(json!({ "mode": "embedded", "port": 5433, "postgres_password": "a", "app_password": "b" }));
+        assert_eq!(migrate(&mut v1), Some(1));
+        assert_eq!(v1["version"], CONFIG_VERSION);
+        assert_eq!(v1["mode"], "Embedded");
+        assert_eq!(v1["admin_user"], "postgres");
+        assert_eq!(v1["app_user"], APP_USER);
+    }
+
+    #[test]
+    fn leaves_current_and_newer_files_untouched() {
+        let current = fields(json!({ "version": CONFIG_VERSION, "port": 5433, "admin_user": "dba" }));
+        let mut migrated = current.clone();
+        assert_eq!(migrate(&mut migrated), None);
+        assert_eq!(migrated, current);
+
+        let newer = fields(json!({ "version": CONFIG_VERSION + 1, "port": 5433, "mode": "embedded" }));
+        let mut migrated = newer.clone();
+        assert_eq!(migrate(&mut migrated), None);
+        assert_eq!(migrated, newer);
+    }
+
+    #[test]
+    fn loads_a_version_1_file_with_default_accounts() {
+        let (_dir, path) = temp_config();
+        fs::write(&path, r#"{ "mode": "Embedded", "port": 5433, "postgres_password": "a", "app_password": "b" }"#).unwrap();
+        let (config, migrated_from) = DbConfig::load(&path).unwrap();
+        assert_eq!(migrated_from, Some(1));
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.host, "127.0.0.1");
+        assert_eq!(config.admin_user, "postgres");
+        assert_eq!(config.app_user, APP_USER);
+    }
+
+    #[test]
+    fn plaintext_passwords_move_to_the_secret_store() {
+        let (dir, path) = temp_config();
+        let key = dir.path().join("secrets.key");
+        let open_store = |backend: SecretBackend, secrets_dir: &Path| -> Result<Box<dyn SecretStore>, String> {
+            assert_eq!(backend, SecretBackend::EncryptedFile);
+            Ok(Box::new(secrets::EncryptedFileStore::at(secrets_dir, key.clone(), None)))
+        };
+        fs::write(&path, r#"{ "version": 2, "port": 5433, "postgres_password": "a", "app_password": "b" }"#).unwrap();
+        let (mut config, _) = DbConfig::load_with(&path, open_store).unwrap();
+        assert!(config.credentials.is_none());
+
+        config.save_with(&path, Some(SecretBackend::EncryptedFile), open_store).unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.get("postgres_password"), None);
+        assert_eq!(saved.get("app_password"), None);
+        assert_eq!(saved["credentials"]["store"], "encrypted_file");
+        assert!(dir.path().join("secrets.enc.json").exists());
+        assert!(key.exists());
+        assert_eq!(config.secret_backend(), SecretBackend::EncryptedFile);
+
+        let (loaded, _) = DbConfig::load_with(&path, open_store).unwrap();
+        assert_eq!((loaded.postgres_password.as_str(), loaded.app_password.as_str()), ("a", "b"));
+    }
+
+    #[test]
+    fn save_keeps_unknown_keys_from_disk() {
+        let (_dir, path) = temp_config();
+        fs::write(&path, r#"{ "version": 2, "port": 5433, "postgres_password": "a", "app_password": "b", "collab": { "theme": "dark" } }"#).unwrap();
+        let (mut config, _) = DbConfig::load(&path).unwrap();
+        // Entre-temps, CollabTools réécrit le fichier avec ses propres champs
+        fs::write(&path, r#"{ "version": 2, "port": 5433, "postgres_password": "a", "app_password": "b", "collab": { "theme": "light" }, "added_later": true }"#).unwrap();
+
+        config.port = 5434;
+        config.save(&path, None).unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["port"], 5434);
+        assert_eq!(saved["app_password"], "b");
+        assert_eq!(saved["collab"], json!({ "theme": "light" }));
+        assert_eq!(saved["added_later"], true);
+    }
+
+    #[test]
+    fn save_shared_keeps_the_collabtools_format() {
+        let (_dir, path) = temp_config();
+        fs::write(&path, r#"{ "mode": "embedded", "port": 5433, "postgres_password": "a", "app_password": "b", "collab": { "theme": "dark" } }"#).unwrap();
+        let (mut config, migrated_from) = DbConfig::load(&path).unwrap();
+        assert_eq!(migrated_from, Some(1));
+
+        config.port = 5434;
+        config.app_password = "c".into();
+        config.save_shared(&path).unwrap();
+        let saved = read_raw(&path);
+        assert_eq!(Value::Object(saved), json!({
+            "mode": "embedded",
+            "port": 5434,
+            "postgres_password": "a",
+            "app_password": "c",
+            "collab": { "theme": "dark" },
+        }));
+    }
+
+    #[test]
+    fn redacted_masks_nested_secrets() {
+        let mut config = DbConfig::embedded(5433, "a".into(), "b".into());
+        config.extra = fields(json!({
+            "collab": { "api_token": "t", "theme": "dark", "sync": [{ "secret": "s", "url": "https://x" }] },
+            "signing_key": null,
+        }));
+        let redacted = config.redacted();
+        assert_eq!(redacted["postgres_password"], "********");
+        assert_eq!(redacted["app_password"], "********");
+        assert_eq!(redacted["collab"], json!({
+            "api_token": "********",
+            "theme": "dark",
+            "sync": [{ "secret": "********", "url": "https://x" }],
+        }));
+        assert_eq!(redacted["signing_key"], Value::Null);
+        assert_eq!(redacted["port"], 5433);
+    }
+
+    #[test]
+    fn check_fields_explains_invalid_values() {
+        let issues = check_fields(&fields(json!({
+            "port": 70000,
+            "mode": "Cloud",
+            "ssl_mode": "tls",
+            "host": "serveur pg",
+            "app_user": 3,
+            "provision": "oui",
+        })));
+        for expected in [
+            "port : 70000 invalide",
+            "mode : \"Cloud\" inconnu, valeurs possibles : Embedded, Network",
+            "ssl_mode : \"tls\" inconnu",
+            "host : \"serveur pg\" n'est pas un nom d'hôte ou une adresse IP valide",
+            "app_user : 3 invalide",
+            "provision : \"oui\" invalide",
+        ] {
+            assert!(issues.iter().any(|issue| issue.starts_with(expected)), "{:?} absent de {:#?}", expected, issues);
+        }
+        assert_eq!(issues.len(), 6);
+    }
+
+    #[test]
+    fn check_fields_reports_missing_port_and_accepts_valid_files() {
+        let issues = check_fields(&fields(json!({ "host": "127.0.0.1" })));
+        assert_eq!(issues, ["port : champ manquant (port du serveur PostgreSQL, 1-65535)"]);
+        let valid = json!({ "port": 5432, "mode": "Network", "ssl_mode": "verify-full", "host": "pg.example.org", "provision": true });
+        assert!(check_fields(&fields(valid)).is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+use tauri::Url;
+
+/// Schéma des liens profonds, déclaré dans `tauri.conf.json` (`plugins.deep-link`).
+pub const SCHEME: &str = "wikitools";
+
+/// Chemin de la page wiki visée par un lien `wikitools://page/<chemin>`, requête et
+/// ancre comprises (ex. `fr/procedures/vpn#installation`). `None` pour tout autre lien.
+pub fn page_path(url: &Url) -> Option<String> {
+    if url.scheme() != SCHEME || url.host_str() != Some("page") {
+        return None;
+    }
+    let path = url.path().trim_start_matches('/');
+    // Le lien ne doit désigner qu'un chemin relatif au wiki, y compris une fois décodé
+    // (`..` est déjà résolu par l'analyse de l'URL, pas `..%2F`)
+    let escapes = |segment: &str| {
+        let decoded = urlencoding::decode(segment).map_or_else(|_| segment.to_string(), |decoded| decoded.into_owned());
+        decoded == ".." || decoded == "." || decoded.contains(['/', '\\'])
+    };
+    if path.split('/').any(escapes) {
+        return None;
+    }
+    let mut target = path.to_string();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    if let Some(fragment) = url.fragment() {
+        target.push('#');
+        target.push_str(fragment);
+    }
+    Some(target)
+}
+
+/// Vrai si l'un des arguments de la ligne de commande est un lien `wikitools://`.
+pub fn in_args(args: &[String]) -> bool {
+    args.iter().any(|arg| arg.starts_with(&format!("{}://", SCHEME)))
+}
+
+/// URL complète de la page `path` sur le wiki servi à `wiki_url`.
+pub fn page_url(wiki_url: &str, path: &str) -> String {
+    format!("{}/{}", wiki_url.trim_end_matches('/'), path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(link: &str) -> Option<String> {
+        page_path(&Url::parse(link).unwrap())
+    }
+
+    #[test]
+    fn extracts_the_page_path_with_query_and_fragment() {
+        assert_eq!(page("wikitools://page/fr/procedures/vpn").as_deref(), Some("fr/procedures/vpn"));
+        assert_eq!(page("wikitools://page/fr/vpn?tab=2#installation").as_deref(), Some("fr/vpn?tab=2#installation"));
+        assert_eq!(page("wikitools://page/").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn dot_segments_never_leave_the_wiki() {
+        // Résolus à l'analyse, sans pouvoir remonter au-dessus de la racine du wiki
+        assert_eq!(page("wikitools://page/fr/../../admin").as_deref(), Some("admin"));
+        assert_eq!(page("wikitools://page/%2e%2e/%2E%2E/admin").as_deref(), Some("admin"));
+        // Séparateurs encodés : refusés plutôt que transmis au serveur
+        assert_eq!(page("wikitools://page/..%2Fadmin"), None);
+        assert_eq!(page("wikitools://page/fr/%2E%2E%2F%2E%2E%2Fadmin"), None);
+        assert_eq!(page("wikitools://page/fr/..%5Cadmin"), None);
+    }
+
+    #[test]
+    fn ignores_other_schemes_and_hosts() {
+        assert_eq!(page("https://page/fr/vpn"), None);
+        assert_eq!(page("file:///etc/passwd"), None);
+        assert_eq!(page("wikitools://settings/fr/vpn"), None);
+        assert_eq!(page("wikitools:page/fr/vpn"), None);
+    }
+
+    #[test]
+    fn detects_links_in_arguments() {
+        assert!(in_args(&["WikiTools.exe".into(), "wikitools://page/fr/vpn".into()]));
+        assert!(!in_args(&["WikiTools.exe".into(), "--minimized".into()]));
+    }
+
+    #[test]
+    fn builds_the_page_url() {
+        assert_eq!(page_url("http://127.0.0.1:3000/", "fr/vpn#install"), "http://127.0.0.1:3000/fr/vpn#install");
+        assert_eq!(page_url("http://127.0.0.1:3000", ""), "http://127.0.0.1:3000/");
+    }
+}
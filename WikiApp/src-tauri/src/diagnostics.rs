@@ -0,0 +1,200 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::health::HealthReport;
+use crate::platform;
+use crate::postgres_manager::DatabaseDiagnostics;
+use crate::settings;
+use crate::wiki_supervisor::WikiStatus;
+
+/// Au-delà, seule la fin de chaque fichier de log est incluse dans le paquet.
+const MAX_LOG_BYTES: u64 = 2 * 1024 * 1024;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct OsDetails {
+    pub os: String,
+    pub version: String,
+    pub arch: String,
+}
+
+impl OsDetails {
+    pub fn current() -> Self {
+        let info = os_info::get();
+        Self {
+            os: info.os_type().to_string(),
+            version: info.version().to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        }
+    }
+}
+
+/// Versions des composants livrés avec l'application.
+#[derive(Serialize, Clone, Debug)]
+pub struct ComponentVersions {
+    pub app: String,
+    pub node: Option<String>,
+    pub wiki_js: Option<String>,
+}
+
+/// Port attendu par un composant, et s'il est actuellement occupé sur 127.0.0.1.
+#[derive(Serialize, Clone, Debug)]
+pub struct PortUsage {
+    pub role: &'static str,
+    pub port: u16,
+    pub in_use: bool,
+}
+
+impl PortUsage {
+    pub fn check(role: &'static str, port: u16) -> Self {
+        Self { role, port, in_use: !settings::is_port_free(port) }
+    }
+}
+
+/// Contenu de `summary.json` dans le paquet de diagnostic.
+#[derive(Serialize, Clone, Debug)]
+pub struct DiagnosticsSummary {
+    pub generated_at: DateTime<Local>,
+    pub os: OsDetails,
+    pub versions: ComponentVersions,
+    pub database: Option<DatabaseDiagnostics>,
+    /// Raison pour laquelle la base n'a pas pu être décrite.
+    pub database_error: Option<String>,
+    pub ports: Vec<PortUsage>,
+    pub wiki: WikiStatus,
+    pub last_health: Option<HealthReport>,
+}
+
+/// `node --version`, avec le Node.js utilisé pour lancer Wiki.js.
+pub async fn node_version() -> Option<String> {
+    let mut cmd = Command::new("node");
+    cmd.arg("--version");
+    platform::hide_console(&mut cmd);
+    let output = tokio::process::Command::from(cmd).output().await.ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Version de Wiki.js lue dans son `package.json`.
+pub fn wiki_js_version(wiki_dir: &Path) -> Option<String> {
+    let content = fs::read_to_string(wiki_dir.join("package.json")).ok()?;
+    let package: serde_json::Value = serde_json::from_str(&content).ok()?;
+    package.get("version")?.as_str().map(str::to_string)
+}
+
+/// Ecrit le paquet : `summary.json`, puis chaque fichier de `files` sous le nom indiqué.
+/// Les fichiers absents sont ignorés, les plus gros tronqués à leurs 2 derniers Mo.
+pub fn write_bundle(path: &Path, summary: &DiagnosticsSummary, files: &[(String, PathBuf)]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Impossible de créer {:?}: {}", parent, e))?;
+    }
+    let file = File::create(path).map_err(|e| format!("Création de {:?} impossible: {}", path, e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let zip_error = |e: zip::result::ZipError| format!("Ecriture du paquet de diagnostic impossible: {}", e);
+    let io_error = |e: std::io::Error| format!("Ecriture du paquet de diagnostic impossible: {}", e);
+
+    let content = serde_json::to_vec_pretty(summary).map_err(|e| e.to_string())?;
+    zip.start_file("summary.json", options).map_err(zip_error)?;
+    zip.write_all(&content).map_err(io_error)?;
+
+    for (name, source) in files {
+        let Ok(content) = read_tail(source) else {
+            continue;
+        };
+        zip.start_file(name.as_str(), options).map_err(zip_error)?;
+        zip.write_all(&content).map_err(io_error)?;
+    }
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+fn read_tail(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut content = Vec::new();
+    if len > MAX_LOG_BYTES {
+        content.extend_from_slice(format!("[... {} octets omis ...]\n", len - MAX_LOG_BYTES).as_bytes());
+        file.seek(SeekFrom::Start(len - MAX_LOG_BYTES))?;
+    }
+    file.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_config::{DatabaseMode, DbConfig};
+    use crate::pg_probe::Readiness;
+    use crate::wiki_supervisor::WikiState;
+
+    const SECRETS: [&str; 3] = ["superuser-s3cret", "app-s3cret", "collab-t0ken"];
+
+    fn summary(dir: &Path) -> DiagnosticsSummary {
+        let mut config = DbConfig::embedded(5433, SECRETS[0].into(), SECRETS[1].into());
+        config.extra.insert("collab".into(), serde_json::json!({ "api_token": SECRETS[2] }));
+        DiagnosticsSummary {
+            generated_at: Local::now(),
+            os: OsDetails::current(),
+            versions: ComponentVersions { app: "1.0.0".into(), node: None, wiki_js: None },
+            database: Some(DatabaseDiagnostics {
+                mode: DatabaseMode::Embedded,
+                host: config.host.clone(),
+                port: config.port,
+                db_name: "wikijs".into(),
+                config_file: dir.join("db_config.json"),
+                config: config.redacted(),
+                data_dir: dir.join("data"),
+                bin_dir: dir.join("bin"),
+                log_dir: dir.join("logs"),
+                binaries_version: None,
+                cluster_version: Some("16".into()),
+                readiness: Readiness::PortClosed,
+                consumers: Vec::new(),
+            }),
+            database_error: None,
+            ports: Vec::new(),
+            wiki: WikiStatus {
+                state: WikiState::Stopped,
+                pid: None,
+                restarts: 0,
+                last_exit_code: None,
+                last_exit_at: None,
+                last_error: None,
+                log_file: dir.join("wiki.log"),
+            },
+            last_health: None,
+        }
+    }
+
+    #[test]
+    fn bundle_contains_no_secret() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("postgresql.log");
+        fs::write(&log, "LOG:  database system is ready to accept connections\n").unwrap();
+        let files = vec![
+            ("logs/postgresql.log".to_string(), log),
+            ("logs/absent.log".to_string(), dir.path().join("absent.log")),
+        ];
+        let bundle = dir.path().join("diagnostics").join("bundle.zip");
+        write_bundle(&bundle, &summary(dir.path()), &files).unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&bundle).unwrap()).unwrap();
+        let mut names = Vec::new();
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index).unwrap();
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            for secret in SECRETS {
+                assert!(!content.contains(secret), "{} contient un secret", entry.name());
+            }
+            names.push(entry.name().to_string());
+        }
+        assert_eq!(names, ["summary.json", "logs/postgresql.log"]);
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use serde::Serialize;
+use tauri::Url;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+
+/// Taille maximale d'un document téléchargé.
+const MAX_DOWNLOAD_BYTES: u64 = 512 * 1024 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Délai maximal sans recevoir de données avant d'abandonner.
+const STALL_TIMEOUT: Duration = Duration::from_secs(60);
+/// Intervalle minimal entre deux événements de progression.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+/// Limite en octets (255 pour la plupart des systèmes de fichiers), avec une marge pour le
+/// suffixe ` (999)` ajouté par `create_unique`.
+const MAX_FILE_NAME_BYTES: usize = 240;
+
+/// Avancement d'un téléchargement, émis (`download-progress`) vers l'interface.
+#[derive(Serialize, Clone, Debug)]
+pub struct DownloadProgress {
+    pub id: u64,
+    /// URL demandée, telle que reçue (relative le plus souvent).
+    pub url: String,
+    pub file_name: String,
+    pub received: u64,
+    /// `None` si le serveur n'annonce pas la taille.
+    pub total: Option<u64>,
+    pub done: bool,
+    /// Téléchargement interrompu (erreur ou annulation) ; le fichier partiel est supprimé.
+    pub failed: bool,
+}
+
+/// Echec d'un téléchargement. L'annulation est distinguée pour ne pas être présentée comme une erreur.
+#[derive(Debug)]
+pub enum DownloadError {
+    Cancelled,
+    Failed(String),
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cancelled => f.write_str("Téléchargement annulé"),
+            Self::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+impl From<String> for DownloadError {
+    fn from(error: String) -> Self {
+        Self::Failed(error)
+    }
+}
+
+/// Téléchargements en cours, annulables par leur identifiant.
+pub struct Downloads {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, watch::Sender<bool>>>,
+}
+
+impl Downloads {
+    pub fn new() -> Self {
+        Self { next_id: AtomicU64::new(1), active: Mutex::new(HashMap::new()) }
+    }
+
+    /// Demande l'annulation ; `false` si aucun téléchargement ne porte cet identifiant.
+    pub fn cancel(&self, id: u64) -> bool {
+        self.active.lock().unwrap().get(&id).is_some_and(|cancel| cancel.send(true).is_ok())
+    }
+
+    /// Télécharge `target` dans `dir` sous un nom unique, sans jamais écraser un fichier
+    /// existant (éventuellement ouvert dans une autre application). `requested` est l'URL
+    /// d'origine, reprise dans les événements de progression.
+    pub async fn fetch(
+        &self,
+        requested: &str,
+        target: Url,
+        dir: &Path,
+        mut on_progress: impl FnMut(&DownloadProgress),
+    ) -> Result<PathBuf, DownloadError> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        self.active.lock().unwrap().insert(id, cancel_tx);
+        let result = download(id, requested, target, dir, cancel_rx, &mut on_progress).await;
+        self.active.lock().unwrap().remove(&id);
+        result
+    }
+}
+
+async fn download(
+    id: u64,
+    requested: &str,
+    target: Url,
+    dir: &Path,
+    mut cancelled: watch::Receiver<bool>,
+    on_progress: &mut impl FnMut(&DownloadProgress),
+) -> Result<PathBuf, DownloadError> {
+    let client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut response = client
+        .get(target.clone())
+        .send()
+        .await
+        .map_err(|e| format!("Téléchargement impossible: {}", e))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("Le wiki a répondu HTTP {} pour {}", status.as_u16(), target).into());
+    }
+    // Une page HTML à la place d'un document : page de connexion ou d'erreur du wiki
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    let wants_html = target.path().ends_with(".html") || target.path().ends_with(".htm");
+    if is_html && !wants_html {
+        return Err("Le wiki a renvoyé une page web au lieu du document (droits insuffisants ou lien cassé ?)".to_string().into());
+    }
+    let total = response.content_length();
+    if total.is_some_and(|total| total > MAX_DOWNLOAD_BYTES) {
+        return Err(format!("Document trop volumineux (limite : {} Mo)", MAX_DOWNLOAD_BYTES / (1024 * 1024)).into());
+    }
+
+    let name = response
+        .headers()
+        .get(CONTENT_DISPOSITION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(disposition_file_name)
+        .or_else(|| url_file_name(&target))
+        .unwrap_or_else(|| "document".to_string());
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| format!("Impossible de créer {:?}: {}", dir, e))?;
+    let (path, mut file) = create_unique(dir, &sanitize_file_name(&name)).await?;
+    println!("📥 Téléchargement de {} vers {:?}", target, path);
+
+    let mut progress = DownloadProgress {
+        id,
+        url: requested.to_string(),
+        file_name: path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+        received: 0,
+        total,
+        done: false,
+        failed: false,
+    };
+    on_progress(&progress);
+    let mut last_event = Instant::now();
+
+    let outcome: Result<(), DownloadError> = async {
+        loop {
+            let chunk = tokio::select! {
+                chunk = tokio::time::timeout(STALL_TIMEOUT, response.chunk()) => chunk
+                    .map_err(|_| "Téléchargement interrompu : le serveur ne répond plus".to_string())?
+                    .map_err(|e| format!("Téléchargement interrompu: {}", e))?,
+                _ = cancelled.wait_for(|cancelled| *cancelled) => return Err(DownloadError::Cancelled),
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            progress.received += chunk.len() as u64;
+            if progress.received > MAX_DOWNLOAD_BYTES {
+                return Err(format!("Document trop volumineux (limite : {} Mo)", MAX_DOWNLOAD_BYTES / (1024 * 1024)).into());
+            }
+            file.write_all(&chunk).await.map_err(|e| format!("Ecriture de {:?} impossible: {}", path, e))?;
+            if last_event.elapsed() >= PROGRESS_INTERVAL {
+                on_progress(&progress);
+                last_event = Instant::now();
+            }
+        }
+        file.flush().await.map_err(|e| format!("Ecriture de {:?} impossible: {}", path, e).into())
+    }
+    .await;
+
+    drop(file);
+    if let Err(e) = outcome {
+        // Ne pas laisser de document tronqué derrière nous
+        let _ = tokio::fs::remove_file(&path).await;
+        progress.failed = true;
+        on_progress(&progress);
+        return Err(e);
+    }
+    progress.done = true;
+    on_progress(&progress);
+    Ok(path)
+}
+
+/// Nom proposé par `Content-Disposition`, `filename*` (RFC 5987) en priorité.
+fn disposition_file_name(value: &str) -> Option<String> {
+    let mut plain = None;
+    for param in value.split(';').skip(1) {
+        let Some((key, raw)) = param.split_once('=') else {
+            continue;
+        };
+        let raw = raw.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "filename*" => {
+                // charset'langue'valeur-encodée
+                let Some(encoded) = raw.splitn(3, '\'').nth(2) else {
+                    continue;
+                };
+                if let Ok(decoded) = urlencoding::decode(encoded.trim_matches('"')) {
+                    return Some(decoded.into_owned());
+                }
+            }
+            "filename" => plain = Some(raw.trim_matches('"').to_string()),
+            _ => {}
+        }
+    }
+    plain.filter(|name| !name.is_empty())
+}
+
+/// Dernier segment du chemin de l'URL, décodé (sans la requête ni l'ancre).
+fn url_file_name(url: &Url) -> Option<String> {
+    let segment = url.path_segments()?.next_back().filter(|segment| !segment.is_empty())?;
+    Some(urlencoding::decode(segment).map_or_else(|_| segment.to_string(), |name| name.into_owned()))
+}
+
+/// Nom de fichier sûr sur tous les systèmes : ni séparateur, ni caractère interdit sous
+/// Windows, ni nom réservé (`CON`, `NUL`...), ni nom caché ou vide.
+fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if c.is_control() || r#"<>:"/\|?*"#.contains(c) { '_' } else { c })
+        .collect();
+    let cleaned = cleaned.trim().trim_start_matches('.').trim_end_matches(['.', ' ']);
+    if cleaned.is_empty() {
+        return "document".to_string();
+    }
+
+    let (stem, extension) = match cleaned.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && extension.chars().count() <= 10 => (stem, Some(extension)),
+        _ => (cleaned, None),
+    };
+    // Windows ignore tout ce qui suit le premier point : `CON.tar.gz` est réservé
+    let upper = cleaned.split('.').next().unwrap_or_default().trim_end().to_ascii_uppercase();
+    let reserved = ["CON", "PRN", "AUX", "NUL"].contains(&upper.as_str())
+        || (upper.len() == 4
+            && (upper.starts_with("COM") || upper.starts_with("LPT"))
+            && upper.ends_with(|c: char| c.is_ascii_digit()));
+    let max_stem = MAX_FILE_NAME_BYTES - extension.map_or(0, |extension| extension.len() + 1);
+    let mut end = stem.len().min(max_stem);
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut stem = stem[..end].trim_end_matches(['.', ' ']).to_string();
+    if reserved {
+        stem.insert(0, '_');
+    }
+    match extension {
+        Some(extension) => format!("{}.{}", stem, extension),
+        None => stem,
+    }
+}
+
+/// Crée `name` dans `dir`, ou `nom (2).ext`, `nom (3).ext`... s'il existe déjà.
+async fn create_unique(dir: &Path, name: &str) -> Result<(PathBuf, tokio::fs::File), String> {
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (name, String::new()),
+    };
+    for n in 1..1000 {
+        let candidate = match n {
+            1 => dir.join(name),
+            _ => dir.join(format!("{} ({}){}", stem, n, extension)),
+        };
+        match tokio::fs::OpenOptions::new().write(true).create_new(true).open(&candidate).await {
+            Ok(file) => return Ok((candidate, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Création de {:?} impossible: {}", candidate, e)),
+        }
+    }
+    Err(format!("Trop de fichiers nommés {:?} dans {:?}", name, dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disposition_prefers_encoded_file_name() {
+        let value = r#"attachment; filename="rapport.pdf"; filename*=UTF-8''rapport%20d%C3%A9taill%C3%A9.pdf"#;
+        assert_eq!(disposition_file_name(value).as_deref(), Some("rapport détaillé.pdf"));
+    }
+
+    #[test]
+    fn disposition_falls_back_to_plain_file_name() {
+        assert_eq!(disposition_file_name(r#"attachment; filename="notes.txt""#).as_deref(), Some("notes.txt"));
+        assert_eq!(disposition_file_name("attachment; filename=notes.txt").as_deref(), Some("notes.txt"));
+        // filename* mal formé : on garde filename
+        assert_eq!(disposition_file_name(r#"attachment; filename*=broken; filename="notes.txt""#).as_deref(), Some("notes.txt"));
+    }
+
+    #[test]
+    fn disposition_without_file_name() {
+        assert_eq!(disposition_file_name("inline"), None);
+        assert_eq!(disposition_file_name(r#"attachment; filename="""#), None);
+    }
+
+    #[test]
+    fn sanitize_replaces_forbidden_characters() {
+        assert_eq!(sanitize_file_name("../../etc/passwd"), "_.._etc_passwd");
+        assert_eq!(sanitize_file_name(r#"a<b>c:d"e|f?g*h\i.pdf"#), "a_b_c_d_e_f_g_h_i.pdf");
+        assert_eq!(sanitize_file_name("tab\there.txt"), "tab_here.txt");
+    }
+
+    #[test]
+    fn sanitize_rejects_hidden_empty_and_trailing_dots() {
+        assert_eq!(sanitize_file_name(".bashrc"), "bashrc");
+        assert_eq!(sanitize_file_name("   "), "document");
+        assert_eq!(sanitize_file_name("..."), "document");
+        assert_eq!(sanitize_file_name("rapport.pdf. . "), "rapport.pdf");
+    }
+
+    #[test]
+    fn sanitize_prefixes_reserved_windows_names() {
+        assert_eq!(sanitize_file_name("CON"), "_CON");
+        assert_eq!(sanitize_file_name("nul.txt"), "_nul.txt");
+        assert_eq!(sanitize_file_name("com1.docx"), "_com1.docx");
+        assert_eq!(sanitize_file_name("LPT9"), "_LPT9");
+        assert_eq!(sanitize_file_name("console.txt"), "console.txt");
+        assert_eq!(sanitize_file_name("COM10.txt"), "COM10.txt");
+        assert_eq!(sanitize_file_name("CON.tar.gz"), "_CON.tar.gz");
+        assert_eq!(sanitize_file_name("aux .txt"), "_aux.txt");
+        assert_eq!(sanitize_file_name("nul.backup.2024.txt"), "_nul.backup.2024.txt");
+        assert_eq!(sanitize_file_name("context.tar.gz"), "context.tar.gz");
+    }
+
+    #[test]
+    fn sanitize_truncates_long_names_but_keeps_the_extension() {
+        let name = sanitize_file_name(&format!("{}.pdf", "é".repeat(300)));
+        assert!(name.len() <= MAX_FILE_NAME_BYTES);
+        assert!(name.len() > MAX_FILE_NAME_BYTES - 2);
+        assert!(name.ends_with("é.pdf"));
+
+        // Caractères de 4 octets : coupure sur une frontière de caractère
+        let name = sanitize_file_name(&"📄".repeat(100));
+        assert_eq!(name, "📄".repeat(MAX_FILE_NAME_BYTES / 4));
+    }
+
+    #[tokio::test]
+    async fn create_unique_never_overwrites() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("rapport.pdf"), b"existant").unwrap();
+
+        let (first, _) = create_unique(dir, "rapport.pdf").await.unwrap();
+        let (second, _) = create_unique(dir, "rapport.pdf").await.unwrap();
+        let (plain, _) = create_unique(dir, "LISEZMOI").await.unwrap();
+        assert_eq!(first, dir.join("rapport (2).pdf"));
+        assert_eq!(second, dir.join("rapport (3).pdf"));
+        assert_eq!(plain, dir.join("LISEZMOI"));
+        assert_eq!(std::fs::read(dir.join("rapport.pdf")).unwrap(), b"existant");
+    }
+}
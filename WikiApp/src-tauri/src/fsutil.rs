@@ -0,0 +1,138 @@
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Copie récursive d'un dossier (fichiers et sous-dossiers ; les liens sont ignorés).
+pub fn copy_dir_recursive(from: &Path, to: &Path) -> Result<(), String> {
+    fs::create_dir_all(to).map_err(|e| format!("Impossible de créer {:?}: {}", to, e))?;
+    let entries = fs::read_dir(from).map_err(|e| format!("Lecture de {:?} impossible: {}", from, e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let target = to.join(entry.file_name());
+        let file_type = entry.file_type().map_err(|e| e.to_string())?;
+        if file_type.is_dir() {
+            copy_dir_recursive(&entry.path(), &target)?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), &target).map_err(|e| format!("Copie de {:?} impossible: {}", entry.path(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Verrou consultatif inter-processus sur un fichier dédié (`*.lock`), libéré à la destruction.
+/// Ne protège que contre les processus qui prennent le même verrou.
+pub struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    /// Attend le verrou au plus `timeout`.
+    pub fn acquire(lock_path: &Path, timeout: Duration) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(lock_path)
+            .map_err(|e| format!("Ouverture du verrou {:?} impossible: {}", lock_path, e))?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(Self { file }),
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => thread::sleep(Duration::from_millis(100)),
+                Err(TryLockError::WouldBlock) => {
+                    return Err(format!("{:?} est tenu par un autre processus, réessayez dans un instant", lock_path));
+                }
+                Err(TryLockError::Error(e)) => return Err(format!("Verrouillage de {:?} impossible: {}", lock_path, e)),
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+/// Ecrit dans un fichier temporaire propre à ce processus, le synchronise sur disque puis le
+/// renomme : un lecteur voit l'ancien ou le nouveau contenu, jamais un fichier tronqué.
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), String> {
+    write_atomic_with(path, content, |tmp_path| File::create(tmp_path))
+}
+
+/// Comme [`write_atomic`], pour un fichier lisible par son seul propriétaire (mode 0600 sous
+/// Unix, dès la création du fichier temporaire). Sous Windows, les droits du dossier s'appliquent.
+pub fn write_atomic_private(path: &Path, content: &[u8]) -> Result<(), String> {
+    write_atomic_with(path, content, |tmp_path| {
+        // Le mode ne s'applique qu'à la création : pas de fichier temporaire hérité d'un plantage
+        let _ = fs::remove_file(tmp_path);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(tmp_path)
+    })
+}
+
+fn write_atomic_with(path: &Path, content: &[u8], create: impl FnOnce(&Path) -> io::Result<File>) -> Result<(), String> {
+    let mut tmp_name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path: PathBuf = path.with_file_name(tmp_name);
+    let result = create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, path));
+    result.map_err(|e| {
+        let _ = fs::remove_file(&tmp_path);
+        format!("Echec écriture {:?}: {}", path, e)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_atomic_replaces_content_without_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db_config.json");
+        fs::write(&path, "ancien contenu, plus long que le nouveau").unwrap();
+
+        write_atomic(&path, b"nouveau").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "nouveau");
+        let names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, ["db_config.json"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_files_are_readable_by_their_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secrets.key");
+        write_atomic_private(&path, b"cle").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn held_lock_times_out_and_is_released_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock_path = dir.path().join("db_config.json.lock");
+        let held = FileLock::acquire(&lock_path, Duration::ZERO).unwrap();
+
+        let started = Instant::now();
+        let error = FileLock::acquire(&lock_path, Duration::from_millis(300)).err().unwrap();
+        assert!(error.contains("tenu par un autre processus"), "{}", error);
+        assert!(started.elapsed() >= Duration::from_millis(300));
+
+        drop(held);
+        assert!(FileLock::acquire(&lock_path, Duration::ZERO).is_ok());
+    }
+}
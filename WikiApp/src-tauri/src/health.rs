@@ -0,0 +1,275 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use sqlx::postgres::PgConnectOptions;
+
+use crate::pg_admin;
+
+/// Fréquence de la surveillance en tâche de fond.
+pub const HEALTH_INTERVAL: Duration = Duration::from_secs(30);
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Au-delà, un service qui répond est signalé comme lent.
+const SLOW_RESPONSE: Duration = Duration::from_secs(2);
+/// Espace libre sous lequel le volume de données est signalé (dégradé, puis critique).
+const LOW_DISK_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+const CRITICAL_DISK_BYTES: u64 = 500 * 1024 * 1024;
+
+/// Du meilleur au pire : l'état global est le pire des états connus des vérifications.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Ok,
+    /// Pas encore démarré ou sans objet (ex. processus PostgreSQL d'un serveur distant).
+    Unknown,
+    Degraded,
+    Down,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Check {
+    pub status: HealthStatus,
+    pub latency_ms: Option<u64>,
+    pub detail: Option<String>,
+}
+
+impl Check {
+    pub fn ok(detail: impl Into<String>) -> Self {
+        Self { status: HealthStatus::Ok, latency_ms: None, detail: Some(detail.into()) }
+    }
+
+    pub fn down(detail: impl Into<String>) -> Self {
+        Self { status: HealthStatus::Down, latency_ms: None, detail: Some(detail.into()) }
+    }
+
+    pub fn unknown(detail: impl Into<String>) -> Self {
+        Self { status: HealthStatus::Unknown, latency_ms: None, detail: Some(detail.into()) }
+    }
+
+    /// Résultat d'un appel chronométré : lent au-delà de [`SLOW_RESPONSE`].
+    fn timed(started: Instant, result: Result<String, String>) -> Self {
+        let elapsed = started.elapsed();
+        let status = match &result {
+            Ok(_) if elapsed > SLOW_RESPONSE => HealthStatus::Degraded,
+            Ok(_) => HealthStatus::Ok,
+            Err(_) => HealthStatus::Down,
+        };
+        Self {
+            status,
+            latency_ms: Some(elapsed.as_millis() as u64),
+            detail: Some(result.unwrap_or_else(|e| e)),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct DiskCheck {
+    pub path: PathBuf,
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+    pub status: HealthStatus,
+}
+
+/// Etat de l'ensemble des services, renvoyé par `check_health` et émis (`health-changed`)
+/// par la surveillance en tâche de fond lorsqu'un statut change.
+#[derive(Serialize, Clone, Debug)]
+pub struct HealthReport {
+    pub checked_at: DateTime<Local>,
+    pub status: HealthStatus,
+    /// Connexion à la base Wiki.js avec le compte applicatif.
+    pub database: Check,
+    pub wiki_http: Check,
+    pub wiki_graphql: Check,
+    pub disk: Option<DiskCheck>,
+    pub postgres_process: Check,
+    pub wiki_process: Check,
+}
+
+impl HealthReport {
+    pub fn new(
+        database: Check,
+        wiki_http: Check,
+        wiki_graphql: Check,
+        disk: Option<DiskCheck>,
+        postgres_process: Check,
+        wiki_process: Check,
+    ) -> Self {
+        // Les vérifications sans objet ne comptent pas, sauf si aucune autre n'a abouti
+        let status = [&database, &wiki_http, &wiki_graphql, &postgres_process, &wiki_process]
+            .iter()
+            .map(|check| check.status)
+            .chain(disk.as_ref().map(|disk| disk.status))
+            .filter(|status| *status != HealthStatus::Unknown)
+            .max()
+            .unwrap_or(HealthStatus::Unknown);
+        Self { checked_at: Local::now(), status, database, wiki_http, wiki_graphql, disk, postgres_process, wiki_process }
+    }
+
+    /// Un statut (global ou d'une vérification) a-t-il changé depuis `previous` ? Latences et
+    /// détails ne comptent pas : seul un changement d'état justifie un événement.
+    pub fn changed_since(&self, previous: Option<&HealthReport>) -> bool {
+        previous.is_none_or(|previous| previous.statuses() != self.statuses())
+    }
+
+    /// Statuts de chaque vérification, pour détecter un changement d'un rapport à l'autre.
+    pub fn statuses(&self) -> Vec<HealthStatus> {
+        vec![
+            self.status,
+            self.database.status,
+            self.wiki_http.status,
+            self.wiki_graphql.status,
+            self.disk.as_ref().map_or(HealthStatus::Unknown, |disk| disk.status),
+            self.postgres_process.status,
+            self.wiki_process.status,
+        ]
+    }
+}
+
+/// Connexion et `SELECT 1` avec `options` (compte applicatif sur la base Wiki.js).
+pub async fn check_database(options: &PgConnectOptions) -> Check {
+    let started = Instant::now();
+    let attempt = async {
+        let mut conn = pg_admin::connect(options).await?;
+        let result = sqlx::query_scalar::<_, i32>("SELECT 1")
+            .fetch_one(&mut conn)
+            .await
+            .map(|_| "connexion et requête réussies".to_string())
+            .map_err(|e| pg_admin::describe_error(&e));
+        pg_admin::close(conn).await;
+        result
+    };
+    let result = tokio::time::timeout(CHECK_TIMEOUT, attempt)
+        .await
+        .unwrap_or_else(|_| Err("délai de connexion dépassé".to_string()));
+    Check::timed(started, result)
+}
+
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder().timeout(CHECK_TIMEOUT).build().unwrap_or_default()
+}
+
+/// GET sur la racine de Wiki.js.
+pub async fn check_http(url: &str) -> Check {
+    let started = Instant::now();
+    let result = match http_client().get(url).send().await {
+        Ok(res) if res.status().is_success() => Ok(format!("HTTP {}", res.status().as_u16())),
+        Ok(res) => Err(format!("HTTP {}", res.status().as_u16())),
+        Err(e) => Err(format!("pas de réponse ({})", e)),
+    };
+    Check::timed(started, result)
+}
+
+/// Requête minimale sur l'API GraphQL de Wiki.js (qui interroge la base).
+pub async fn check_graphql(url: &str) -> Check {
+    let started = Instant::now();
+    let endpoint = format!("{}/graphql", url.trim_end_matches('/'));
+    let response = http_client()
+        .post(&endpoint)
+        .json(&serde_json::json!({ "query": "{ __typename }" }))
+        .send()
+        .await;
+    let result = match response {
+        Ok(res) if res.status().is_success() => match res.json::<serde_json::Value>().await {
+            Ok(body) if body.get("data").is_some_and(|data| !data.is_null()) => Ok("API GraphQL opérationnelle".to_string()),
+            Ok(body) => Err(format!("réponse GraphQL en erreur ({})", body.get("errors").unwrap_or(&body))),
+            Err(e) => Err(format!("réponse GraphQL illisible ({})", e)),
+        },
+        Ok(res) => Err(format!("HTTP {}", res.status().as_u16())),
+        Err(e) => Err(format!("pas de réponse ({})", e)),
+    };
+    Check::timed(started, result)
+}
+
+/// Espace libre sur le volume contenant `path` (ou son plus proche parent existant).
+pub fn check_disk(path: &Path) -> Option<DiskCheck> {
+    let existing = path.ancestors().find(|dir| dir.exists())?;
+    let stats = fs4::statvfs(existing).ok()?;
+    let available = stats.available_space();
+    Some(DiskCheck {
+        path: path.to_path_buf(),
+        available_bytes: available,
+        total_bytes: stats.total_space(),
+        status: disk_status(available),
+    })
+}
+
+fn disk_status(available_bytes: u64) -> HealthStatus {
+    if available_bytes < CRITICAL_DISK_BYTES {
+        HealthStatus::Down
+    } else if available_bytes < LOW_DISK_BYTES {
+        HealthStatus::Degraded
+    } else {
+        HealthStatus::Ok
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disk(available_bytes: u64) -> DiskCheck {
+        DiskCheck {
+            path: PathBuf::from("data"),
+            available_bytes,
+            total_bytes: 100 * 1024 * 1024 * 1024,
+            status: disk_status(available_bytes),
+        }
+    }
+
+    fn report(database: Check, disk: Option<DiskCheck>) -> HealthReport {
+        HealthReport::new(
+            database,
+            Check::ok("HTTP 200"),
+            Check::ok("API GraphQL opérationnelle"),
+            disk,
+            Check::ok("processus actif"),
+            Check::ok("processus Node.js actif"),
+        )
+    }
+
+    #[test]
+    fn disk_thresholds() {
+        assert_eq!(disk_status(LOW_DISK_BYTES), HealthStatus::Ok);
+        assert_eq!(disk_status(LOW_DISK_BYTES - 1), HealthStatus::Degraded);
+        assert_eq!(disk_status(CRITICAL_DISK_BYTES - 1), HealthStatus::Down);
+    }
+
+    #[test]
+    fn low_disk_degrades_the_report() {
+        assert_eq!(report(Check::ok("SELECT 1"), Some(disk(LOW_DISK_BYTES * 4))).status, HealthStatus::Ok);
+        assert_eq!(report(Check::ok("SELECT 1"), Some(disk(LOW_DISK_BYTES - 1))).status, HealthStatus::Degraded);
+        assert_eq!(report(Check::down("refusé"), Some(disk(LOW_DISK_BYTES - 1))).status, HealthStatus::Down);
+    }
+
+    #[test]
+    fn unknown_checks_do_not_count_unless_nothing_else_is_known() {
+        assert_eq!(report(Check::unknown("non démarré"), None).status, HealthStatus::Ok);
+        let nothing = HealthReport::new(
+            Check::unknown("-"), Check::unknown("-"), Check::unknown("-"), None, Check::unknown("-"), Check::unknown("-"),
+        );
+        assert_eq!(nothing.status, HealthStatus::Unknown);
+    }
+
+    #[test]
+    fn only_status_changes_are_reported() {
+        let first = report(Check::ok("SELECT 1"), Some(disk(LOW_DISK_BYTES * 4)));
+        assert!(first.changed_since(None));
+
+        // Même état, latences et détails différents
+        let mut same = report(Check::ok("connexion et requête réussies"), Some(disk(LOW_DISK_BYTES * 3)));
+        same.database.latency_ms = Some(12);
+        assert!(!same.changed_since(Some(&first)));
+
+        // Seul le disque change : le rapport global passe aussi en dégradé
+        let low_disk = report(Check::ok("SELECT 1"), Some(disk(LOW_DISK_BYTES - 1)));
+        assert!(low_disk.changed_since(Some(&first)));
+
+        // Le pire état ne bouge pas, mais une autre vérification change
+        let mut also_down = report(Check::down("refusé"), None);
+        let before = also_down.clone();
+        also_down.wiki_graphql = Check::down("HTTP 500");
+        assert_eq!(also_down.status, before.status);
+        assert!(also_down.changed_since(Some(&before)));
+    }
+}
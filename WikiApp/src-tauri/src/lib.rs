@@ -1,81 +1,189 @@
 use std::process::Command;
-use std::time::Duration;
-use tauri::Manager;
-use std::sync::Mutex;
+use tauri::ipc::CapabilityBuilder;
+use tauri::{Emitter, Manager, RunEvent, WindowEvent};
+use tauri_plugin_deep_link::DeepLinkExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::path::PathBuf;
-use std::os::windows::process::CommandExt;
+use sqlx::PgPool;
 
+mod backup;
+mod cluster_lease;
+mod cluster_recovery;
+mod command_server;
+mod db_config;
+mod deep_link;
+mod diagnostics;
+mod download;
+mod fsutil;
+mod health;
+mod logs;
+mod pg_admin;
+mod pg_probe;
+mod pg_upgrade;
+mod platform;
 mod postgres_manager;
-use postgres_manager::PostgresManager;
+mod secrets;
+mod settings;
+mod startup;
+mod tray;
+mod wiki_config;
+mod wiki_supervisor;
+use pg_upgrade::UpgradeRecord;
+use postgres_manager::{ClusterConsumer, ConnectionTest, DataDirReport, NetworkDbSettings, PostgresManager};
+use download::{DownloadError, Downloads};
+use diagnostics::{ComponentVersions, DiagnosticsSummary, OsDetails, PortUsage};
+use health::{Check, HealthReport, HealthStatus};
+use pg_probe::Readiness;
+use logs::{LogLevel, LogTail};
+use backup::{BackupInfo, BackupKind, BackupPaths, BackupSettings, BackupTarget};
+use settings::{LauncherSettings, ShutdownSettings};
+use command_server::{LocalCommand, CommandError, CommandGuard, CommandHost};
+use startup::{StartupPhase, StartupProgress};
+use tray::TrayAction;
+use wiki_config::WikiServerSettings;
+use wiki_supervisor::{RestartPolicy, WikiState, WikiStatus, WikiSupervisor};
+
+/// Dossier de données propre à WikiTools (<AppData>/com.wikitools.app).
+fn wikitools_app_dir() -> Result<PathBuf, String> {
+    Ok(platform::app_data_root()?.join("com.wikitools.app"))
+}
+
+/// Fichiers Wiki.js gérés par le launcher (config.yml généré et dossier `data`).
+fn wiki_app_dir() -> Result<PathBuf, String> {
+    Ok(wikitools_app_dir()?.join("wiki"))
+}
+
+fn backup_paths() -> Result<BackupPaths, String> {
+    let wiki_dir = wiki_app_dir()?;
+    Ok(BackupPaths {
+        backups_dir: wikitools_app_dir()?.join("backups"),
+        wiki_data_dir: wiki_dir.join("data"),
+        wiki_config_path: wiki_dir.join("config.yml"),
+    })
+}
 
 struct AppState {
-    postgres_manager: Mutex<Option<PostgresManager>>,
-    wiki_process: Mutex<Option<std::process::Child>>,
+    /// Verrou async : migrations et retours arrière gardent le manager en place pendant
+    /// leurs `await`, les autres commandes attendent au lieu de le trouver absent.
+    postgres_manager: tokio::sync::Mutex<Option<PostgresManager>>,
+    /// Pool superutilisateur sur la base de maintenance `postgres`.
+    db_pool: Mutex<Option<PgPool>>,
+    wiki: WikiSupervisor,
+    /// Port de Wiki.js, choisi au premier démarrage puis fixe pour la session.
+    wiki_port: Mutex<Option<u16>>,
+    /// Emet les événements `startup-progress` vers le launcher.
+    startup: StartupProgress,
+    /// Sérialise sauvegardes et restaurations.
+    backup_lock: tokio::sync::Mutex<()>,
+    /// Dernier bilan de santé (surveillance ou `check_health`), repris dans les diagnostics.
+    last_health: Mutex<Option<HealthReport>>,
+    /// Page du launcher, rechargée pour relancer les services après « Tout arrêter ».
+    launcher_url: Option<tauri::Url>,
+    /// Services arrêtés depuis le menu de notification.
+    stopped_from_tray: AtomicBool,
+    /// Page demandée par un lien `wikitools://` avant que le wiki ne soit affiché.
+    pending_page: Mutex<Option<String>>,
+    /// Secret exigé par le serveur de commandes local.
+    command_guard: CommandGuard,
+    downloads: Downloads,
 }
 
-#[tauri::command]
-async fn init_db(app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<String, String> {
-    println!("🔌 Initialisation de la Base de Données...");
-    
-    // 1. Chercher la config CollabTools (Roaming/com.collabtools.core/postgresql/db_config.json)
-    let roaming = std::env::var("APPDATA").map_err(|_| "Impossible de trouver AppData".to_string())?;
-    let collab_config_path = PathBuf::from(roaming.clone())
+impl AppState {
+    /// URL racine de Wiki.js (`None` tant que le serveur n'a pas été démarré).
+    fn wiki_url(&self) -> Option<String> {
+        self.wiki_port.lock().unwrap().map(|port| format!("http://localhost:{}", port))
+    }
+
+    async fn backup_target(&self) -> Result<BackupTarget, String> {
+        let pm = self.postgres_manager.lock().await;
+        let pm = pm.as_ref().ok_or("Base de données non initialisée")?;
+        if !pm.has_admin_credentials() {
+            return Err("Sauvegardes indisponibles : aucun compte d'administration configuré pour le serveur PostgreSQL".into());
+        }
+        Ok(BackupTarget {
+            tools: pm.pg_tools(),
+            admin_options: pm.superuser_options("postgres"),
+            db_name: pm.db_name.clone(),
+            owner: pm.app_user().to_string(),
+        })
+    }
+}
+
+/// Charge la configuration PostgreSQL : celle de CollabTools si elle existe (cluster partagé),
+/// sinon la nôtre (<AppData>/com.wikitools.app/postgresql). Renvoie aussi `true` si le cluster est partagé.
+fn open_postgres_manager(app_handle: &tauri::AppHandle) -> Result<(PostgresManager, bool), String> {
+    // 1. Chercher la config CollabTools (<AppData>/com.collabtools.core/postgresql/db_config.json)
+    let collab_config_path = platform::app_data_root()?
         .join("com.collabtools.core")
         .join("postgresql")
         .join("db_config.json");
         
     let resource_dir = app_handle.path().resource_dir().map_err(|e| e.to_string())?;
 
-    let mut pm = if collab_config_path.exists() {
+    if collab_config_path.exists() {
         println!("✅ Configuration CollabTools trouvée à : {:?}", collab_config_path);
-        PostgresManager::from_existing_config(collab_config_path, resource_dir, "wiki")?
-    } else {
-        println!("⚠️ Pas de CollabTools détecté. Passage en mode Autonome.");
-        
-        // Mode Autonome : On utilise notre propre AppData
-        // AppData/Roaming/com.wikitools.app/postgresql
-        let wiki_app_data = PathBuf::from(roaming).join("com.wikitools.app");
-        
-        // Créer le dossier s'il n'existe pas
-        if !wiki_app_data.exists() {
-             std::fs::create_dir_all(&wiki_app_data).map_err(|e| format!("Impossible de créer AppData: {}", e))?;
-        }
+        let pm = PostgresManager::from_existing_config(collab_config_path, resource_dir, "wiki")?;
+        return Ok((pm, true));
+    }
+
+    println!("⚠️ Pas de CollabTools détecté. Passage en mode Autonome.");
+    // Mode Autonome : On utilise notre propre AppData
+    // <AppData>/com.wikitools.app/postgresql
+    let wiki_app_data = wikitools_app_dir()?;
+    
+    // Créer le dossier s'il n'existe pas
+    if !wiki_app_data.exists() {
+         std::fs::create_dir_all(&wiki_app_data).map_err(|e| format!("Impossible de créer AppData: {}", e))?;
+    }
+
+    let pm = PostgresManager::new(wiki_app_data, resource_dir)
+        .map_err(|e| format!("Echec init manager autonome: {}", e))?;
+    Ok((pm, false))
+}
+
+#[tauri::command]
+async fn init_db(app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<String, String> {
+    println!("🔌 Initialisation de la Base de Données...");
+    let progress = &state.startup;
+    let (mut pm, shared) = progress.step(StartupPhase::LoadConfig, || open_postgres_manager(&app_handle))?;
 
-        let mut standalone_pm = PostgresManager::new(wiki_app_data, resource_dir)
-            .map_err(|e| format!("Echec init manager autonome: {}", e))?;
-            
+    if !shared {
         // En mode autonome, on s'assure d'initialiser (initdb) si c'est la toute première fois
-        standalone_pm.init_database().map_err(|e| format!("Echec initdb autonome: {}", e))?;
-            
-        standalone_pm
-    };
+        pm.init_database(progress).await.map_err(|e| format!("Echec initdb autonome: {}", e))?;
+    }
+
+    // Binaires plus récents que le cluster (mise à jour de l'application) : migration avant démarrage
+    progress.step_async(StartupPhase::ClusterUpgrade, pm.upgrade_if_needed()).await?;
 
     println!("🔄 Tentative de démarrage du Manager PostgreSQL...");
     // 2. Démarrer / Vérifier Postgre
-    pm.start().map_err(|e| format!("Erreur start(): {}", e))?;
+    progress.step_async(StartupPhase::DatabaseStart, pm.start()).await
+        .map_err(|e| format!("Erreur start(): {}", e))?;
     println!("✅ Manager démarré (ou déjà running).");
     
     // 3. Créer la DB 'wiki' si nécessaire
     println!("🔄 Vérification de la base de données 'wiki'...");
-    pm.ensure_database_exists().map_err(|e| format!("Erreur ensure_db(): {}", e))?;
+    progress.step_async(StartupPhase::DatabaseCheck, pm.ensure_database_exists()).await
+        .map_err(|e| format!("Erreur ensure_db(): {}", e))?;
     println!("✅ Base 'wiki' validée.");
+
+    // Pool d'administration partagé (superutilisateur), non bloquant s'il échoue
+    if pm.has_admin_credentials() {
+        match pg_admin::connect_pool(&pm.superuser_options("postgres")).await {
+            Ok(pool) => *state.db_pool.lock().unwrap() = Some(pool),
+            Err(e) => eprintln!("⚠️ Pool d'administration indisponible: {}", e),
+        }
+    }
     
     // Stocker le manager dans l'état
-    *state.postgres_manager.lock().unwrap() = Some(pm);
+    *state.postgres_manager.lock().await = Some(pm);
     
     Ok("Base de données prête.".to_string())
 }
 
-#[tauri::command]
-async fn start_wiki_server(app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<String, String> {
-    println!("🚀 Démarrage du Serveur Wiki.js...");
-    
-    // Vérifier si déjà lancé
-    let mut procs = state.wiki_process.lock().unwrap();
-    if procs.is_some() {
-        return Ok("Déjà lancé".to_string());
-    }
-
+/// Dossier 'wiki' (serveur Wiki.js) livré avec l'application.
+fn find_wiki_dir() -> Result<PathBuf, String> {
     // Chemin vers le dossier 'wiki' (à côté de l'exe)
     // En prod, l'exe est à la racine, 'wiki' est à côté.
     // En dev, on est dans src-tauri... attention.
@@ -92,139 +200,978 @@ async fn start_wiki_server(app_handle: tauri::AppHandle, state: tauri::State<'_,
         current_dir.join("../../wiki"),    // Dev (depuis src-tauri/src ?)
     ];
     
-    let wiki_dir = candidates.into_iter().find(|p| p.exists())
-        .ok_or(format!("Dossier 'wiki' introuvable. Cherché dans : {:?}", current_dir))?;
+    candidates.into_iter().find(|p| p.exists())
+        .ok_or(format!("Dossier 'wiki' introuvable. Cherché dans : {:?}", current_dir))
+}
+
+/// Ecrit le `config.yml` de Wiki.js (dossier de données du launcher) et renvoie son chemin.
+fn write_wiki_server_config(wiki_dir: &std::path::Path, wiki_port: u16, db_settings: wiki_config::WikiDbSettings) -> Result<PathBuf, String> {
+    let wiki_app_dir = wiki_app_dir()?;
+    let config_path = wiki_app_dir.join("config.yml");
+    let settings = WikiServerSettings::new(wiki_port, wiki_app_dir.join("data"), db_settings);
+    wiki_config::write_wiki_config(&config_path, Some(&wiki_dir.join("config.yml")), &settings)?;
+    Ok(config_path)
+}
+
+/// Prépare la commande `node server` : localise le dossier 'wiki' et régénère le config.yml.
+/// Rappelée par le superviseur à chaque (re)démarrage.
+fn prepare_wiki_command(app_handle: &tauri::AppHandle) -> Result<Command, String> {
+    let wiki_dir = find_wiki_dir()?;
 
+    // Générer le config.yml à partir de la config DB (le port PostgreSQL est aléatoire)
+    let state = app_handle.state::<AppState>();
+    // Appelée hors du runtime async (thread du superviseur ou `spawn_blocking`)
+    let db_settings = state.postgres_manager.blocking_lock()
+        .as_ref()
+        .map(|pm| pm.wiki_db_settings())
+        .ok_or("Base de données non initialisée (init_db doit être appelé avant)")?;
+    let wiki_port = state.wiki_port.lock().unwrap().ok_or("Port Wiki.js non attribué")?;
+    let db_pass = db_settings.pass_env_value();
+    let config_path = write_wiki_server_config(&wiki_dir, wiki_port, db_settings)
+        .map_err(|e| format!("Configuration Wiki.js invalide, démarrage annulé: {}", e))?;
+    println!("📝 config.yml généré : {:?}", config_path);
 
-    // Configurer l'environnement (notamment le port DB si besoin, mais c'est dans config.yml)
     // On lance "node server"
-    let child = Command::new("node")
-        .arg("server")
+    let mut cmd = Command::new("node");
+    cmd.arg("server")
         .current_dir(&wiki_dir)
-        .creation_flags(0x08000000) // CREATE_NO_WINDOW
-        .spawn()
-        .map_err(|e| format!("Impossible de lancer Node.js: {}", e))?;
-        
-    *procs = Some(child);
-    
+        .env("CONFIG_FILE", &config_path)
+        .env(wiki_config::DB_PASS_ENV, db_pass);
+    platform::own_process_group(&mut cmd);
+    Ok(cmd)
+}
+
+#[tauri::command]
+async fn start_wiki_server(app_handle: tauri::AppHandle) -> Result<String, String> {
+    println!("🚀 Démarrage du Serveur Wiki.js...");
+
+    // Le premier lancement est synchrone et attend le verrou du manager : hors du runtime async.
+    // `start` vérifie lui-même, de façon atomique, que Wiki.js n'est pas déjà lancé.
+    let handle = app_handle.clone();
+    let launched = tauri::async_runtime::spawn_blocking(move || {
+        let state = handle.state::<AppState>();
+        state.startup.step(StartupPhase::WikiBoot, || {
+            let port = ensure_wiki_port(&handle, &state)?;
+            println!("🌐 Wiki.js utilisera le port {}", port);
+
+            let launcher_handle = handle.clone();
+            state.wiki.start(Box::new(move || prepare_wiki_command(&launcher_handle)))
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    if !launched {
+        return Ok("Déjà lancé".to_string());
+    }
+
     println!("✅ Wiki.js démarré en tâche de fond.");
+    tauri::async_runtime::spawn(watch_wiki_boot(app_handle));
     Ok("Wiki lancé".to_string())
 }
 
+/// Attribue le port de Wiki.js pour la session et autorise cette origine à appeler l'API Tauri.
+fn ensure_wiki_port(app_handle: &tauri::AppHandle, state: &AppState) -> Result<u16, String> {
+    let mut wiki_port = state.wiki_port.lock().unwrap();
+    if let Some(port) = *wiki_port {
+        return Ok(port);
+    }
+
+    let app_dir = wikitools_app_dir()?;
+    let mut settings = LauncherSettings::load(&app_dir)?;
+    let port = settings.resolve_wiki_port()?;
+    if let Err(e) = settings.save(&app_dir) {
+        eprintln!("⚠️ Impossible d'enregistrer le port Wiki.js: {}", e);
+    }
+
+    app_handle
+        .add_capability(
+            CapabilityBuilder::new("localhost-access")
+                .remote(format!("http://localhost:{}", port))
+                .window("main")
+                .permission("core:default")
+                .permission("opener:default"),
+        )
+        .map_err(|e| format!("Impossible d'autoriser l'origine Wiki.js: {}", e))?;
+
+    *wiki_port = Some(port);
+    Ok(port)
+}
+
+#[tauri::command]
+async fn wiki_url(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    state.wiki_url().ok_or("Wiki.js n'a pas encore été démarré".to_string())
+}
+
+/// Page demandée par un lien `wikitools://` pendant le démarrage, ouverte par le launcher
+/// à la place de l'accueil du wiki.
+#[tauri::command]
+async fn take_pending_page(state: tauri::State<'_, AppState>) -> Result<Option<String>, String> {
+    let wiki_url = state.wiki_url().ok_or("Wiki.js n'a pas encore été démarré".to_string())?;
+    Ok(state.pending_page.lock().unwrap().take().map(|path| deep_link::page_url(&wiki_url, &path)))
+}
+
+#[tauri::command]
+async fn wiki_status(state: tauri::State<'_, AppState>) -> Result<WikiStatus, String> {
+    Ok(state.wiki.status())
+}
+
+#[tauri::command]
+async fn restart_wiki(state: tauri::State<'_, AppState>) -> Result<WikiStatus, String> {
+    println!("🔄 Redémarrage de Wiki.js...");
+    restart_wiki_process(&state.wiki).await?;
+    Ok(state.wiki.status())
+}
+
 #[tauri::command]
-async fn check_health() -> bool {
+async fn stop_wiki(state: tauri::State<'_, AppState>) -> Result<WikiStatus, String> {
+    println!("🛑 Arrêt de Wiki.js...");
+    stop_wiki_process(&state.wiki).await;
+    Ok(state.wiki.status())
+}
+
+/// Sauvegarde puis applique la politique de rétention.
+async fn run_backup(state: &AppState, kind: BackupKind) -> Result<BackupInfo, String> {
+    let _guard = state.backup_lock.lock().await;
+    backup_locked(state, kind, &[]).await
+}
+
+/// Comme [`run_backup`], `backup_lock` étant déjà tenu par l'appelant.
+/// Les sauvegardes de `protected` échappent à la rétention.
+async fn backup_locked(state: &AppState, kind: BackupKind, protected: &[&str]) -> Result<BackupInfo, String> {
+    let target = state.backup_target().await?;
+    let paths = backup_paths()?;
+    let info = backup::create_backup(&target, &paths, kind).await?;
+
+    let settings = LauncherSettings::load(&wikitools_app_dir()?)?;
+    match backup::apply_retention(&paths, &settings.backup, protected) {
+        Ok(removed) if !removed.is_empty() => println!("🧹 Sauvegardes supprimées (rétention) : {:?}", removed),
+        Ok(_) => {}
+        Err(e) => eprintln!("⚠️ Rétention des sauvegardes: {}", e),
+    }
+    Ok(info)
+}
+
+#[tauri::command]
+async fn backup_now(state: tauri::State<'_, AppState>) -> Result<BackupInfo, String> {
+    run_backup(&state, BackupKind::Manual).await
+}
+
+#[tauri::command]
+async fn list_backups() -> Result<Vec<BackupInfo>, String> {
+    backup::list_backups(&backup_paths()?)
+}
+
+#[tauri::command]
+async fn restore_backup(state: tauri::State<'_, AppState>, id: String) -> Result<(), String> {
+    // Un seul verrou pour la sauvegarde de sécurité et la restauration : aucune sauvegarde
+    // planifiée ne peut s'intercaler entre les deux
+    let _guard = state.backup_lock.lock().await;
+
+    // Filet de sécurité : l'état actuel est sauvegardé avant d'être écrasé
+    let safety = backup_locked(&state, BackupKind::PreRestore, &[id.as_str()]).await
+        .map_err(|e| format!("Sauvegarde de sécurité impossible, restauration annulée: {}", e))?;
+    println!("🛟 Sauvegarde de sécurité {} créée avant restauration.", safety.id);
+
+    let target = state.backup_target().await?;
+    let wiki_was_active = state.wiki.is_active();
+    stop_wiki_process(&state.wiki).await;
+
+    let result = backup::restore_backup(&target, &backup_paths()?, &id).await;
+    if wiki_was_active {
+        if let Err(e) = restart_wiki_process(&state.wiki).await {
+            eprintln!("⚠️ Redémarrage de Wiki.js après restauration impossible: {}", e);
+        }
+    }
+    result
+}
+
+/// Renouvelle les mots de passe PostgreSQL (voir `CredentialRotation::run`),
+/// puis redémarre Wiki.js pour qu'il se reconnecte avec le nouveau mot de passe.
+#[tauri::command]
+async fn rotate_credentials(state: tauri::State<'_, AppState>) -> Result<Vec<String>, String> {
+    let _guard = state.backup_lock.lock().await;
+    // Rotation sur une copie de la configuration : le gestionnaire reste dans l'état partagé
+    let rotation = state.postgres_manager.lock().await.as_ref()
+        .ok_or("Base de données non initialisée")?
+        .credential_rotation()?;
+
+    let wiki_port = *state.wiki_port.lock().unwrap();
+    let wiki_config_path = wiki_app_dir()?.join("config.yml");
+    let previous_wiki_config = std::fs::read(&wiki_config_path).ok();
+    let result = rotation.run(|db_settings| match wiki_port {
+        // Wiki.js pas encore démarré : le config.yml sera régénéré à son lancement
+        None => Ok(()),
+        Some(port) => write_wiki_server_config(&find_wiki_dir()?, port, db_settings).map(|_| ()),
+    }).await;
+    let rotated = match result {
+        Ok(rotated) => rotated,
+        Err(e) => {
+            if let Some(content) = previous_wiki_config {
+                if let Err(restore) = fsutil::write_atomic(&wiki_config_path, &content) {
+                    eprintln!("⚠️ Restauration de {:?} impossible: {}", wiki_config_path, restore);
+                }
+            }
+            return Err(e);
+        }
+    };
+
+    // Le pool d'administration ouvrirait ses prochaines connexions avec l'ancien mot de passe
+    let (roles, admin_options) = {
+        let mut pm = state.postgres_manager.lock().await;
+        let pm = pm.as_mut().ok_or("Base de données déchargée pendant la rotation")?;
+        let roles = pm.apply_rotation(rotated);
+        (roles, pm.has_admin_credentials().then(|| pm.superuser_options("postgres")))
+    };
+    let pool = match admin_options {
+        Some(options) => pg_admin::connect_pool(&options).await.ok(),
+        None => None,
+    };
+    if let Some(old_pool) = std::mem::replace(&mut *state.db_pool.lock().unwrap(), pool) {
+        tauri::async_runtime::spawn(async move { old_pool.close().await });
+    }
+    println!("🔑 Mots de passe renouvelés pour : {}", roles.join(", "));
+
+    if state.wiki.is_active() {
+        if let Err(e) = restart_wiki_process(&state.wiki).await {
+            eprintln!("⚠️ Redémarrage de Wiki.js après rotation impossible: {}", e);
+        }
+    }
+    Ok(roles)
+}
+
+#[tauri::command]
+async fn test_db_connection(settings: NetworkDbSettings) -> Result<ConnectionTest, String> {
+    postgres_manager::test_network_connection(&settings, "wiki").await
+}
+
+/// Enregistre un serveur PostgreSQL distant après l'avoir testé. `init_db` doit ensuite être rappelé.
+#[tauri::command]
+async fn set_network_db(app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>, settings: NetworkDbSettings) -> Result<ConnectionTest, String> {
+    let test = postgres_manager::test_network_connection(&settings, "wiki").await?;
+    if !test.database_exists && !settings.provision {
+        return Err("La base 'wiki' n'existe pas sur ce serveur : activez sa création automatique ou créez-la au préalable".into());
+    }
+    if test.can_provision == Some(false) && !test.database_exists {
+        return Err("Le compte d'administration n'a pas le droit de créer bases et rôles (CREATEDB et CREATEROLE requis)".into());
+    }
+
+    let (mut pm, shared) = open_postgres_manager(&app_handle)?;
+    if shared {
+        return Err("La base est gérée par CollabTools : changez de serveur depuis CollabTools".into());
+    }
+    pm.use_network_server(&settings)?;
+    stop_wiki_process(&state.wiki).await;
+    // Le cluster embarqué n'est plus utilisé
+    let previous = state.postgres_manager.lock().await.take();
+    if let Some(mut previous) = previous {
+        let _ = previous.leave().await;
+    }
+    if let Some(pool) = state.db_pool.lock().unwrap().take() {
+        tauri::async_runtime::spawn(async move { pool.close().await });
+    }
+    println!("📡 Serveur PostgreSQL distant enregistré : {}:{}", settings.host, settings.port);
+    Ok(test)
+}
+
+#[tauri::command]
+async fn upgrade_status(state: tauri::State<'_, AppState>) -> Result<Option<UpgradeRecord>, String> {
+    Ok(state.postgres_manager.lock().await.as_ref().and_then(|pm| pm.pending_upgrade()))
+}
+
+#[tauri::command]
+async fn confirm_upgrade(state: tauri::State<'_, AppState>) -> Result<UpgradeRecord, String> {
+    let pm = state.postgres_manager.lock().await;
+    let record = pm.as_ref().ok_or("Base de données non initialisée")?.confirm_upgrade().await?;
+    println!("🗑️ Migration PostgreSQL {} -> {} confirmée, ancien cluster supprimé.", record.from_version, record.to_version);
+    Ok(record)
+}
+
+/// Relance la migration PostgreSQL reportée par un retour arrière.
+#[tauri::command]
+async fn retry_upgrade(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let _guard = state.backup_lock.lock().await;
+    let mut manager = state.postgres_manager.lock().await;
+    let pm = manager.as_mut().ok_or("Base de données non initialisée")?;
+    let wiki_was_active = state.wiki.is_active();
+    stop_wiki_process(&state.wiki).await;
+
+    let result = pm.retry_upgrade().await;
+    // Relâché avant de relancer Wiki.js, dont le lanceur relit le manager
+    drop(manager);
+    if wiki_was_active {
+        if let Err(e) = restart_wiki_process(&state.wiki).await {
+            eprintln!("⚠️ Redémarrage de Wiki.js après migration impossible: {}", e);
+        }
+    }
+    result?;
+    println!("⬆️ Migration PostgreSQL relancée.");
+    Ok(())
+}
+
+#[tauri::command]
+async fn rollback_upgrade(state: tauri::State<'_, AppState>) -> Result<UpgradeRecord, String> {
+    let _guard = state.backup_lock.lock().await;
+    let mut manager = state.postgres_manager.lock().await;
+    let pm = manager.as_mut().ok_or("Base de données non initialisée")?;
+    let wiki_was_active = state.wiki.is_active();
+    stop_wiki_process(&state.wiki).await;
+
+    let result = pm.rollback_upgrade().await;
+    // Relâché avant de relancer Wiki.js, dont le lanceur relit le manager
+    drop(manager);
+    if wiki_was_active {
+        if let Err(e) = restart_wiki_process(&state.wiki).await {
+            eprintln!("⚠️ Redémarrage de Wiki.js après retour arrière impossible: {}", e);
+        }
+    }
+    let record = result?;
+    println!("↩️ Retour à PostgreSQL {} effectué.", record.from_version);
+    Ok(record)
+}
+
+#[tauri::command]
+async fn get_backup_settings() -> Result<BackupSettings, String> {
+    Ok(LauncherSettings::load(&wikitools_app_dir()?)?.backup)
+}
+
+#[tauri::command]
+async fn set_backup_settings(backup_settings: BackupSettings) -> Result<(), String> {
+    let app_dir = wikitools_app_dir()?;
+    let mut settings = LauncherSettings::load(&app_dir)?;
+    settings.backup = backup_settings;
+    settings.save(&app_dir)
+}
+
+#[tauri::command]
+async fn get_shutdown_settings() -> Result<ShutdownSettings, String> {
+    Ok(LauncherSettings::load(&wikitools_app_dir()?)?.shutdown)
+}
+
+#[tauri::command]
+async fn set_shutdown_settings(state: tauri::State<'_, AppState>, shutdown_settings: ShutdownSettings) -> Result<(), String> {
+    let app_dir = wikitools_app_dir()?;
+    let mut settings = LauncherSettings::load(&app_dir)?;
+    let grace = std::time::Duration::from_secs(shutdown_settings.wiki_grace_secs);
+    settings.shutdown = shutdown_settings;
+    settings.save(&app_dir)?;
+    state.wiki.set_stop_grace(grace);
+    Ok(())
+}
+
+/// Tâche de fond : maintient notre bail sur le cluster PostgreSQL (voir `cluster_lease`).
+async fn lease_heartbeat(app_handle: tauri::AppHandle) {
+    let mut interval = tokio::time::interval(cluster_lease::HEARTBEAT_INTERVAL);
+    loop {
+        interval.tick().await;
+        let state = app_handle.state::<AppState>();
+        // Registre copié sous le verrou du manager ; le verrou de fichier du registre est
+        // attendu hors du runtime
+        let leases = state.postgres_manager.lock().await.as_ref().and_then(|pm| pm.lease_to_refresh());
+        let Some(leases) = leases else {
+            continue;
+        };
+        let result = tauri::async_runtime::spawn_blocking(move || leases.heartbeat()).await;
+        if let Err(e) = result.map_err(|e| e.to_string()).and_then(|acquired| acquired) {
+            eprintln!("⚠️ Rafraîchissement du bail PostgreSQL impossible: {}", e);
+        }
+    }
+}
+
+/// Etat du dossier `data` du cluster local, utilisable même si `init_db` a échoué.
+#[tauri::command]
+async fn data_dir_status(app_handle: tauri::AppHandle) -> Result<Option<DataDirReport>, String> {
+    let (pm, _) = open_postgres_manager(&app_handle)?;
+    Ok(pm.data_dir_report())
+}
+
+/// Réparation d'un dossier `data` incomplet : mise en quarantaine (jamais de suppression),
+/// puis `init_db` doit être rappelé pour créer un cluster vide où restaurer une sauvegarde.
+#[tauri::command]
+async fn repair_data_dir(app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<PathBuf, String> {
+    if state.postgres_manager.lock().await.is_some() {
+        return Err("La base de données est déjà initialisée".into());
+    }
+    let (mut pm, shared) = open_postgres_manager(&app_handle)?;
+    if shared {
+        return Err("Le cluster appartient à CollabTools : réparez-le depuis CollabTools".into());
+    }
+    pm.quarantine_data_dir().await
+}
+
+/// Dernières lignes des logs du serveur PostgreSQL local, disponibles même si `init_db` a échoué.
+#[tauri::command]
+async fn get_postgres_logs(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    lines: Option<usize>,
+    level: Option<LogLevel>,
+) -> Result<LogTail, String> {
+    let files = match state.postgres_manager.lock().await.as_ref() {
+        Some(pm) => pm.postgres_log_files(),
+        None => open_postgres_manager(&app_handle).and_then(|(pm, _)| pm.postgres_log_files()),
+    }?;
+    Ok(logs::tail(files, lines, level, logs::postgres_level))
+}
+
+/// Dernières lignes de `wiki.log` et de ses rotations.
+#[tauri::command]
+async fn get_wiki_logs(state: tauri::State<'_, AppState>, lines: Option<usize>, level: Option<LogLevel>) -> Result<LogTail, String> {
+    let files = logs::rotated_files(&state.wiki.status().log_file);
+    Ok(logs::tail(files, lines, level, logs::wiki_level))
+}
+
+#[tauri::command]
+async fn cluster_consumers(state: tauri::State<'_, AppState>) -> Result<Vec<ClusterConsumer>, String> {
+    Ok(state.postgres_manager.lock().await.as_ref().map(|pm| pm.consumers()).unwrap_or_default())
+}
+
+/// Tâche de fond : lance une sauvegarde planifiée lorsque la dernière est trop ancienne.
+async fn backup_scheduler(app_handle: tauri::AppHandle) {
+    const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+
+        let state = app_handle.state::<AppState>();
+        if state.postgres_manager.lock().await.is_none() {
+            continue;
+        }
+        let (Ok(app_dir), Ok(paths)) = (wikitools_app_dir(), backup_paths()) else {
+            continue;
+        };
+        let settings = match LauncherSettings::load(&app_dir) {
+            Ok(settings) => settings.backup,
+            Err(e) => {
+                eprintln!("⚠️ Planification des sauvegardes: {}", e);
+                continue;
+            }
+        };
+        if !settings.enabled {
+            continue;
+        }
+
+        let last = backup::list_backups(&paths).ok()
+            .and_then(|backups| backups.into_iter().next())
+            .map(|b| b.created_at);
+        let interval = chrono::Duration::hours(settings.interval_hours.max(1) as i64);
+        if last.is_some_and(|last| chrono::Local::now() - last < interval) {
+            continue;
+        }
+
+        println!("⏰ Sauvegarde planifiée...");
+        if let Err(e) = run_backup(&state, BackupKind::Scheduled).await {
+            eprintln!("❌ Sauvegarde planifiée en échec: {}", e);
+        }
+    }
+}
+
+/// Attend que Wiki.js réponde, puis émet l'étape `Ready` (ou l'échec de `WikiBoot`).
+async fn watch_wiki_boot(app_handle: tauri::AppHandle) {
+    const BOOT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+    let state = app_handle.state::<AppState>();
+    let deadline = std::time::Instant::now() + BOOT_TIMEOUT;
+
+    while std::time::Instant::now() < deadline {
+        let status = state.wiki.status();
+        if matches!(status.state, WikiState::CrashLoop | WikiState::Failed | WikiState::Stopped) {
+            let error = status.last_error.unwrap_or_else(|| "Wiki.js s'est arrêté pendant le démarrage".to_string());
+            state.startup.fail(StartupPhase::WikiBoot, error);
+            return;
+        }
+        if let Some(url) = state.wiki_url() {
+            if wiki_responds(&url).await {
+                state.startup.begin(StartupPhase::Ready);
+                return;
+            }
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+    state.startup.fail(
+        StartupPhase::WikiBoot,
+        format!("Délai dépassé : Wiki.js ne répond pas après {} s", BOOT_TIMEOUT.as_secs()),
+    );
+}
+
+async fn wiki_responds(url: &str) -> bool {
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(2))
         .build()
         .unwrap_or_default();
         
-    match client.get("http://localhost:3000").send().await {
+    match client.get(url).send().await {
         Ok(res) => res.status().is_success(),
         Err(_) => false,
     }
 }
 
+/// Vérifie base, Wiki.js (HTTP et GraphQL), espace disque et processus.
+async fn collect_health(state: &AppState) -> HealthReport {
+    // Tout ce qui vient du manager est extrait sous le verrou, les vérifications se font après
+    let database = state.postgres_manager.lock().await.as_mut().map(|pm| {
+        (pm.app_options(), pm.child_status(), pm.is_network(), pm.readiness(), pm.local_data_dir().map(PathBuf::from))
+    });
+    let (database, postgres_process, data_dir) = match database {
+        None => (
+            Check::unknown("base de données non initialisée"),
+            Check::unknown("base de données non initialisée"),
+            None,
+        ),
+        Some((options, child, network, readiness, data_dir)) => {
+            let process = match child {
+                Some(Ok(())) => Check::ok("processus PostgreSQL actif"),
+                Some(Err(code)) => Check::down(format!("processus PostgreSQL arrêté ({})", code)),
+                None if network => Check::unknown("serveur distant"),
+                None => match readiness.await {
+                    Readiness::Ready => Check::ok("cluster démarré par une autre application"),
+                    other => Check::down(other.describe()),
+                },
+            };
+            (health::check_database(&options).await, process, data_dir)
+        }
+    };
+
+    let (wiki_http, wiki_graphql) = match state.wiki_url() {
+        Some(url) => (health::check_http(&url).await, health::check_graphql(&url).await),
+        None => (Check::unknown("Wiki.js non démarré"), Check::unknown("Wiki.js non démarré")),
+    };
+    let wiki = state.wiki.status();
+    let wiki_process = match wiki.state {
+        WikiState::Running => Check::ok(format!("processus Node.js actif (pid {})", wiki.pid.unwrap_or_default())),
+        WikiState::Starting | WikiState::Backoff => Check {
+            status: HealthStatus::Degraded,
+            latency_ms: None,
+            detail: Some(wiki.last_error.unwrap_or_else(|| "redémarrage en cours".to_string())),
+        },
+        WikiState::CrashLoop => Check::down(wiki.last_error.unwrap_or_else(|| "redémarrages abandonnés".to_string())),
+        WikiState::Failed => Check::down(wiki.last_error.unwrap_or_else(|| "lancement impossible".to_string())),
+        WikiState::Stopped => Check::unknown("Wiki.js arrêté"),
+    };
+
+    let disk = data_dir.or_else(|| wikitools_app_dir().ok()).and_then(|dir| health::check_disk(&dir));
+    HealthReport::new(database, wiki_http, wiki_graphql, disk, postgres_process, wiki_process)
+}
+
+/// Mémorise le bilan et émet `health-changed` si un statut a changé depuis le précédent.
+fn record_health(app_handle: &tauri::AppHandle, report: &HealthReport) {
+    let state = app_handle.state::<AppState>();
+    let previous = state.last_health.lock().unwrap().replace(report.clone());
+    if report.changed_since(previous.as_ref()) {
+        println!("🩺 Etat de santé : {:?}", report.status);
+        let _ = app_handle.emit("health-changed", report);
+        refresh_tray(app_handle, &state.wiki.status());
+    }
+}
+
+/// Met à jour la ligne d'état du menu de notification.
+fn refresh_tray(app_handle: &tauri::AppHandle, wiki: &WikiStatus) {
+    let wiki_text = match wiki.state {
+        WikiState::Running => "Wiki.js actif",
+        WikiState::Starting => "Wiki.js en démarrage",
+        WikiState::Backoff => "Wiki.js redémarre",
+        WikiState::CrashLoop | WikiState::Failed => "Wiki.js en échec",
+        WikiState::Stopped => "Wiki.js arrêté",
+    };
+    let health = app_handle.try_state::<AppState>()
+        .and_then(|state| state.last_health.lock().unwrap().as_ref().map(|report| report.status));
+    let text = match health {
+        Some(HealthStatus::Degraded) => format!("{} · service dégradé", wiki_text),
+        Some(HealthStatus::Down) => format!("{} · service indisponible", wiki_text),
+        _ => wiki_text.to_string(),
+    };
+    tray::set_status(app_handle, &text);
+}
+
+fn handle_tray_action(app_handle: &tauri::AppHandle, action: TrayAction) {
+    let app_handle = app_handle.clone();
+    match action {
+        TrayAction::OpenWiki => open_from_tray(&app_handle),
+        TrayAction::RestartWiki => {
+            println!("🔄 Redémarrage de Wiki.js (menu de notification)...");
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = restart_wiki_process(&app_handle.state::<AppState>().wiki).await {
+                    eprintln!("⚠️ Redémarrage de Wiki.js impossible: {}", e);
+                }
+            });
+        }
+        TrayAction::StopAll => {
+            tauri::async_runtime::spawn(async move { stop_services(&app_handle).await });
+        }
+        TrayAction::BackupNow => {
+            tauri::async_runtime::spawn(async move {
+                match run_backup(&app_handle.state::<AppState>(), BackupKind::Manual).await {
+                    Ok(info) => println!("💾 Sauvegarde effectuée : {}", info.id),
+                    Err(e) => eprintln!("❌ Sauvegarde impossible: {}", e),
+                }
+            });
+        }
+        TrayAction::Quit => app_handle.exit(0),
+    }
+}
+
+/// Réaffiche la fenêtre ; si les services ont été arrêtés depuis le menu, recharge le launcher
+/// qui les relance (init_db puis Wiki.js).
+fn open_from_tray(app_handle: &tauri::AppHandle) {
+    let state = app_handle.state::<AppState>();
+    if state.stopped_from_tray.swap(false, Ordering::SeqCst) {
+        if let (Some(window), Some(url)) = (app_handle.get_webview_window("main"), state.launcher_url.clone()) {
+            let _ = window.navigate(url);
+        }
+    }
+    tray::show_main_window(app_handle);
+}
+
+/// Ouvre dans la fenêtre principale la page visée par un lien `wikitools://page/<chemin>`.
+/// Si le wiki n'est pas encore affiché, la page est mémorisée pour le launcher.
+fn open_deep_link(app_handle: &tauri::AppHandle, url: &tauri::Url) {
+    let Some(path) = deep_link::page_path(url) else {
+        eprintln!("⚠️ Lien ignoré (attendu : {}://page/<chemin>) : {}", deep_link::SCHEME, url);
+        return;
+    };
+    println!("🔗 Lien reçu : {}", url);
+    let state = app_handle.state::<AppState>();
+    let wiki_shown = match (app_handle.get_webview_window("main"), state.wiki_url()) {
+        (Some(window), Some(wiki_url)) if !state.stopped_from_tray.load(Ordering::SeqCst) => {
+            let current = window.url().ok();
+            let target = tauri::Url::parse(&deep_link::page_url(&wiki_url, &path)).ok();
+            match (current, target) {
+                (Some(current), Some(target)) if current.origin() == target.origin() => window.navigate(target).is_ok(),
+                _ => false,
+            }
+        }
+        _ => false,
+    };
+    if !wiki_shown {
+        *state.pending_page.lock().unwrap() = Some(path);
+    }
+    open_from_tray(app_handle);
+}
+
+/// Arrête Wiki.js et quitte le cluster PostgreSQL ; l'application reste dans la zone de notification.
+async fn stop_services(app_handle: &tauri::AppHandle) {
+    println!("🛑 Arrêt des services (menu de notification)...");
+    let state = app_handle.state::<AppState>();
+    state.stopped_from_tray.store(true, Ordering::SeqCst);
+    if let Some(window) = app_handle.get_webview_window("main") {
+        let _ = window.hide();
+    }
+    // Arrêt demandé explicitement : le cluster suit la règle par défaut, pas la politique de fermeture
+    let settings = ShutdownSettings { postgres: settings::ShutdownPolicy::OnlyIfOwned, ..load_shutdown_settings() };
+    shutdown_services(app_handle, &settings).await;
+}
+
+/// Arrête Wiki.js hors du runtime async : l'arrêt propre peut durer tout le délai de grâce.
+async fn stop_wiki_process(wiki: &WikiSupervisor) {
+    let wiki = wiki.clone();
+    let _ = tauri::async_runtime::spawn_blocking(move || wiki.stop()).await;
+}
+
+async fn restart_wiki_process(wiki: &WikiSupervisor) -> Result<(), String> {
+    let wiki = wiki.clone();
+    tauri::async_runtime::spawn_blocking(move || wiki.restart())
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn load_shutdown_settings() -> ShutdownSettings {
+    wikitools_app_dir()
+        .and_then(|dir| LauncherSettings::load(&dir))
+        .map(|settings| settings.shutdown)
+        .unwrap_or_else(|e| {
+            eprintln!("⚠️ Réglages d'arrêt illisibles, valeurs par défaut utilisées: {}", e);
+            ShutdownSettings::default()
+        })
+}
+
+/// Séquence d'arrêt : Wiki.js (proprement, puis de force après le délai), connexions
+/// du launcher, puis le cluster PostgreSQL selon `settings.postgres`.
+async fn shutdown_services(app_handle: &tauri::AppHandle, settings: &ShutdownSettings) {
+    let state = app_handle.state::<AppState>();
+    let wiki = state.wiki.clone();
+    let grace = std::time::Duration::from_secs(settings.wiki_grace_secs);
+    let _ = tauri::async_runtime::spawn_blocking(move || wiki.shutdown(grace)).await;
+
+    let pool = state.db_pool.lock().unwrap().take();
+    if let Some(pool) = pool {
+        pool.close().await;
+    }
+    let pm = state.postgres_manager.lock().await.take();
+    if let Some(mut pm) = pm {
+        if let Err(e) = pm.leave_with(settings.postgres).await {
+            eprintln!("⚠️ Libération du cluster PostgreSQL impossible: {}", e);
+        }
+    }
+}
+
 #[tauri::command]
-async fn download_and_open(url: String) -> Result<(), String> {
-    let filename = url.split('/').last().unwrap_or("document.bin");
-    let temp_dir = std::env::temp_dir();
-    let file_path = temp_dir.join(filename);
-
-    // Gérer les URLs relatives
-    let full_url = if url.starts_with("http") { 
-        url 
-    } else if url.starts_with("/") {
-        format!("http://localhost:3000{}", url)
-    } else {
-        format!("http://localhost:3000/{}", url)
+async fn check_health(app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<HealthReport, String> {
+    let report = collect_health(&state).await;
+    record_health(&app_handle, &report);
+    Ok(report)
+}
+
+/// Surveillance en tâche de fond : l'interface est prévenue dès qu'un service se dégrade.
+async fn health_monitor(app_handle: tauri::AppHandle) {
+    let mut interval = tokio::time::interval(health::HEALTH_INTERVAL);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let report = collect_health(&app_handle.state::<AppState>()).await;
+        record_health(&app_handle, &report);
+    }
+}
+
+/// Paquet zip pour le support : configuration (mots de passe masqués), versions, ports,
+/// état de Wiki.js et logs. Ecrit dans `destination`, ou dans `<AppData>/diagnostics`.
+#[tauri::command]
+async fn export_diagnostics(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    destination: Option<PathBuf>,
+) -> Result<PathBuf, String> {
+    let app_dir = wikitools_app_dir()?;
+    // Manager en cours, ou relu depuis la configuration si init_db a échoué
+    let current = state.postgres_manager.lock().await
+        .as_ref()
+        .map(|pm| (pm.diagnostics(), pm.postgres_log_files().unwrap_or_default()));
+    let pending = match current {
+        Some(current) => Ok(current),
+        None => open_postgres_manager(&app_handle).map(|(pm, _)| (pm.diagnostics(), pm.postgres_log_files().unwrap_or_default())),
+    };
+    let (database, database_error, mut files) = match pending {
+        Ok((database, logs)) => (Some(database.await), None, logs),
+        Err(e) => (None, Some(e), Vec::new()),
     };
+    let wiki = state.wiki.status();
+    files.extend(logs::rotated_files(&wiki.log_file));
 
-    println!("📥 Téléchargement de : {}", full_url);
+    let mut ports = vec![PortUsage::check("commandes locales", command_server::PORT)];
+    if let Some(db) = database.as_ref().filter(|db| db.mode == db_config::DatabaseMode::Embedded) {
+        ports.push(PortUsage::check("postgresql", db.port));
+    }
+    if let Some(port) = *state.wiki_port.lock().unwrap() {
+        ports.push(PortUsage::check("wiki.js", port));
+    }
 
-    // Utilisation ASYNC pour ne pas bloquer/paniquer le runtime
-    let response = reqwest::get(&full_url).await.map_err(|e| e.to_string())?;
-    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
-    
-    // Écriture synchrone acceptable ici (ou utiliser tokio::fs)
-    std::fs::write(&file_path, bytes).map_err(|e| e.to_string())?;
+    let summary = DiagnosticsSummary {
+        generated_at: chrono::Local::now(),
+        os: OsDetails::current(),
+        versions: ComponentVersions {
+            app: app_handle.package_info().version.to_string(),
+            node: diagnostics::node_version().await,
+            wiki_js: find_wiki_dir().ok().and_then(|dir| diagnostics::wiki_js_version(&dir)),
+        },
+        database,
+        database_error,
+        ports,
+        wiki,
+        last_health: state.last_health.lock().unwrap().clone(),
+    };
+    let files: Vec<(String, PathBuf)> = files
+        .into_iter()
+        .filter_map(|path| Some((format!("logs/{}", path.file_name()?.to_string_lossy()), path)))
+        .collect();
+
+    let path = destination.unwrap_or_else(|| {
+        app_dir.join("diagnostics").join(format!("wikitools-diagnostics-{}.zip", chrono::Local::now().format("%Y%m%d-%H%M%S")))
+    });
+    diagnostics::write_bundle(&path, &summary, &files)?;
+    println!("🩺 Paquet de diagnostic écrit : {:?}", path);
+    Ok(path)
+}
+
+/// Télécharge un document du wiki (URL relative ou absolue) dans `<temp>/WikiTools`,
+/// en émettant `download-progress` pendant le transfert.
+async fn download_document(app_handle: &tauri::AppHandle, url: &str) -> Result<PathBuf, DownloadError> {
+    // URLs relatives résolues sur le wiki ; les autres hôtes sont refusés
+    let state = app_handle.state::<AppState>();
+    let wiki_url = state.wiki_url()
+        .ok_or_else(|| "Wiki.js n'est pas démarré, impossible de résoudre l'URL".to_string())?;
+    let full_url = command_server::resolve_target(url, &wiki_url).map_err(|e| e.to_string())?;
+    let dir = std::env::temp_dir().join("WikiTools");
+    state.downloads
+        .fetch(url, full_url, &dir, |progress| {
+            let _ = app_handle.emit("download-progress", progress);
+        })
+        .await
+}
+
+#[tauri::command]
+async fn cancel_download(state: tauri::State<'_, AppState>, id: u64) -> Result<bool, String> {
+    Ok(state.downloads.cancel(id))
+}
 
+#[tauri::command]
+async fn download_and_open(app_handle: tauri::AppHandle, url: String) -> Result<(), String> {
+    let file_path = download_document(&app_handle, &url).await.map_err(|e| e.to_string())?;
     println!("📂 Ouverture native : {:?}", file_path);
     open::that(&file_path).map_err(|e| e.to_string())?;
     Ok(())
 }
 
-fn start_local_command_server() {
-    std::thread::spawn(|| {
-        // Port inhabituel pour éviter les conflits
-        match std::net::TcpListener::bind("127.0.0.1:45678") {
-            Ok(listener) => {
-                println!("🚀 Serveur Commandes Local démarré sur :45678");
-                for stream in listener.incoming() {
-                    match stream {
-                        Ok(mut stream) => {
-                            std::thread::spawn(move || {
-                                let mut buffer = [0; 2048]; // Buffer suffisant pour URL longue
-                                use std::io::Read;
-                                use std::io::Write;
-                                
-                                if let Ok(n) = stream.read(&mut buffer) {
-                                    if n > 0 {
-                                        let request = String::from_utf8_lossy(&buffer[..n]);
-                                        // On cherche "GET /open?url="
-                                        if let Some(start_idx) = request.find("GET /open?url=") {
-                                            let rest = &request[start_idx + 14..];
-                                            if let Some(end_idx) = rest.find(" HTTP") {
-                                                let encoded_url = &rest[..end_idx];
-                                                let decoded = urlencoding::decode(encoded_url).unwrap_or_default().to_string();
-                                                println!("📡 Commande reçue : Open {}", decoded);
-                                                
-                                                // Lancer l'action via le runtime Tauri
-                                                tauri::async_runtime::spawn(async move {
-                                                    let _ = download_and_open(decoded).await;
-                                                });
-                                            }
-                                        }
-                                    }
-                                }
-
-                                // Réponse image PNG vide 1x1 base64 pour que le navigateur soit content (si utilisé en <img src>)
-                                // Ou juste 200 OK avec CORS
-                                let response = "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: 2\r\n\r\nOK";
-                                let _ = stream.write_all(response.as_bytes());
-                            });
-                        }
-                        Err(e) => eprintln!("Erreur connexion: {}", e),
-                    }
-                }
+/// Commandes du serveur local, exécutées pour le compte des pages du wiki.
+struct LocalCommands(tauri::AppHandle);
+
+impl CommandHost for LocalCommands {
+    fn guard(&self) -> &CommandGuard {
+        &self.0.state::<AppState>().inner().command_guard
+    }
+
+    fn wiki_url(&self) -> Option<String> {
+        self.0.state::<AppState>().wiki_url()
+    }
+
+    async fn execute(&self, command: LocalCommand) -> Result<serde_json::Value, CommandError> {
+        match command {
+            LocalCommand::Open { url } => {
+                println!("📡 Commande reçue : Open {}", url);
+                let path = download_document(&self.0, &url).await?;
+                println!("📂 Ouverture native : {:?}", path);
+                open::that(&path).map_err(|e| CommandError::open(path.clone(), e.to_string()))?;
+                Ok(serde_json::json!({ "downloaded": true, "opened": true, "path": path }))
+            }
+            LocalCommand::Reveal { url } => {
+                println!("📡 Commande reçue : Reveal {}", url);
+                let path = download_document(&self.0, &url).await?;
+                tauri_plugin_opener::reveal_item_in_dir(&path).map_err(|e| CommandError::open(path.clone(), e.to_string()))?;
+                Ok(serde_json::json!({ "downloaded": true, "opened": true, "path": path }))
+            }
+            LocalCommand::Cancel { id } => {
+                let cancelled = self.0.state::<AppState>().downloads.cancel(id);
+                Ok(serde_json::json!({ "cancelled": cancelled }))
             }
-            Err(e) => eprintln!("❌ Impossible de démarrer le serveur de commandes : {}", e),
+            LocalCommand::Status => {
+                let state = self.0.state::<AppState>();
+                let health = state.last_health.lock().unwrap().as_ref().map(|report| report.status);
+                Ok(serde_json::json!({ "wiki": state.wiki.status(), "health": health }))
+            }
+            LocalCommand::Version => Ok(serde_json::json!({
+                "app": self.0.package_info().version.to_string(),
+                "wiki_js": find_wiki_dir().ok().and_then(|dir| diagnostics::wiki_js_version(&dir)),
+            })),
         }
-    });
+    }
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        // En premier : une seconde instance transmet ses arguments à la première puis se termine
+        .plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
+            println!("🪟 WikiTools déjà lancé, arguments transmis : {:?}", args);
+            // Les liens `wikitools://` sont relayés au plugin deep-link (voir `on_open_url`)
+            if !deep_link::in_args(&args) {
+                open_from_tray(app);
+            }
+        }))
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_opener::init())
-        .setup(|_app| {
+        .setup(|app| {
+            let wiki = WikiSupervisor::new(wikitools_app_dir()?.join("logs"), RestartPolicy::default());
+            wiki.set_stop_grace(std::time::Duration::from_secs(load_shutdown_settings().wiki_grace_secs));
+            let handle = app.handle().clone();
+            wiki.set_listener(move |status| {
+                let _ = handle.emit("wiki-status", status);
+                refresh_tray(&handle, status);
+            });
+            let handle = app.handle().clone();
+            let startup = StartupProgress::new(move |event| {
+                let _ = handle.emit("startup-progress", event);
+            });
+            app.manage(AppState {
+                postgres_manager: tokio::sync::Mutex::new(None),
+                db_pool: Mutex::new(None),
+                wiki,
+                wiki_port: Mutex::new(None),
+                startup,
+                backup_lock: tokio::sync::Mutex::new(()),
+                last_health: Mutex::new(None),
+                launcher_url: app.get_webview_window("main").and_then(|window| window.url().ok()),
+                stopped_from_tray: AtomicBool::new(false),
+                pending_page: Mutex::new(None),
+                command_guard: CommandGuard::new(),
+                downloads: Downloads::new(),
+            });
+            tray::setup(app.handle(), handle_tray_action)?;
+
+            // Le schéma est déclaré par l'installeur ; on le réenregistre pour les installations portables
+            #[cfg(any(windows, target_os = "linux"))]
+            if let Err(e) = app.deep_link().register_all() {
+                eprintln!("⚠️ Enregistrement du schéma {}:// impossible: {}", deep_link::SCHEME, e);
+            }
+            let handle = app.handle().clone();
+            app.deep_link().on_open_url(move |event| {
+                for url in event.urls() {
+                    open_deep_link(&handle, &url);
+                }
+            });
+            // Application lancée par un lien
+            for url in app.deep_link().get_current()?.unwrap_or_default() {
+                open_deep_link(app.handle(), &url);
+            }
+            tauri::async_runtime::spawn(backup_scheduler(app.handle().clone()));
+            tauri::async_runtime::spawn(lease_heartbeat(app.handle().clone()));
+            tauri::async_runtime::spawn(health_monitor(app.handle().clone()));
+
             // Démarrer notre backend de secours
-            start_local_command_server();
+            let commands = Arc::new(LocalCommands(app.handle().clone()));
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = command_server::serve(command_server::PORT, commands).await {
+                    eprintln!("❌ Impossible de démarrer le serveur de commandes : {}", e);
+                }
+            });
             Ok(())
         })
-
-        .manage(AppState {
-            postgres_manager: Mutex::new(None),
-            wiki_process: Mutex::new(None),
-        })
         .invoke_handler(tauri::generate_handler![
             init_db, 
             start_wiki_server, 
+            wiki_url,
+            take_pending_page,
+            wiki_status,
+            restart_wiki,
+            stop_wiki,
+            backup_now,
+            list_backups,
+            restore_backup,
+            rotate_credentials,
+            cluster_consumers,
+            get_postgres_logs,
+            get_wiki_logs,
+            export_diagnostics,
+            data_dir_status,
+            repair_data_dir,
+            test_db_connection,
+            set_network_db,
+            upgrade_status,
+            confirm_upgrade,
+            rollback_upgrade,
+            retry_upgrade,
+            get_backup_settings,
+            set_backup_settings,
+            get_shutdown_settings,
+            set_shutdown_settings,
             check_health, 
-            download_and_open
+            download_and_open,
+            cancel_download
         ])
+        .on_window_event(|window, event| {
+            // Fermer la fenêtre laisse les serveurs tourner : l'application reste dans la zone de notification
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                if window.label() == "main" {
+                    api.prevent_close();
+                    let _ = window.hide();
+                    println!("🪟 Fenêtre masquée, WikiTools reste actif dans la zone de notification.");
+                }
+            }
+        })
         .on_page_load(|window, _| {
             let injection_script = r#"
                 // 1. Gestionnaire de CLICS pour Téléchargement Natif
+                function wtCommand(path, body) {
+                    return fetch('http://127.0.0.1:__COMMAND_PORT__' + path, {
+                        method: 'POST',
+                        headers: { 'Content-Type': 'application/json', 'X-WikiTools-Token': '__COMMAND_TOKEN__' },
+                        body: JSON.stringify(body)
+                    });
+                }
                 if (!window.wt_click_handler) {
                     window.wt_click_handler = true;
                     document.addEventListener('click', function(e) {
@@ -233,19 +1180,52 @@ pub fn run() {
                         var href = target.getAttribute('href');
                         if (!href) return;
                         var extensions = ['.pdf', '.docx', '.xlsx', '.pptx', '.txt', '.csv', '.rtf', '.msg', '.eml'];
-                        var ext = href.substring(href.lastIndexOf('.')).toLowerCase();
+                        var path = href.split('#')[0].split('?')[0];
+                        var ext = path.substring(path.lastIndexOf('.')).toLowerCase();
                         
                         // Détection des extensions
                         if (extensions.includes(ext)) {
                             e.preventDefault();
                             console.log("WikiTools: Appel au serveur local pour", href);
                             // Appel au serveur local (Plan G)
-                            fetch('http://127.0.0.1:45678/open?url=' + encodeURIComponent(href))
+                            wtCommand('/open', { url: href })
+                                .then(res => res.ok ? null : res.json().then(function(body) {
+                                    if (body.stage === 'cancelled') return;
+                                    var what = body.stage === 'open' ? "Le document a été téléchargé mais n'a pas pu être ouvert" : "Impossible d'ouvrir le document";
+                                    alert("WikiTools : " + what + ".\n" + body.error);
+                                }))
                                 .catch(err => console.error("Echec appel serveur local:", err));
                         }
                     });
                 }
 
+                // Progression des téléchargements (événements download-progress), avec annulation
+                if (!window.wt_download_listener && window.__TAURI__) {
+                    window.wt_download_listener = true;
+                    window.__TAURI__.event.listen('download-progress', function(event) {
+                        var p = event.payload;
+                        var toast = document.getElementById('wt-download-' + p.id);
+                        if (p.failed) {
+                            if (toast) toast.remove();
+                            return;
+                        }
+                        if (!toast) {
+                            toast = document.createElement('div');
+                            toast.id = 'wt-download-' + p.id;
+                            toast.className = 'wt-download';
+                            toast.style.cssText = 'position: fixed; right: 20px; bottom: ' + (20 + 50 * document.querySelectorAll('.wt-download').length) + 'px; z-index: 2147483646; background: #0f172a; color: white; padding: 10px 14px; border-radius: 8px; font-family: sans-serif; font-size: 14px; display: flex; gap: 12px; align-items: center; box-shadow: 0 10px 15px -3px rgba(0, 0, 0, 0.3);';
+                            toast.innerHTML = '<span></span><button style="background: #334155; color: white; border: none; border-radius: 6px; padding: 4px 10px; cursor: pointer;">Annuler</button>';
+                            toast.querySelector('button').addEventListener('click', function() {
+                                wtCommand('/cancel', { id: p.id }).catch(err => console.error("Echec appel serveur local:", err));
+                            });
+                            document.body.appendChild(toast);
+                        }
+                        var amount = p.total ? Math.floor(100 * p.received / p.total) + ' %' : (p.received / 1048576).toFixed(1) + ' Mo';
+                        toast.querySelector('span').textContent = '📥 ' + p.file_name + ' — ' + amount;
+                        if (p.done) setTimeout(function() { toast.remove(); }, 1500);
+                    });
+                }
+
                 // MODAL D'AIDE A L'INSTALLATION & CONFIGURATION
                 function checkAndInjectModal() {
                     // L'URL reste sur / lors du setup, donc on détecte le contenu de la page
@@ -289,7 +1269,7 @@ pub fn run() {
                                     <p style="font-weight: bold; margin-bottom: 10px; font-size: 1.1em;">🔧 CONFIGURATION OBLIGATOIRE :</p>
                                     <p>Dans le champ <strong>Site URL</strong>, COPIEZ CELA :</p>
                                     <div class="wt-info" style="text-align: center; display: flex; align-items: center; justify-content: center; gap: 10px;">
-                                        <span class="wt-code">http://localhost:3000</span>
+                                        <span class="wt-code">__WIKI_URL__</span>
                                         <button id="wt-copy-btn" style="background: #e2e8f0; border: none; padding: 6px 12px; border-radius: 6px; cursor: pointer; font-size: 13px; font-weight: bold; color: #475569; transition: all 0.2s;">COPIER</button>
                                     </div>
                                 </div>
@@ -306,7 +1286,7 @@ pub fn run() {
 
                         // Gestionnaire Copie
                         document.getElementById("wt-copy-btn").addEventListener('click', function(e) {
-                            navigator.clipboard.writeText('http://localhost:3000').then(function() {
+                            navigator.clipboard.writeText('__WIKI_URL__').then(function() {
                                 const btn = e.target;
                                 const originalText = btn.innerText;
                                 btn.innerText = '✅ COPIÉ !';
@@ -324,9 +1304,29 @@ pub fn run() {
                 checkAndInjectModal();
                 if (!window.wt_interval) window.wt_interval = setInterval(checkAndInjectModal, 1000);
             "#;
-            let _ = window.eval(injection_script);
+            let state = window.app_handle().state::<AppState>();
+            let Some(wiki_url) = state.wiki_url() else {
+                return;
+            };
+            // Le secret du serveur de commandes ne doit être confié qu'aux pages du wiki
+            let on_wiki = window.url().is_ok_and(|url| url.origin().ascii_serialization() == command_server::allowed_origin(&wiki_url));
+            if !on_wiki {
+                return;
+            }
+            let script = injection_script
+                .replace("__WIKI_URL__", &wiki_url)
+                .replace("__COMMAND_TOKEN__", state.command_guard.token())
+                .replace("__COMMAND_PORT__", &command_server::PORT.to_string());
+            let _ = window.eval(script);
         })
 
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app_handle, event| {
+            if let RunEvent::Exit = event {
+                println!("👋 Fermeture de l'application : arrêt des services...");
+                let settings = load_shutdown_settings();
+                tauri::async_runtime::block_on(shutdown_services(app_handle, &settings));
+            }
+        });
 }
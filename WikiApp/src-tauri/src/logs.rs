@@ -0,0 +1,254 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Nombre de lignes renvoyées par défaut, et plafond, pour l'affichage des logs.
+const DEFAULT_TAIL_LINES: usize = 200;
+const MAX_TAIL_LINES: usize = 5000;
+
+/// Fichier de log avec rotation par taille : `wiki.log`, `wiki.log.1`, ... `wiki.log.N`.
+pub struct RotatingLog {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: Option<File>,
+    written: u64,
+}
+
+impl RotatingLog {
+    pub fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Impossible de créer {:?}: {}", parent, e))?;
+        }
+        let mut log = Self { path, max_bytes, max_files, file: None, written: 0 };
+        log.reopen()?;
+        Ok(log)
+    }
+
+    /// Ajoute une ligne horodatée, en effectuant la rotation si nécessaire.
+    pub fn write_line(&mut self, line: &str) {
+        if self.written >= self.max_bytes {
+            if let Err(e) = self.rotate() {
+                eprintln!("⚠️ Rotation du log {:?} impossible: {}", self.path, e);
+            }
+        }
+        let entry = format!("{} {}\n", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), line);
+        if let Some(file) = self.file.as_mut() {
+            if file.write_all(entry.as_bytes()).is_ok() {
+                self.written += entry.len() as u64;
+            }
+        }
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> Result<(), String> {
+        self.file = None;
+        let _ = fs::remove_file(self.rotated_path(self.max_files));
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1)).map_err(|e| e.to_string())?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1)).map_err(|e| e.to_string())?;
+        self.reopen()
+    }
+
+    fn reopen(&mut self) -> Result<(), String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Impossible d'ouvrir le log {:?}: {}", self.path, e))?;
+        self.written = file.metadata().map(|m| m.len()).unwrap_or(0);
+        self.file = Some(file);
+        Ok(())
+    }
+}
+
+/// Fichiers d'un log à rotation, du plus ancien au plus récent (`wiki.log.N` ... `wiki.log`).
+pub fn rotated_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = vec![path.to_path_buf()];
+    for index in 1.. {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        let rotated = PathBuf::from(name);
+        if !rotated.exists() {
+            break;
+        }
+        files.push(rotated);
+    }
+    files.reverse();
+    files.retain(|file| file.exists());
+    files
+}
+
+/// Sévérité minimale des lignes à afficher.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, PartialOrd, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+/// Dernières lignes d'un ensemble de fichiers de log.
+#[derive(Serialize, Clone, Debug)]
+pub struct LogTail {
+    pub files: Vec<PathBuf>,
+    pub lines: Vec<String>,
+    /// Des lignes plus anciennes correspondant au filtre ont été omises.
+    pub truncated: bool,
+}
+
+/// Lit les `max_lines` dernières lignes de `files` (du plus ancien au plus récent) dont le niveau
+/// est au moins `min_level`. `level_of` renvoie `None` pour les lignes de continuation (détail,
+/// pile d'appels...), qui suivent alors le niveau de la ligne qui les précède.
+pub fn tail(
+    files: Vec<PathBuf>,
+    max_lines: Option<usize>,
+    min_level: Option<LogLevel>,
+    level_of: fn(&str) -> Option<LogLevel>,
+) -> LogTail {
+    let max_lines = max_lines.unwrap_or(DEFAULT_TAIL_LINES).clamp(1, MAX_TAIL_LINES);
+    let min_level = min_level.unwrap_or(LogLevel::Debug);
+    let mut lines = Vec::new();
+    for file in &files {
+        let Ok(content) = fs::read(file) else {
+            continue;
+        };
+        let mut current = LogLevel::Info;
+        for line in String::from_utf8_lossy(&content).lines() {
+            if let Some(level) = level_of(line) {
+                current = level;
+            }
+            if current >= min_level {
+                lines.push(line.to_string());
+            }
+        }
+    }
+    let truncated = lines.len() > max_lines;
+    let lines = lines.split_off(lines.len().saturating_sub(max_lines));
+    LogTail { files, lines, truncated }
+}
+
+/// Niveau d'une ligne du serveur PostgreSQL (`log_line_prefix = '%m [%p] '`).
+pub fn postgres_level(line: &str) -> Option<LogLevel> {
+    let (_, message) = line.split_once("] ")?;
+    match message.split(':').next()? {
+        "PANIC" | "FATAL" | "ERROR" => Some(LogLevel::Error),
+        "WARNING" => Some(LogLevel::Warning),
+        "LOG" | "INFO" | "NOTICE" => Some(LogLevel::Info),
+        level if level.starts_with("DEBUG") => Some(LogLevel::Debug),
+        // DETAIL, HINT, CONTEXT, STATEMENT... : complètent la ligne précédente
+        _ => None,
+    }
+}
+
+/// Niveau d'une ligne de `wiki.log` (`[MASTER] error: ...` côté Wiki.js).
+pub fn wiki_level(line: &str) -> Option<LogLevel> {
+    let winston = line.split("] ").skip(1).find_map(|message| {
+        let (level, _) = message.split_once(": ")?;
+        matches!(level, "error" | "warn" | "info" | "debug" | "verbose" | "silly").then_some(level)
+    });
+    match winston {
+        Some("error") => Some(LogLevel::Error),
+        Some("warn") => Some(LogLevel::Warning),
+        Some("info") => Some(LogLevel::Info),
+        Some("debug" | "verbose" | "silly") => Some(LogLevel::Debug),
+        _ if line.contains("=== ") => Some(LogLevel::Info),
+        // Lignes indentées (pile d'appels) : suite de la ligne précédente
+        _ if line.contains("[stderr] ") && !line.contains("[stderr]  ") => Some(LogLevel::Warning),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_keeps_max_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("wiki.log");
+        let mut log = RotatingLog::open(path.clone(), 10, 2).unwrap();
+        for index in 0..5 {
+            log.write_line(&format!("ligne {}", index));
+        }
+        drop(log);
+
+        let files = rotated_files(&path);
+        assert_eq!(files, vec![dir.path().join("logs/wiki.log.2"), dir.path().join("logs/wiki.log.1"), path.clone()]);
+        assert!(!dir.path().join("logs/wiki.log.3").exists());
+        assert!(fs::read_to_string(&path).unwrap().ends_with(" ligne 4\n"));
+        assert!(fs::read_to_string(dir.path().join("logs/wiki.log.2")).unwrap().ends_with(" ligne 2\n"));
+    }
+
+    #[test]
+    fn rotated_files_skips_missing_current_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("postgres.log");
+        assert!(rotated_files(&path).is_empty());
+        fs::write(dir.path().join("postgres.log.1"), "").unwrap();
+        assert_eq!(rotated_files(&path), vec![dir.path().join("postgres.log.1")]);
+    }
+
+    #[test]
+    fn tail_filters_by_level_and_keeps_continuations() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("postgres.log.1");
+        let current = dir.path().join("postgres.log");
+        fs::write(&old, "2024-01-01 10:00:00.000 CET [12] LOG:  démarrage\n").unwrap();
+        fs::write(
+            &current,
+            "2024-01-01 10:00:01.000 CET [12] ERROR:  relation absente\n\
+             2024-01-01 10:00:01.000 CET [12] STATEMENT:  SELECT 1\n\
+             2024-01-01 10:00:02.000 CET [12] LOG:  checkpoint\n\
+             2024-01-01 10:00:03.000 CET [12] WARNING:  disque presque plein\n",
+        )
+        .unwrap();
+
+        let all = tail(vec![old.clone(), current.clone()], None, None, postgres_level);
+        assert_eq!(all.lines.len(), 5);
+        assert!(!all.truncated);
+
+        let errors = tail(vec![old.clone(), current.clone()], None, Some(LogLevel::Warning), postgres_level);
+        assert_eq!(errors.lines.len(), 3);
+        assert!(errors.lines[1].contains("STATEMENT"));
+
+        let last = tail(vec![old, current], Some(2), None, postgres_level);
+        assert!(last.truncated);
+        assert!(last.lines[0].contains("checkpoint"));
+        assert!(last.lines[1].contains("WARNING"));
+    }
+
+    #[test]
+    fn postgres_levels() {
+        assert_eq!(postgres_level("2024-01-01 10:00:00.000 CET [12] FATAL:  auth"), Some(LogLevel::Error));
+        assert_eq!(postgres_level("2024-01-01 10:00:00.000 CET [12] WARNING:  x"), Some(LogLevel::Warning));
+        assert_eq!(postgres_level("2024-01-01 10:00:00.000 CET [12] NOTICE:  x"), Some(LogLevel::Info));
+        assert_eq!(postgres_level("2024-01-01 10:00:00.000 CET [12] DEBUG2:  x"), Some(LogLevel::Debug));
+        assert_eq!(postgres_level("2024-01-01 10:00:00.000 CET [12] DETAIL:  x"), None);
+        assert_eq!(postgres_level("sans préfixe"), None);
+    }
+
+    #[test]
+    fn wiki_levels() {
+        assert_eq!(wiki_level("2024-01-01 10:00:00 2024-01-01T09:00:00Z [MASTER] error: x"), Some(LogLevel::Error));
+        assert_eq!(wiki_level("2024-01-01 10:00:00 2024-01-01T09:00:00Z [JOB] warn: x"), Some(LogLevel::Warning));
+        assert_eq!(wiki_level("2024-01-01 10:00:00 2024-01-01T09:00:00Z [MASTER] info: x"), Some(LogLevel::Info));
+        assert_eq!(wiki_level("2024-01-01 10:00:00 2024-01-01T09:00:00Z [MASTER] silly: x"), Some(LogLevel::Debug));
+        assert_eq!(wiki_level("2024-01-01 10:00:00 === Wiki.js démarré (pid 42)"), Some(LogLevel::Info));
+        assert_eq!(wiki_level("2024-01-01 10:00:00 [stderr] Error: boom"), Some(LogLevel::Warning));
+        assert_eq!(wiki_level("2024-01-01 10:00:00 [stderr]     at main.js:1"), None);
+        assert_eq!(wiki_level("2024-01-01 10:00:00 texte libre"), None);
+    }
+}
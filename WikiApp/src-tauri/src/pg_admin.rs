@@ -0,0 +1,281 @@
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::postgres::{PgConnectOptions, PgConnection, PgPool, PgPoolOptions};
+use sqlx::{ConnectOptions, Connection, Executor};
+
+/// Identifiant SQL entre guillemets doubles (`wiki` -> `"wiki"`, `a"b` -> `"a""b"`).
+pub fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Littéral SQL, même règle que `quote_literal()` côté PostgreSQL :
+/// apostrophes doublées, et forme `E'...'` si la chaîne contient des antislashs.
+pub fn quote_literal(value: &str) -> String {
+    let escaped = value.replace('\'', "''");
+    if escaped.contains('\\') {
+        format!("E'{}'", escaped.replace('\\', "\\\\"))
+    } else {
+        format!("'{}'", escaped)
+    }
+}
+
+pub async fn connect(options: &PgConnectOptions) -> Result<PgConnection, String> {
+    options
+        .clone()
+        .disable_statement_logging()
+        .connect()
+        .await
+        .map_err(|e| format!("Connexion PostgreSQL impossible: {}", describe_error(&e)))
+}
+
+/// Message d'erreur compréhensible pour les échecs de connexion les plus courants.
+pub fn describe_error(error: &sqlx::Error) -> String {
+    match error {
+        sqlx::Error::Database(db) => match db.code().as_deref() {
+            Some("28P01") => format!("mot de passe refusé ({})", db.message()),
+            Some("28000") => format!("connexion refusée par pg_hba.conf ou rôle inconnu ({})", db.message()),
+            Some("3D000") => format!("base de données introuvable ({})", db.message()),
+            Some("42501") => format!("droits insuffisants ({})", db.message()),
+            Some("53300") => format!("trop de connexions ouvertes sur le serveur ({})", db.message()),
+            _ => db.message().to_string(),
+        },
+        sqlx::Error::Io(e) => format!("serveur injoignable ({})", e),
+        sqlx::Error::Tls(e) => format!("échec de la négociation TLS, vérifiez le mode SSL et le certificat ({})", e),
+        sqlx::Error::PoolTimedOut => "délai de connexion dépassé".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Version du serveur (`SHOW server_version`, ex. "16.2").
+pub async fn server_version(conn: &mut PgConnection) -> Result<String, String> {
+    sqlx::query_scalar::<_, String>("SHOW server_version")
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| format!("Lecture de la version du serveur impossible: {}", describe_error(&e)))
+}
+
+/// La connexion courante est-elle chiffrée ?
+pub async fn connection_uses_tls(conn: &mut PgConnection) -> Result<bool, String> {
+    sqlx::query_scalar::<_, bool>("SELECT ssl FROM pg_catalog.pg_stat_ssl WHERE pid = pg_backend_pid()")
+        .fetch_optional(&mut *conn)
+        .await
+        .map(|row| row.unwrap_or(false))
+        .map_err(|e| format!("Lecture de l'état TLS impossible: {}", describe_error(&e)))
+}
+
+/// Le rôle connecté peut-il créer bases et rôles (superutilisateur, ou CREATEDB + CREATEROLE) ?
+pub async fn can_create_db_and_roles(conn: &mut PgConnection) -> Result<bool, String> {
+    sqlx::query_scalar::<_, bool>(
+        "SELECT rolsuper OR (rolcreatedb AND rolcreaterole) FROM pg_catalog.pg_roles WHERE rolname = current_user",
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| format!("Lecture des droits du compte d'administration impossible: {}", describe_error(&e)))
+}
+
+/// Pool partagé par le launcher (administration, diagnostics...).
+pub async fn connect_pool(options: &PgConnectOptions) -> Result<PgPool, String> {
+    PgPoolOptions::new()
+        .max_connections(3)
+        .acquire_timeout(Duration::from_secs(10))
+        .connect_with(options.clone().disable_statement_logging())
+        .await
+        .map_err(|e| format!("Création du pool PostgreSQL impossible: {}", describe_error(&e)))
+}
+
+pub async fn role_exists(conn: &mut PgConnection, role: &str) -> Result<bool, String> {
+    sqlx::query_scalar::<_, i32>("SELECT 1 FROM pg_catalog.pg_roles WHERE rolname = $1")
+        .bind(role)
+        .fetch_optional(&mut *conn)
+        .await
+        .map(|row| row.is_some())
+        .map_err(|e| format!("Lecture des rôles impossible: {}", e))
+}
+
+pub async fn database_exists(conn: &mut PgConnection, db_name: &str) -> Result<bool, String> {
+    sqlx::query_scalar::<_, i32>("SELECT 1 FROM pg_catalog.pg_database WHERE datname = $1")
+        .bind(db_name)
+        .fetch_optional(&mut *conn)
+        .await
+        .map(|row| row.is_some())
+        .map_err(|e| format!("Lecture des bases impossible: {}", e))
+}
+
+const SCRAM_ITERATIONS: u32 = 4096;
+const SCRAM_SALT_LEN: usize = 16;
+
+/// Vérificateur SCRAM-SHA-256 calculé localement, au format de `pg_authid.rolpassword`.
+/// PostgreSQL le stocke tel quel : le mot de passe en clair n'apparaît ni dans la requête,
+/// ni dans les logs du serveur (`log_statement`, erreurs).
+fn scram_verifier(password: &str) -> String {
+    let mut salt = [0u8; SCRAM_SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    scram_verifier_with(password, &salt, SCRAM_ITERATIONS)
+}
+
+fn scram_verifier_with(password: &str, salt: &[u8], iterations: u32) -> String {
+    // Même normalisation que le serveur : SASLprep, ou le mot de passe brut s'il est refusé
+    let prepared = stringprep::saslprep(password).map(|p| p.into_owned()).unwrap_or_else(|_| password.to_string());
+    let salted = salted_password(prepared.as_bytes(), salt, iterations);
+    let client_key = hmac_sha256(&salted, b"Client Key");
+    let server_key = hmac_sha256(&salted, b"Server Key");
+    format!(
+        "SCRAM-SHA-256${}:{}${}:{}",
+        iterations,
+        BASE64.encode(salt),
+        BASE64.encode(Sha256::digest(client_key)),
+        BASE64.encode(server_key)
+    )
+}
+
+/// `Hi()` de la RFC 5802 : PBKDF2-HMAC-SHA-256 sur un seul bloc.
+fn salted_password(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(password).expect("HMAC accepte toute longueur de clé");
+    mac.update(salt);
+    mac.update(&1u32.to_be_bytes());
+    let mut block: [u8; 32] = mac.finalize().into_bytes().into();
+    let mut result = block;
+    for _ in 1..iterations {
+        block = hmac_sha256(password, &block);
+        result.iter_mut().zip(block).for_each(|(r, b)| *r ^= b);
+    }
+    result
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepte toute longueur de clé");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// Crée le rôle de connexion s'il n'existe pas, sinon réaligne son mot de passe
+/// sur celui de la configuration.
+pub async fn ensure_login_role(conn: &mut PgConnection, role: &str, password: &str) -> Result<(), String> {
+    let verb = if role_exists(conn, role).await? { "ALTER" } else { "CREATE" };
+    let sql = format!("{} ROLE {} WITH LOGIN PASSWORD {}", verb, quote_ident(role), quote_literal(&scram_verifier(password)));
+    conn.execute(sql.as_str())
+        .await
+        .map_err(|e| format!("{} ROLE {} a échoué: {}", verb, role, e))?;
+    Ok(())
+}
+
+/// Change le mot de passe d'un rôle existant. Transactionnel : sans effet si la transaction est annulée.
+pub async fn set_role_password(conn: &mut PgConnection, role: &str, password: &str) -> Result<(), String> {
+    let sql = format!("ALTER ROLE {} WITH PASSWORD {}", quote_ident(role), quote_literal(&scram_verifier(password)));
+    conn.execute(sql.as_str())
+        .await
+        .map_err(|e| format!("Changement du mot de passe de {} impossible: {}", role, describe_error(&e)))?;
+    Ok(())
+}
+
+/// Crée la base (propriétaire `owner`) si elle n'existe pas. Renvoie `true` si elle a été créée.
+pub async fn ensure_database(conn: &mut PgConnection, db_name: &str, owner: &str) -> Result<bool, String> {
+    if database_exists(conn, db_name).await? {
+        return Ok(false);
+    }
+    let sql = format!(
+        "CREATE DATABASE {} OWNER {} ENCODING 'UTF8'",
+        quote_ident(db_name),
+        quote_ident(owner)
+    );
+    conn.execute(sql.as_str())
+        .await
+        .map_err(|e| format!("CREATE DATABASE {} a échoué: {}", db_name, e))?;
+    Ok(true)
+}
+
+/// Coupe les connexions ouvertes sur `db_name` (préalable à un DROP DATABASE).
+pub async fn terminate_connections(conn: &mut PgConnection, db_name: &str) -> Result<(), String> {
+    sqlx::query("SELECT pg_terminate_backend(pid) FROM pg_catalog.pg_stat_activity WHERE datname = $1 AND pid <> pg_backend_pid()")
+        .bind(db_name)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Fermeture des connexions sur {} impossible: {}", db_name, e))?;
+    Ok(())
+}
+
+pub async fn drop_database(conn: &mut PgConnection, db_name: &str) -> Result<(), String> {
+    let sql = format!("DROP DATABASE IF EXISTS {}", quote_ident(db_name));
+    conn.execute(sql.as_str())
+        .await
+        .map_err(|e| format!("DROP DATABASE {} a échoué: {}", db_name, e))?;
+    Ok(())
+}
+
+/// Renomme une base ; aucune connexion ne doit y être ouverte.
+pub async fn rename_database(conn: &mut PgConnection, from: &str, to: &str) -> Result<(), String> {
+    let sql = format!("ALTER DATABASE {} RENAME TO {}", quote_ident(from), quote_ident(to));
+    conn.execute(sql.as_str())
+        .await
+        .map_err(|e| format!("Renommage de la base {} en {} impossible: {}", from, to, e))?;
+    Ok(())
+}
+
+/// Donne à `role` la propriété de la base et tous les droits sur le schéma `public`.
+/// `conn` doit être connectée à la base `db_name` (les droits de schéma sont locaux à une base).
+pub async fn grant_database_ownership(conn: &mut PgConnection, db_name: &str, role: &str) -> Result<(), String> {
+    let statements = [
+        format!("ALTER DATABASE {} OWNER TO {}", quote_ident(db_name), quote_ident(role)),
+        format!("GRANT ALL ON SCHEMA public TO {}", quote_ident(role)),
+    ];
+    for sql in statements {
+        conn.execute(sql.as_str())
+            .await
+            .map_err(|e| format!("Attribution des droits sur {} impossible ({}): {}", db_name, sql, e))?;
+    }
+    Ok(())
+}
+
+pub async fn close(conn: PgConnection) {
+    let _ = conn.close().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_ident_doubles_quotes_and_keeps_case() {
+        assert_eq!(quote_ident("wiki"), r#""wiki""#);
+        assert_eq!(quote_ident("WikiUser"), r#""WikiUser""#);
+        assert_eq!(quote_ident(r#"a"b"#), r#""a""b""#);
+        assert_eq!(quote_ident(r#"x"; DROP ROLE postgres; --"#), r#""x""; DROP ROLE postgres; --""#);
+        // L'antislash n'a pas de sens particulier dans un identifiant
+        assert_eq!(quote_ident(r"a\b"), r#""a\b""#);
+    }
+
+    #[test]
+    fn quote_literal_doubles_apostrophes() {
+        assert_eq!(quote_literal("secret"), "'secret'");
+        assert_eq!(quote_literal("MotDePasse"), "'MotDePasse'");
+        assert_eq!(quote_literal("l'app"), "'l''app'");
+        assert_eq!(quote_literal("'; DROP ROLE postgres; --"), "'''; DROP ROLE postgres; --'");
+    }
+
+    #[test]
+    fn quote_literal_escapes_backslashes() {
+        assert_eq!(quote_literal(r"a\b"), r"E'a\\b'");
+        assert_eq!(quote_literal(r"a\'b"), r"E'a\\''b'");
+        assert_eq!(quote_literal(r"\"), r"E'\\'");
+    }
+
+    #[test]
+    fn scram_verifier_matches_reference_vector() {
+        // Référence : hashlib.pbkdf2_hmac('sha256', b'secret', bytes(range(16)), 4096) et RFC 5802
+        let salt: Vec<u8> = (0..16).collect();
+        assert_eq!(
+            scram_verifier_with("secret", &salt, 4096),
+            "SCRAM-SHA-256$4096:AAECAwQFBgcICQoLDA0ODw==$THoPhoTAuqyoQsK4dUHncUzgfD8fdmhsgKZhWVqNP5U=:7YiHMMi2OcXGRogub03Ek06JRZ9bkhTOdCzHa5iPLiQ="
+        );
+    }
+
+    #[test]
+    fn scram_verifier_uses_a_fresh_salt() {
+        assert_ne!(scram_verifier("secret"), scram_verifier("secret"));
+    }
+}
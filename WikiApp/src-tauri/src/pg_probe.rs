@@ -0,0 +1,219 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sqlx::postgres::{PgConnectOptions, PgConnection};
+use sqlx::{ConnectOptions, Error};
+
+use crate::pg_admin;
+
+/// Délai maximal d'une tentative (connexion TCP, négociation, authentification).
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(3);
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Etat d'un serveur PostgreSQL, vu à travers une vraie connexion (startup + authentification).
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Readiness {
+    /// Rien n'écoute sur le port.
+    PortClosed,
+    /// Le port est ouvert mais le serveur n'a pas répondu dans le délai.
+    NoResponse,
+    /// Le serveur répond mais refuse encore les connexions (démarrage, récupération, arrêt).
+    StartingUp { message: String },
+    /// Le serveur répond mais refuse nos identifiants.
+    AuthFailed { message: String },
+    /// Connexion réussie, mais sur un autre cluster que celui attendu.
+    WrongCluster { data_directory: String },
+    /// Autre échec (TLS, protocole...).
+    Unreachable { message: String },
+    Ready,
+}
+
+impl Readiness {
+    /// Inutile d'attendre : le serveur ne deviendra pas disponible pour nous tout seul.
+    fn is_final(&self) -> bool {
+        matches!(self, Readiness::Ready | Readiness::AuthFailed { .. } | Readiness::WrongCluster { .. })
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Readiness::PortClosed => "aucun serveur n'écoute sur ce port".into(),
+            Readiness::NoResponse => "le serveur ne répond pas".into(),
+            Readiness::StartingUp { message } => format!("serveur en cours de démarrage ({})", message),
+            Readiness::AuthFailed { message } => format!("identifiants refusés ({})", message),
+            Readiness::WrongCluster { data_directory } => format!("un autre cluster PostgreSQL répond ({})", data_directory),
+            Readiness::Unreachable { message } => message.clone(),
+            Readiness::Ready => "serveur prêt".into(),
+        }
+    }
+}
+
+/// Une tentative de connexion avec `options`. Si `expected_data_dir` est fourni, vérifie
+/// aussi que le serveur qui répond utilise bien ce dossier `data`.
+pub async fn probe(options: &PgConnectOptions, expected_data_dir: Option<&Path>) -> Readiness {
+    let attempt = async {
+        let mut conn = match options.clone().disable_statement_logging().connect().await {
+            Ok(conn) => conn,
+            Err(e) => return classify(&e),
+        };
+        let readiness = match expected_data_dir {
+            Some(expected) => check_data_directory(&mut conn, expected).await,
+            None => Readiness::Ready,
+        };
+        pg_admin::close(conn).await;
+        readiness
+    };
+    tokio::time::timeout(ATTEMPT_TIMEOUT, attempt).await.unwrap_or(Readiness::NoResponse)
+}
+
+/// Sonde le serveur jusqu'à ce qu'il soit prêt, au plus `timeout`. S'arrête tout de suite sur
+/// un refus d'authentification ou un mauvais cluster, et dès que `exited` signale la fin du
+/// processus surveillé. Abandonner le futur (ex. `tokio::select!`) annule l'attente sans effet de bord.
+pub async fn wait_until_ready(
+    options: &PgConnectOptions,
+    expected_data_dir: Option<&Path>,
+    timeout: Duration,
+    mut exited: impl FnMut() -> Option<String>,
+) -> Result<(), String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let readiness = probe(options, expected_data_dir).await;
+        if readiness == Readiness::Ready {
+            return Ok(());
+        }
+        if readiness.is_final() {
+            return Err(format!("PostgreSQL inutilisable : {}", readiness.describe()));
+        }
+        if let Some(status) = exited() {
+            return Err(format!("PostgreSQL s'est arrêté pendant le démarrage ({})", status));
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("Le serveur n'a pas démarré à temps : {}", readiness.describe()));
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+}
+
+fn classify(error: &Error) -> Readiness {
+    match error {
+        Error::Io(e) if e.kind() == ErrorKind::ConnectionRefused => Readiness::PortClosed,
+        Error::Database(db) => match db.code().as_deref() {
+            // cannot_connect_now : démarrage, récupération après crash ou arrêt en cours
+            Some("57P03") => Readiness::StartingUp { message: db.message().to_string() },
+            Some("28P01") | Some("28000") => Readiness::AuthFailed { message: db.message().to_string() },
+            _ => Readiness::Unreachable { message: pg_admin::describe_error(error) },
+        },
+        _ => Readiness::Unreachable { message: pg_admin::describe_error(error) },
+    }
+}
+
+/// Compare le `data_directory` du serveur au dossier attendu. Sans droit de lecture
+/// du paramètre (compte non superutilisateur), le serveur est supposé être le bon.
+async fn check_data_directory(conn: &mut PgConnection, expected: &Path) -> Readiness {
+    let Ok(actual) = sqlx::query_scalar::<_, String>("SELECT current_setting('data_directory')")
+        .fetch_one(&mut *conn)
+        .await
+    else {
+        return Readiness::Ready;
+    };
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if canonical(Path::new(&actual)) == canonical(expected) {
+        Readiness::Ready
+    } else {
+        Readiness::WrongCluster { data_directory: actual }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use std::error::Error as StdError;
+    use std::fmt;
+    use sqlx::error::{DatabaseError, ErrorKind as DbErrorKind};
+
+    /// Erreur renvoyée par le serveur avec un SQLSTATE donné.
+    #[derive(Debug)]
+    struct ServerError(&'static str);
+
+    impl fmt::Display for ServerError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "erreur {}", self.0)
+        }
+    }
+
+    impl StdError for ServerError {}
+
+    impl DatabaseError for ServerError {
+        fn message(&self) -> &str {
+            "message du serveur"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.0))
+        }
+
+        fn as_error(&self) -> &(dyn StdError + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn StdError + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn StdError + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> DbErrorKind {
+            DbErrorKind::Other
+        }
+    }
+
+    fn server(code: &'static str) -> Error {
+        Error::Database(Box::new(ServerError(code)))
+    }
+
+    fn io(kind: ErrorKind) -> Error {
+        Error::Io(std::io::Error::from(kind))
+    }
+
+    #[test]
+    fn errors_map_to_readiness() {
+        let message = || "message du serveur".to_string();
+        for (error, expected) in [
+            (server("57P03"), Readiness::StartingUp { message: message() }),
+            (server("28P01"), Readiness::AuthFailed { message: message() }),
+            (server("28000"), Readiness::AuthFailed { message: message() }),
+            (io(ErrorKind::ConnectionRefused), Readiness::PortClosed),
+        ] {
+            assert_eq!(classify(&error), expected, "{:?}", error);
+        }
+        for error in [server("3D000"), io(ErrorKind::ConnectionReset), Error::PoolTimedOut] {
+            assert!(matches!(classify(&error), Readiness::Unreachable { .. }), "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn only_ready_auth_and_wrong_cluster_stop_the_wait() {
+        let message = String::new;
+        for final_state in [
+            Readiness::Ready,
+            Readiness::AuthFailed { message: message() },
+            Readiness::WrongCluster { data_directory: "/autre/data".into() },
+        ] {
+            assert!(final_state.is_final(), "{:?}", final_state);
+        }
+        for transient in [
+            Readiness::PortClosed,
+            Readiness::NoResponse,
+            Readiness::StartingUp { message: message() },
+            Readiness::Unreachable { message: message() },
+        ] {
+            assert!(!transient.is_final(), "{:?}", transient);
+        }
+    }
+}
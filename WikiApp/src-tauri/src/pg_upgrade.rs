@@ -0,0 +1,502 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use tokio::process::Command as AsyncCommand;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::fsutil::{self, copy_dir_recursive};
+use crate::pg_admin;
+use crate::platform;
+
+/// Marqueur d'une mise à niveau en attente de confirmation (dans le dossier du cluster).
+const PENDING_MARKER: &str = "upgrade_pending.json";
+/// Marqueur posé après un retour arrière : la version indiquée reste sur ses anciens binaires.
+const DEFERRED_MARKER: &str = "upgrade_deferred.json";
+/// Anciennes archives de binaires, dans le dossier du cluster : `<cluster>/versions/<majeure>/bin`.
+/// Toujours consultées, mais plus alimentées (le dossier du cluster peut être itinérant).
+const LEGACY_VERSIONS_DIR: &str = "versions";
+/// Dans `archive_dir` : dossier `bin` d'une installation système, référencé plutôt que copié.
+const SYSTEM_BIN_RECORD: &str = "system_bin_dir.txt";
+/// Copie du `pg_hba.conf` d'origine pendant une migration, restaurée même après une interruption.
+const HBA_BACKUP: &str = "pg_hba.conf.pre-upgrade";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum UpgradeMethod {
+    PgUpgrade,
+    DumpRestore,
+}
+
+/// Mise à niveau effectuée, conservée jusqu'à confirmation ou retour arrière.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UpgradeRecord {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub method: UpgradeMethod,
+    /// Ancien dossier `data`, intact, utilisé en cas de retour arrière.
+    pub rollback_dir: PathBuf,
+    /// Binaires de l'ancienne version.
+    pub old_bin_dir: PathBuf,
+    pub upgraded_at: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DeferredUpgrade {
+    version: u32,
+}
+
+/// Version majeure d'un cluster, lue dans `PG_VERSION`.
+pub fn cluster_version(data_dir: &Path) -> Result<u32, String> {
+    let raw = fs::read_to_string(data_dir.join("PG_VERSION"))
+        .map_err(|e| format!("Lecture de PG_VERSION impossible: {}", e))?;
+    parse_major(raw.trim()).ok_or_else(|| format!("PG_VERSION illisible: {:?}", raw.trim()))
+}
+
+/// Version majeure des binaires d'un dossier `bin` (`postgres --version`).
+pub async fn binaries_version(bin_dir: &Path) -> Result<u32, String> {
+    let mut cmd = Command::new(bin_dir.join(platform::exe_name("postgres")));
+    cmd.arg("--version");
+    platform::hide_console(&mut cmd);
+    let output = AsyncCommand::from(cmd)
+        .output()
+        .await
+        .map_err(|e| format!("Impossible d'exécuter postgres --version dans {:?}: {}", bin_dir, e))?;
+    // "postgres (PostgreSQL) 16.2"
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.split_whitespace()
+        .last()
+        .and_then(parse_major)
+        .ok_or_else(|| format!("Version PostgreSQL illisible: {:?}", text.trim()))
+}
+
+fn parse_major(version: &str) -> Option<u32> {
+    let digits: String = version.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Cherche des binaires de la version `version` : installés à côté des binaires embarqués
+/// (`<ressources>/postgresql-<version>/bin`), archivés ou référencés par `archive_binaries`,
+/// ou installés sur le système.
+pub async fn find_binaries(cluster_root: &Path, resources_dir: &Path, version: u32) -> Option<PathBuf> {
+    let postgres = platform::exe_name("postgres");
+    let mut candidates = vec![resources_dir.join(format!("postgresql-{}", version)).join("bin")];
+    if let Ok(archive) = archive_dir(version) {
+        candidates.push(archive.join("bin"));
+        if let Ok(recorded) = fs::read_to_string(archive.join(SYSTEM_BIN_RECORD)) {
+            candidates.push(PathBuf::from(recorded.trim()));
+        }
+    }
+    candidates.push(cluster_root.join(LEGACY_VERSIONS_DIR).join(version.to_string()).join("bin"));
+    for dir in candidates.into_iter().chain(platform::system_postgres_bin_dirs()) {
+        if dir.join(&postgres).exists() && binaries_version(&dir).await.ok() == Some(version) {
+            return Some(dir);
+        }
+    }
+    None
+}
+
+/// Emplacement des binaires archivés de la version `version`, dans les données locales du
+/// poste (binaires propres à la machine, à ne pas synchroniser avec le profil itinérant).
+pub fn archive_dir(version: u32) -> Result<PathBuf, String> {
+    Ok(platform::local_data_root()?
+        .join("com.wikitools.app")
+        .join("postgresql-versions")
+        .join(version.to_string()))
+}
+
+/// Racine d'une installation PostgreSQL propre à l'application (`<ressources>/postgresql` ou
+/// `<ressources>/postgresql-<N>`), la seule que l'on s'autorise à copier. `None` pour une
+/// installation système, dont le parent de `bin` peut être `/usr` ou `/usr/local`.
+fn owned_install_root(bin_dir: &Path, resources_dir: &Path) -> Option<PathBuf> {
+    let root = bin_dir.parent()?;
+    let name = root.file_name()?.to_str()?;
+    let dedicated = name == "postgresql" || name.strip_prefix("postgresql-").is_some_and(|v| v.parse::<u32>().is_ok());
+    (dedicated && root.starts_with(resources_dir)).then(|| root.to_path_buf())
+}
+
+/// Conserve les binaires de `version` pour pouvoir faire tourner ou migrer ce cluster après
+/// une mise à jour des binaires embarqués : les binaires embarqués sont copiés, ceux d'une
+/// installation système sont seulement référencés (ils restent gérés par le système).
+pub async fn archive_binaries(bin_dir: &Path, resources_dir: &Path, version: u32) -> Result<(), String> {
+    let dest = archive_dir(version)?;
+    if dest.join("bin").exists() {
+        return Ok(());
+    }
+    let Some(install_root) = owned_install_root(bin_dir, resources_dir) else {
+        let record = dest.join(SYSTEM_BIN_RECORD);
+        let path = bin_dir.to_string_lossy();
+        if fs::read_to_string(&record).is_ok_and(|recorded| recorded.trim() == path) {
+            return Ok(());
+        }
+        fs::create_dir_all(&dest).map_err(|e| format!("Création de {:?} impossible: {}", dest, e))?;
+        fsutil::write_atomic(&record, path.as_bytes())?;
+        println!("📌 Binaires PostgreSQL {} du système référencés : {:?}", version, bin_dir);
+        return Ok(());
+    };
+    // Plusieurs centaines de Mo : copie hors des threads du runtime async
+    tokio::task::spawn_blocking(move || {
+        let partial = dest.with_extension("partial");
+        if partial.exists() {
+            fs::remove_dir_all(&partial).map_err(|e| e.to_string())?;
+        }
+        copy_dir_recursive(&install_root, &partial)?;
+        // Une simple référence à des binaires système peut précéder la copie
+        if dest.exists() {
+            fs::remove_dir_all(&dest).map_err(|e| e.to_string())?;
+        }
+        fs::rename(&partial, &dest).map_err(|e| format!("Archivage des binaires PostgreSQL {} impossible: {}", version, e))?;
+        println!("📦 Binaires PostgreSQL {} archivés dans {:?}", version, dest);
+        Ok(())
+    })
+    .await
+    .map_err(|e| format!("Archivage des binaires PostgreSQL {} interrompu: {}", version, e))?
+}
+
+pub fn pending_upgrade(cluster_root: &Path) -> Option<UpgradeRecord> {
+    let content = fs::read_to_string(cluster_root.join(PENDING_MARKER)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+pub fn save_pending_upgrade(cluster_root: &Path, record: &UpgradeRecord) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(record).map_err(|e| e.to_string())?;
+    fsutil::write_atomic(&cluster_root.join(PENDING_MARKER), content.as_bytes())
+        .map_err(|e| format!("Ecriture du marqueur de mise à niveau impossible: {}", e))
+}
+
+/// Version restée volontairement sur ses anciens binaires après un retour arrière.
+pub fn deferred_version(cluster_root: &Path) -> Option<u32> {
+    let content = fs::read_to_string(cluster_root.join(DEFERRED_MARKER)).ok()?;
+    serde_json::from_str::<DeferredUpgrade>(&content).ok().map(|d| d.version)
+}
+
+/// Lève le report posé par un retour arrière : la migration sera de nouveau tentée.
+pub fn clear_deferred(cluster_root: &Path) -> Result<(), String> {
+    match fs::remove_file(cluster_root.join(DEFERRED_MARKER)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("Suppression du report de migration impossible: {}", e)),
+        _ => Ok(()),
+    }
+}
+
+/// Valide la mise à niveau : l'ancien dossier `data` est supprimé.
+pub fn confirm_upgrade(cluster_root: &Path) -> Result<UpgradeRecord, String> {
+    let record = pending_upgrade(cluster_root).ok_or("Aucune mise à niveau en attente de confirmation")?;
+    if record.rollback_dir.exists() {
+        fs::remove_dir_all(&record.rollback_dir)
+            .map_err(|e| format!("Suppression de {:?} impossible: {}", record.rollback_dir, e))?;
+    }
+    fs::remove_file(cluster_root.join(PENDING_MARKER)).map_err(|e| e.to_string())?;
+    Ok(record)
+}
+
+/// Remet l'ancien dossier `data` en place (le cluster doit être arrêté). Le dossier migré est
+/// conservé à côté, et la version d'origine est marquée pour ne pas être re-migrée automatiquement.
+pub fn rollback_upgrade(cluster_root: &Path, data_dir: &Path) -> Result<UpgradeRecord, String> {
+    let record = pending_upgrade(cluster_root).ok_or("Aucune mise à niveau en attente de confirmation")?;
+    if !record.rollback_dir.exists() {
+        return Err(format!("Dossier de retour arrière introuvable: {:?}", record.rollback_dir));
+    }
+    let upgraded_dir = cluster_root.join(format!(
+        "data.pg{}.rolled-back-{}",
+        record.to_version,
+        Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    fs::rename(data_dir, &upgraded_dir).map_err(|e| format!("Mise de côté du cluster migré impossible: {}", e))?;
+    if let Err(e) = fs::rename(&record.rollback_dir, data_dir) {
+        let _ = fs::rename(&upgraded_dir, data_dir);
+        return Err(format!("Restauration de l'ancien cluster impossible: {}", e));
+    }
+
+    let deferred = serde_json::to_string(&DeferredUpgrade { version: record.from_version }).map_err(|e| e.to_string())?;
+    fsutil::write_atomic(&cluster_root.join(DEFERRED_MARKER), deferred.as_bytes())?;
+    fs::remove_file(cluster_root.join(PENDING_MARKER)).map_err(|e| e.to_string())?;
+    Ok(record)
+}
+
+/// `pg_hba.conf` temporaire pour la migration : `pg_upgrade` se connecte par socket local
+/// sous Unix et en TCP sous Windows, toujours avec mot de passe, en tant que `admin_user`.
+/// Le nom est entre guillemets doubles (guillemets internes doublés, comme en SQL) ; un nom
+/// vide ou sur plusieurs lignes est refusé.
+pub fn write_upgrade_hba(data_dir: &Path, admin_user: &str) -> Result<(), String> {
+    if admin_user.is_empty() || admin_user.chars().any(char::is_control) {
+        return Err(format!("Nom de rôle invalide pour pg_hba.conf: {:?}", admin_user));
+    }
+    let user = pg_admin::quote_ident(admin_user);
+    let mut hba = format!("host all {} 127.0.0.1/32 scram-sha-256\n", user);
+    if cfg!(unix) {
+        hba.insert_str(0, &format!("local all {} scram-sha-256\n", user));
+    }
+    fsutil::write_atomic(&data_dir.join("pg_hba.conf"), hba.as_bytes())
+}
+
+/// Comme [`write_upgrade_hba`], en gardant une copie du fichier d'origine pour
+/// [`restore_hba`]. Une copie laissée par une migration interrompue n'est pas écrasée.
+pub fn replace_hba_for_upgrade(data_dir: &Path, admin_user: &str) -> Result<(), String> {
+    let backup = data_dir.join(HBA_BACKUP);
+    if !backup.exists() {
+        let original = fs::read(data_dir.join("pg_hba.conf")).map_err(|e| format!("Lecture de pg_hba.conf impossible: {}", e))?;
+        fsutil::write_atomic(&backup, &original)?;
+    }
+    write_upgrade_hba(data_dir, admin_user)
+}
+
+/// Remet en place le `pg_hba.conf` sauvegardé par [`replace_hba_for_upgrade`] ; `true` s'il y en avait un.
+pub fn restore_hba(data_dir: &Path) -> Result<bool, String> {
+    let backup = data_dir.join(HBA_BACKUP);
+    if !backup.exists() {
+        return Ok(false);
+    }
+    fs::rename(&backup, data_dir.join("pg_hba.conf"))
+        .map_err(|e| format!("Restauration de pg_hba.conf impossible: {}", e))?;
+    Ok(true)
+}
+
+/// Ports et identifiants utilisés par les serveurs temporaires de la migration.
+pub struct UpgradeContext<'a> {
+    pub old_bin: &'a Path,
+    pub new_bin: &'a Path,
+    pub old_data: &'a Path,
+    pub new_data: &'a Path,
+    pub work_dir: &'a Path,
+    pub user: &'a str,
+    pub password: &'a str,
+    pub old_port: u16,
+    pub new_port: u16,
+}
+
+impl UpgradeContext<'_> {
+    fn tool(&self, bin_dir: &Path, name: &str) -> AsyncCommand {
+        let mut cmd = Command::new(bin_dir.join(platform::exe_name(name)));
+        cmd.current_dir(self.work_dir)
+            .env("PGUSER", self.user)
+            .env("PGPASSWORD", self.password);
+        platform::hide_console(&mut cmd);
+        AsyncCommand::from(cmd)
+    }
+
+    /// Migration en place avec `pg_upgrade` (mode copie : l'ancien cluster reste intact).
+    pub async fn run_pg_upgrade(&self) -> Result<(), String> {
+        let mut cmd = self.tool(self.new_bin, "pg_upgrade");
+        cmd.arg("--old-bindir").arg(self.old_bin)
+            .arg("--new-bindir").arg(self.new_bin)
+            .arg("--old-datadir").arg(self.old_data)
+            .arg("--new-datadir").arg(self.new_data)
+            .arg("--old-port").arg(self.old_port.to_string())
+            .arg("--new-port").arg(self.new_port.to_string())
+            .arg("--username").arg(self.user);
+        run(cmd, "pg_upgrade").await
+    }
+
+    /// Solution de repli : export logique de l'ancien cluster (rôles, puis chaque base)
+    /// et import dans le nouveau, les deux serveurs tournant sur des ports temporaires.
+    pub async fn run_dump_restore(&self) -> Result<(), String> {
+        self.pg_ctl(self.old_bin, self.old_data, self.old_port, "start").await?;
+        if let Err(e) = self.pg_ctl(self.new_bin, self.new_data, self.new_port, "start").await {
+            let _ = self.pg_ctl(self.old_bin, self.old_data, self.old_port, "stop").await;
+            return Err(e);
+        }
+        let result = self.transfer().await;
+        let _ = self.pg_ctl(self.new_bin, self.new_data, self.new_port, "stop").await;
+        let _ = self.pg_ctl(self.old_bin, self.old_data, self.old_port, "stop").await;
+        result
+    }
+
+    async fn transfer(&self) -> Result<(), String> {
+        let globals = self.work_dir.join("globals.sql");
+        let mut cmd = self.tool(self.new_bin, "pg_dumpall");
+        cmd.arg("-h").arg("127.0.0.1").arg("-p").arg(self.old_port.to_string())
+            .arg("--globals-only").arg("--file").arg(&globals);
+        run(cmd, "pg_dumpall").await?;
+
+        // Le rôle d'administration existe déjà dans le nouveau cluster : erreurs tolérées sur les globaux
+        let mut cmd = self.tool(self.new_bin, "psql");
+        cmd.arg("-h").arg("127.0.0.1").arg("-p").arg(self.new_port.to_string())
+            .arg("-X").arg("-q").arg("-d").arg("postgres").arg("-f").arg(&globals);
+        run(cmd, "psql (rôles)").await?;
+
+        let mut cmd = self.tool(self.new_bin, "psql");
+        cmd.arg("-h").arg("127.0.0.1").arg("-p").arg(self.old_port.to_string())
+            .arg("-X").arg("-At").arg("-d").arg("postgres")
+            .arg("-c").arg("SELECT datname FROM pg_database WHERE NOT datistemplate AND datname <> 'postgres'");
+        let output = cmd.output().await.map_err(|e| format!("Lancement de psql impossible: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("Liste des bases impossible: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+
+        for db_name in String::from_utf8_lossy(&output.stdout).lines().filter(|l| !l.is_empty()) {
+            println!("🔁 Migration de la base '{}'...", db_name);
+            let dump = self.work_dir.join(format!("{}.dump", db_name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")));
+            let mut cmd = self.tool(self.new_bin, "pg_dump");
+            cmd.arg("-h").arg("127.0.0.1").arg("-p").arg(self.old_port.to_string())
+                .arg("--format=custom").arg("--file").arg(&dump).arg("--dbname").arg(db_name);
+            run(cmd, "pg_dump").await?;
+
+            let mut cmd = self.tool(self.new_bin, "pg_restore");
+            cmd.arg("-h").arg("127.0.0.1").arg("-p").arg(self.new_port.to_string())
+                .arg("--create").arg("--exit-on-error").arg("--dbname").arg("postgres").arg(&dump);
+            run(cmd, "pg_restore").await?;
+        }
+        Ok(())
+    }
+
+    async fn pg_ctl(&self, bin_dir: &Path, data_dir: &Path, port: u16, action: &str) -> Result<(), String> {
+        let mut cmd = self.tool(bin_dir, "pg_ctl");
+        cmd.arg(action).arg("-D").arg(data_dir).arg("-w");
+        if action == "start" {
+            cmd.arg("-o").arg(format!("-p {} -c listen_addresses=127.0.0.1", port))
+                .arg("-l").arg(self.work_dir.join(format!("postgres-{}.log", port)));
+        } else {
+            cmd.arg("-m").arg("fast");
+        }
+        run(cmd, &format!("pg_ctl {}", action)).await
+    }
+}
+
+async fn run(mut cmd: AsyncCommand, name: &str) -> Result<(), String> {
+    let output = cmd.output().await.map_err(|e| format!("Lancement de {} impossible: {}", name, e))?;
+    if !output.status.success() {
+        let mut details = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        if details.is_empty() {
+            details = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        }
+        return Err(format!("{} a échoué: {}", name, details));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cluster factice : `<root>/<name>/PG_VERSION`.
+    fn cluster(root: &Path, name: &str, version: u32) -> PathBuf {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("PG_VERSION"), format!("{}\n", version)).unwrap();
+        dir
+    }
+
+    fn record(root: &Path) -> UpgradeRecord {
+        UpgradeRecord {
+            from_version: 15,
+            to_version: 16,
+            method: UpgradeMethod::PgUpgrade,
+            rollback_dir: root.join("data.pg15"),
+            old_bin_dir: root.join("bin15"),
+            upgraded_at: Local::now(),
+        }
+    }
+
+    #[test]
+    fn reads_the_major_version() {
+        assert_eq!(parse_major("16.2"), Some(16));
+        assert_eq!(parse_major("9.6.24"), Some(9));
+        assert_eq!(parse_major("17beta1"), Some(17));
+        assert_eq!(parse_major("devel"), None);
+
+        let root = tempfile::tempdir().unwrap();
+        assert_eq!(cluster_version(&cluster(root.path(), "data", 15)), Ok(15));
+        fs::write(root.path().join("data").join("PG_VERSION"), "?").unwrap();
+        assert!(cluster_version(&root.path().join("data")).is_err());
+        assert!(cluster_version(&root.path().join("absent")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn reads_the_binaries_version() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let bin = tempfile::tempdir().unwrap();
+        let postgres = bin.path().join("postgres");
+        fs::write(&postgres, "#!/bin/sh\necho 'postgres (PostgreSQL) 16.2'\n").unwrap();
+        fs::set_permissions(&postgres, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(binaries_version(bin.path()).await, Ok(16));
+        assert!(binaries_version(&bin.path().join("absent")).await.is_err());
+    }
+
+    #[test]
+    fn rollback_restores_the_old_cluster_and_defers_the_upgrade() {
+        let root = tempfile::tempdir().unwrap();
+        let data_dir = cluster(root.path(), "data", 16);
+        cluster(root.path(), "data.pg15", 15);
+        save_pending_upgrade(root.path(), &record(root.path())).unwrap();
+
+        let rolled_back = rollback_upgrade(root.path(), &data_dir).unwrap();
+        assert_eq!(rolled_back.from_version, 15);
+        assert_eq!(cluster_version(&data_dir), Ok(15));
+        assert!(!root.path().join("data.pg15").exists());
+        // Le cluster migré est conservé à côté
+        let kept: Vec<PathBuf> = fs::read_dir(root.path()).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name().unwrap().to_string_lossy().starts_with("data.pg16.rolled-back-"))
+            .collect();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(cluster_version(&kept[0]), Ok(16));
+
+        assert!(pending_upgrade(root.path()).is_none());
+        assert_eq!(deferred_version(root.path()), Some(15));
+        clear_deferred(root.path()).unwrap();
+        assert_eq!(deferred_version(root.path()), None);
+        clear_deferred(root.path()).unwrap();
+    }
+
+    #[test]
+    fn rollback_without_the_old_cluster_changes_nothing() {
+        let root = tempfile::tempdir().unwrap();
+        let data_dir = cluster(root.path(), "data", 16);
+        assert!(rollback_upgrade(root.path(), &data_dir).is_err());
+
+        save_pending_upgrade(root.path(), &record(root.path())).unwrap();
+        assert!(rollback_upgrade(root.path(), &data_dir).is_err());
+        assert_eq!(cluster_version(&data_dir), Ok(16));
+        assert!(pending_upgrade(root.path()).is_some());
+        assert_eq!(deferred_version(root.path()), None);
+    }
+
+    #[test]
+    fn confirm_removes_the_old_cluster_and_the_marker() {
+        let root = tempfile::tempdir().unwrap();
+        let data_dir = cluster(root.path(), "data", 16);
+        cluster(root.path(), "data.pg15", 15);
+        save_pending_upgrade(root.path(), &record(root.path())).unwrap();
+
+        assert_eq!(confirm_upgrade(root.path()).unwrap().to_version, 16);
+        assert!(!root.path().join("data.pg15").exists());
+        assert!(pending_upgrade(root.path()).is_none());
+        assert_eq!(cluster_version(&data_dir), Ok(16));
+        assert!(confirm_upgrade(root.path()).is_err());
+    }
+
+    #[test]
+    fn upgrade_hba_quotes_the_role_name() {
+        let data_dir = tempfile::tempdir().unwrap();
+        write_upgrade_hba(data_dir.path(), "dba\"x").unwrap();
+        let hba = fs::read_to_string(data_dir.path().join("pg_hba.conf")).unwrap();
+        assert!(hba.lines().all(|line| line.contains(" all \"dba\"\"x\" ")), "{}", hba);
+
+        for invalid in ["", "dba\nhost all all 0.0.0.0/0 trust"] {
+            assert!(write_upgrade_hba(data_dir.path(), invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn only_bundled_installs_are_copied() {
+        let resources = Path::new("/opt/wikitools/resources");
+        assert_eq!(
+            owned_install_root(&resources.join("postgresql").join("bin"), resources),
+            Some(resources.join("postgresql"))
+        );
+        assert_eq!(
+            owned_install_root(&resources.join("postgresql-15").join("bin"), resources),
+            Some(resources.join("postgresql-15"))
+        );
+        for system in ["/usr/bin", "/usr/local/bin", "/usr/lib/postgresql/15/bin", "/usr/pgsql-15/bin"] {
+            assert_eq!(owned_install_root(Path::new(system), resources), None, "{}", system);
+        }
+        // Dossier des ressources lui-même, ou nom seulement préfixé : rien n'est copié
+        assert_eq!(owned_install_root(&resources.join("bin"), resources), None);
+        assert_eq!(owned_install_root(&resources.join("postgresql-extra").join("bin"), resources), None);
+    }
+}
@@ -0,0 +1,205 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+
+/// CREATE_NO_WINDOW : évite l'ouverture d'une console pour chaque processus enfant.
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+/// CREATE_NEW_PROCESS_GROUP : le processus devient chef de son groupe et peut recevoir CTRL_BREAK.
+#[cfg(windows)]
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x00000200;
+
+/// Nom d'exécutable propre à la plateforme (`initdb` -> `initdb.exe` sous Windows).
+pub fn exe_name(name: &str) -> String {
+    if cfg!(windows) {
+        format!("{}.exe", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Applique les flags de création propres à la plateforme (pas de console sous Windows).
+pub fn hide_console(cmd: &mut Command) -> &mut Command {
+    #[cfg(windows)]
+    cmd.creation_flags(CREATE_NO_WINDOW);
+    cmd
+}
+
+/// Comme [`hide_console`], en plaçant en plus le processus dans son propre groupe sous
+/// Windows, pour pouvoir lui demander de s'arrêter avec [`request_termination`].
+pub fn own_process_group(cmd: &mut Command) -> &mut Command {
+    #[cfg(windows)]
+    cmd.creation_flags(CREATE_NO_WINDOW | CREATE_NEW_PROCESS_GROUP);
+    cmd
+}
+
+/// Demande poliment l'arrêt du processus `pid` : SIGTERM sous Unix, CTRL_BREAK sous Windows
+/// (le processus doit avoir été lancé avec [`own_process_group`]). `false` si la demande
+/// n'a pas pu être transmise.
+pub fn request_termination(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        let Ok(pid) = libc::pid_t::try_from(pid) else {
+            return false;
+        };
+        // SAFETY: kill() n'accède à aucune mémoire ; un pid invalide renvoie simplement -1
+        unsafe { libc::kill(pid, libc::SIGTERM) == 0 }
+    }
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::System::Console::{AttachConsole, FreeConsole, GenerateConsoleCtrlEvent, CTRL_BREAK_EVENT};
+        // Une application fenêtrée n'a pas de console : on s'attache le temps d'envoyer
+        // l'événement à celle (masquée) du processus, dont il est le chef de groupe.
+        // SAFETY: appels Win32 sans pointeur ; la console est libérée dans tous les cas
+        unsafe {
+            if AttachConsole(pid) == 0 {
+                return false;
+            }
+            let sent = GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pid) != 0;
+            FreeConsole();
+            sent
+        }
+    }
+}
+
+/// Valeur de `dynamic_shared_memory_type` supportée par l'OS courant.
+pub fn shared_memory_type() -> &'static str {
+    if cfg!(windows) {
+        "windows"
+    } else {
+        "posix"
+    }
+}
+
+/// Dossier racine des données applicatives, partagé avec CollabTools.
+///
+/// - Windows : `%APPDATA%` (Roaming)
+/// - macOS : `~/Library/Application Support`
+/// - Linux : `$XDG_DATA_HOME` ou `~/.local/share`
+pub fn app_data_root() -> Result<PathBuf, String> {
+    #[cfg(windows)]
+    {
+        std::env::var("APPDATA")
+            .map(PathBuf::from)
+            .map_err(|_| "Impossible de trouver AppData".to_string())
+    }
+    #[cfg(target_os = "macos")]
+    {
+        home_dir().map(|home| home.join("Library").join("Application Support"))
+    }
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        match std::env::var("XDG_DATA_HOME") {
+            Ok(xdg) if Path::new(&xdg).is_absolute() => Ok(PathBuf::from(xdg)),
+            _ => home_dir().map(|home| home.join(".local").join("share")),
+        }
+    }
+}
+
+/// Dossier de données propre à la machine (non synchronisé par le profil itinérant Windows).
+pub fn local_data_root() -> Result<PathBuf, String> {
+    #[cfg(windows)]
+    {
+        std::env::var("LOCALAPPDATA")
+            .map(PathBuf::from)
+            .map_err(|_| "Impossible de trouver LocalAppData".to_string())
+    }
+    #[cfg(not(windows))]
+    {
+        app_data_root()
+    }
+}
+
+/// Dossier d'état propre à la machine, distinct de [`app_data_root`] sous Linux : pour ce qui
+/// ne doit pas être copié avec les données (clé de chiffrement des secrets).
+///
+/// - Windows : `%LOCALAPPDATA%`
+/// - macOS : `~/Library/Application Support` (pas d'équivalent distinct)
+/// - Linux : `$XDG_STATE_HOME` ou `~/.local/state`
+pub fn local_state_root() -> Result<PathBuf, String> {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        match std::env::var("XDG_STATE_HOME") {
+            Ok(xdg) if Path::new(&xdg).is_absolute() => Ok(PathBuf::from(xdg)),
+            _ => home_dir().map(|home| home.join(".local").join("state")),
+        }
+    }
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        local_data_root()
+    }
+}
+
+#[cfg(unix)]
+fn home_dir() -> Result<PathBuf, String> {
+    std::env::var("HOME")
+        .map(PathBuf::from)
+        .map_err(|_| "Impossible de trouver le dossier personnel ($HOME)".to_string())
+}
+
+/// Supprime le préfixe `\\?\` ajouté par Windows aux chemins canoniques, que Postgres n'accepte pas.
+pub fn clean_path(path: &Path) -> PathBuf {
+    PathBuf::from(path.to_string_lossy().replace("\\\\?\\", ""))
+}
+
+/// Résout le dossier `bin` de PostgreSQL.
+///
+/// Ordre de recherche : variable `WIKITOOLS_PG_BIN`, binaires embarqués dans les
+/// ressources, puis (hors Windows) les installations système usuelles.
+pub fn resolve_postgres_bin_dir(resources_dir: &Path) -> PathBuf {
+    let postgres = exe_name("postgres");
+
+    if let Ok(custom) = std::env::var("WIKITOOLS_PG_BIN") {
+        let custom = PathBuf::from(custom);
+        if custom.join(&postgres).exists() {
+            return custom;
+        }
+    }
+
+    let bundled = clean_path(&resources_dir.join("postgresql").join("bin"));
+    if bundled.join(&postgres).exists() {
+        return bundled;
+    }
+
+    system_postgres_bin_dirs()
+        .into_iter()
+        .find(|dir| dir.join(&postgres).exists())
+        .unwrap_or(bundled)
+}
+
+/// Emplacements système connus, du plus récent au plus ancien.
+pub fn system_postgres_bin_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if cfg!(windows) {
+        return dirs;
+    }
+
+    // Debian / Ubuntu : /usr/lib/postgresql/<version>/bin
+    // RHEL / Fedora (PGDG) : /usr/pgsql-<version>/bin
+    // Homebrew : /opt/homebrew/opt/postgresql@<version>/bin
+    for (parent, prefix, suffix) in [
+        ("/usr/lib/postgresql", "", "bin"),
+        ("/usr", "pgsql-", "bin"),
+        ("/opt/homebrew/opt", "postgresql@", "bin"),
+        ("/usr/local/opt", "postgresql@", "bin"),
+    ] {
+        let mut versioned: Vec<(u32, PathBuf)> = std::fs::read_dir(parent)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let version = name.strip_prefix(prefix)?.split('.').next()?.parse().ok()?;
+                Some((version, entry.path().join(suffix)))
+            })
+            .collect();
+        versioned.sort_by_key(|(version, _)| std::cmp::Reverse(*version));
+        dirs.extend(versioned.into_iter().map(|(_, path)| path));
+    }
+
+    dirs.push(PathBuf::from("/Applications/Postgres.app/Contents/Versions/latest/bin"));
+    dirs.push(PathBuf::from("/usr/local/bin"));
+    dirs.push(PathBuf::from("/usr/bin"));
+    dirs
+}
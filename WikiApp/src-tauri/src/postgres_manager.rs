@@ -1,140 +1,423 @@
 use std::process::{Command, Stdio};
-#[cfg(windows)]
-use std::os::windows::process::CommandExt;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
+use std::future::Future;
 use std::time::Duration;
-use std::thread;
 use rand::Rng;
 use rand::distributions::Alphanumeric;
 use serde::{Serialize, Deserialize};
-use serde_json;
 
-const APP_USER: &str = "app_user";
+use sqlx::postgres::PgConnectOptions;
+use sqlx::Connection;
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-pub enum DatabaseMode {
-    Embedded,
-    Network,
+use crate::cluster_lease::{self, Lease, LeaseRegistry};
+use crate::cluster_recovery::{self, DataDirState};
+use crate::db_config::{self, DatabaseMode, DbConfig, SslMode};
+use crate::fsutil::{self, FileLock};
+use crate::pg_admin;
+use crate::pg_probe::{self, Readiness};
+use crate::pg_upgrade::{self, UpgradeContext, UpgradeMethod, UpgradeRecord};
+use crate::platform;
+use crate::settings::{self, ShutdownPolicy};
+use crate::startup::{StartupPhase, StartupProgress};
+use crate::wiki_config::WikiDbSettings;
+
+/// Serveur PostgreSQL distant saisi depuis l'interface (mode réseau).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NetworkDbSettings {
+    pub host: String,
+    pub port: u16,
+    #[serde(default)]
+    pub ssl_mode: SslMode,
+    #[serde(default)]
+    pub ca_file: Option<PathBuf>,
+    #[serde(default = "db_config::default_app_user")]
+    pub app_user: String,
+    pub app_password: String,
+    #[serde(default)]
+    pub admin_user: Option<String>,
+    #[serde(default)]
+    pub admin_password: Option<String>,
+    #[serde(default)]
+    pub provision: bool,
 }
 
-impl Default for DatabaseMode {
-    fn default() -> Self {
-        DatabaseMode::Embedded
+impl NetworkDbSettings {
+    fn validate(&self) -> Result<(), String> {
+        if self.host.trim().is_empty() {
+            return Err("Hôte PostgreSQL manquant".into());
+        }
+        if self.port == 0 {
+            return Err("Port PostgreSQL invalide".into());
+        }
+        if self.app_user.trim().is_empty() || self.app_password.is_empty() {
+            return Err("Identifiants de l'utilisateur applicatif manquants".into());
+        }
+        if let Some(ca_file) = &self.ca_file {
+            if !ca_file.is_file() {
+                return Err(format!("Certificat d'autorité introuvable: {:?}", ca_file));
+            }
+        }
+        if self.provision && self.admin_password.as_deref().unwrap_or("").is_empty() {
+            return Err("La création automatique de la base nécessite un compte d'administration".into());
+        }
+        Ok(())
+    }
+
+    fn to_config(&self) -> DbConfig {
+        DbConfig {
+            version: db_config::CONFIG_VERSION,
+            mode: DatabaseMode::Network,
+            host: self.host.trim().to_string(),
+            port: self.port,
+            admin_user: self.admin_user.clone().filter(|u| !u.is_empty()).unwrap_or_else(db_config::default_admin_user),
+            postgres_password: self.admin_password.clone().unwrap_or_default(),
+            app_user: self.app_user.trim().to_string(),
+            app_password: self.app_password.clone(),
+            credentials: None,
+            ssl_mode: self.ssl_mode,
+            ca_file: self.ca_file.clone(),
+            provision: self.provision,
+            extra: Default::default(),
+        }
     }
 }
 
-fn default_host() -> String {
-    "127.0.0.1".to_string()
+/// Résultat de `test_network_connection`.
+#[derive(Serialize, Clone, Debug)]
+pub struct ConnectionTest {
+    pub latency_ms: u64,
+    pub server_version: String,
+    pub tls: bool,
+    /// `false` : la base n'existe pas encore (elle sera créée si `provision` est actif).
+    pub database_exists: bool,
+    /// Le compte d'administration peut créer bases et rôles (`None` : pas de compte fourni).
+    pub can_provision: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct DbConfig {
-    #[serde(default)]
-    mode: DatabaseMode,
-    #[serde(default = "default_host")]
+fn wiki_db_settings(config: &DbConfig, db_name: &str) -> WikiDbSettings {
+    let network = config.mode == DatabaseMode::Network;
+    WikiDbSettings {
+        host: config.host.clone(),
+        port: config.port,
+        user: config.app_user.clone(),
+        pass: config.app_password.clone(),
+        db: db_name.to_string(),
+        ssl: network && config.ssl_mode != SslMode::Disable,
+        ssl_verify: network && matches!(config.ssl_mode, SslMode::VerifyCa | SslMode::VerifyFull),
+        ca_file: config.ca_file.clone().filter(|_| network),
+    }
+}
+
+/// Renouvellement des mots de passe, préparé par [`PostgresManager::credential_rotation`].
+pub struct CredentialRotation {
+    config: DbConfig,
+    config_file_path: PathBuf,
+    db_name: String,
+}
+
+/// Résultat d'une rotation réussie, à adopter avec [`PostgresManager::apply_rotation`].
+pub struct RotatedCredentials {
+    config: DbConfig,
+    roles: Vec<String>,
+}
+
+impl CredentialRotation {
+    /// Renouvelle les mots de passe de l'utilisateur applicatif et, en mode embarqué, du
+    /// superutilisateur `postgres` (le compte d'administration d'un serveur distant n'est pas touché).
+    ///
+    /// Les `ALTER ROLE` sont faits dans une transaction qui n'est validée qu'une fois
+    /// `db_config.json` et `write_wiki_config` (config.yml de Wiki.js) écrits : au moindre échec,
+    /// la transaction est annulée et `db_config.json` remis dans son état d'origine. Les sessions
+    /// ouvertes restent valides.
+    pub async fn run(
+        mut self,
+        write_wiki_config: impl FnOnce(WikiDbSettings) -> Result<(), String>,
+    ) -> Result<RotatedCredentials, String> {
+        let rotate_admin = self.config.mode != DatabaseMode::Network;
+        let previous = (self.config.postgres_password.clone(), self.config.app_password.clone());
+        let new_admin_password = if rotate_admin { PostgresManager::generate_strong_password() } else { previous.0.clone() };
+        let new_app_password = PostgresManager::generate_strong_password();
+
+        let mut roles = vec![self.config.app_user.clone()];
+        if rotate_admin {
+            roles.push(self.config.admin_user.clone());
+        }
+
+        let mut conn = pg_admin::connect(&self.config.admin_options("postgres")).await?;
+        let result = async {
+            let mut tx = conn.begin().await.map_err(|e| format!("Ouverture de transaction impossible: {}", e))?;
+            pg_admin::set_role_password(&mut tx, &self.config.app_user, &new_app_password).await?;
+            if rotate_admin {
+                pg_admin::set_role_password(&mut tx, &self.config.admin_user, &new_admin_password).await?;
+            }
+
+            self.config.postgres_password = new_admin_password;
+            self.config.app_password = new_app_password;
+            let persisted = self.config.save(&self.config_file_path, Some(self.config.secret_backend()))
+                .and_then(|_| write_wiki_config(wiki_db_settings(&self.config, &self.db_name)))
+                .map_err(|e| format!("Enregistrement des nouveaux mots de passe impossible: {}", e));
+            let committed = match persisted {
+                Ok(()) => tx.commit().await.map_err(|e| format!("Validation de la transaction impossible: {}", e)),
+                Err(e) => {
+                    let _ = tx.rollback().await;
+                    Err(e)
+                }
+            };
+            if committed.is_err() {
+                (self.config.postgres_password, self.config.app_password) = previous;
+                if let Err(e) = self.config.save(&self.config_file_path, Some(self.config.secret_backend())) {
+                    eprintln!("⚠️ Restauration de {:?} impossible: {}", self.config_file_path, e);
+                }
+            }
+            committed
+        }.await;
+        pg_admin::close(conn).await;
+        result.map(|_| RotatedCredentials { config: self.config, roles })
+    }
+}
+
+/// Teste un serveur distant avant d'enregistrer la configuration : connexion de l'utilisateur
+/// applicatif à `db_name`, ou à défaut vérification des droits du compte d'administration.
+pub async fn test_network_connection(settings: &NetworkDbSettings, db_name: &str) -> Result<ConnectionTest, String> {
+    settings.validate()?;
+    let config = settings.to_config();
+
+    let started = std::time::Instant::now();
+    let (mut conn, database_exists) = match pg_admin::connect(&config.app_options(db_name)).await {
+        Ok(conn) => (conn, true),
+        Err(e) if config.has_admin_credentials() => {
+            let mut admin = pg_admin::connect(&config.admin_options("postgres")).await
+                .map_err(|admin_err| format!("{} ; compte d'administration : {}", e, admin_err))?;
+            let exists = pg_admin::database_exists(&mut admin, db_name).await?;
+            if exists {
+                pg_admin::close(admin).await;
+                return Err(e);
+            }
+            (admin, false)
+        }
+        Err(e) => return Err(e),
+    };
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    let result = async {
+        let server_version = pg_admin::server_version(&mut conn).await?;
+        let tls = pg_admin::connection_uses_tls(&mut conn).await?;
+        Ok::<_, String>((server_version, tls))
+    }.await;
+    pg_admin::close(conn).await;
+    let (server_version, tls) = result?;
+
+    let can_provision = if config.has_admin_credentials() {
+        let mut admin = pg_admin::connect(&config.admin_options("postgres")).await?;
+        let privileges = pg_admin::can_create_db_and_roles(&mut admin).await;
+        pg_admin::close(admin).await;
+        Some(privileges?)
+    } else {
+        None
+    };
+
+    Ok(ConnectionTest { latency_ms, server_version, tls, database_exists, can_provision })
+}
+
+/// Configuration et état de la base, pour le paquet de diagnostic.
+#[derive(Serialize, Clone, Debug)]
+pub struct DatabaseDiagnostics {
+    pub mode: DatabaseMode,
+    pub host: String,
+    pub port: u16,
+    pub db_name: String,
+    pub config_file: PathBuf,
+    /// Contenu de `db_config.json`, mots de passe masqués.
+    pub config: serde_json::Value,
+    pub data_dir: PathBuf,
+    pub bin_dir: PathBuf,
+    pub log_dir: PathBuf,
+    /// Sortie de `postgres --version`.
+    pub binaries_version: Option<String>,
+    /// Contenu de `PG_VERSION`.
+    pub cluster_version: Option<String>,
+    pub readiness: Readiness,
+    pub consumers: Vec<ClusterConsumer>,
+}
+
+/// Diagnostic du dossier `data` pour l'écran de réparation.
+#[derive(Serialize, Clone, Debug)]
+pub struct DataDirReport {
+    pub data_dir: PathBuf,
+    pub state: DataDirState,
+    pub quarantined: Vec<PathBuf>,
+}
+
+/// Application inscrite sur le cluster local, pour l'affichage.
+#[derive(Serialize, Clone, Debug)]
+pub struct ClusterConsumer {
+    #[serde(flatten)]
+    pub lease: Lease,
+    /// Ce processus WikiTools.
+    pub is_self: bool,
+}
+
+/// Outils clients PostgreSQL (pg_dump, pg_restore...) préconfigurés pour se connecter
+/// au cluster en superutilisateur, via les variables d'environnement libpq.
+#[derive(Clone)]
+pub struct PgTools {
+    bin_dir: PathBuf,
     host: String,
     port: u16,
-    postgres_password: String,
-    app_password: String,
+    user: String,
+    password: String,
+    ssl_mode: SslMode,
+    ca_file: Option<PathBuf>,
+}
+
+impl PgTools {
+    pub fn command(&self, tool: &str) -> Command {
+        let mut cmd = Command::new(self.bin_dir.join(platform::exe_name(tool)));
+        cmd.env("PGHOST", &self.host)
+            .env("PGPORT", self.port.to_string())
+            .env("PGUSER", &self.user)
+            .env("PGPASSWORD", &self.password)
+            .env("PGSSLMODE", self.ssl_mode.as_str());
+        if let Some(ca_file) = &self.ca_file {
+            cmd.env("PGSSLROOTCERT", ca_file);
+        }
+        platform::hide_console(&mut cmd);
+        cmd
+    }
 }
 
+/// Délai laissé à PostgreSQL pour accepter les connexions après son lancement
+/// (une récupération après arrêt brutal peut prendre du temps).
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+/// Sortie console de `postgres` : erreurs émises avant le démarrage du collecteur de logs.
+const CONSOLE_LOG: &str = "postgresql-console.log";
+const CONSOLE_LOG_MAX_BYTES: u64 = 5 * 1024 * 1024;
+
 pub struct PostgresManager {
-    child: Option<std::process::Child>,
+    child: Option<tokio::process::Child>,
     data_dir: PathBuf,
     postgres_bin_dir: PathBuf,
+    resources_dir: PathBuf,
+    /// Applications inscrites comme utilisatrices du cluster local.
+    leases: LeaseRegistry,
+    /// Vrai entre un `start` réussi et `leave` : notre bail doit alors rester inscrit.
+    joined: bool,
     config_file_path: PathBuf,
     config: DbConfig,
+    /// Mots de passe hors du fichier de configuration. Désactivé pour le fichier partagé
+    /// de CollabTools, qui doit rester lisible par CollabTools.
+    protect_secrets: bool,
     pub db_name: String,
 }
 
 impl PostgresManager {
     pub fn new(app_dir: PathBuf, resources_dir: PathBuf) -> Result<Self, String> {
-        let postgres_root = resources_dir.join("postgresql");
-        let postgres_bin_dir_raw = postgres_root.join("bin");
-        let postgres_bin_dir = PathBuf::from(postgres_bin_dir_raw.to_string_lossy().replace("\\\\?\\", ""));
+        let postgres_bin_dir = platform::resolve_postgres_bin_dir(&resources_dir);
         let data_dir = app_dir.join("postgresql").join("data");
         let config_file_path = app_dir.join("postgresql").join("db_config.json");
         
         println!("📂 DB Paths: bin={:?}, data={:?}, config={:?}", postgres_bin_dir, data_dir, config_file_path);
         
         let is_new_config = !config_file_path.exists();
-        let config = if !is_new_config {
-            let config_str = fs::read_to_string(&config_file_path)
-                .map_err(|e| format!("Echec lecture config: {}", e))?;
-            serde_json::from_str(&config_str)
-                .map_err(|e| format!("Echec parse config: {}", e))?
+        let (config, migrated_from) = if !is_new_config {
+            DbConfig::load(&config_file_path)?
         } else {
-            let new_config = DbConfig {
-                mode: DatabaseMode::Embedded,
-                host: "127.0.0.1".to_string(),
-                port: rand::thread_rng().gen_range(15000..25000),
-                postgres_password: Self::generate_strong_password(),
-                app_password: Self::generate_strong_password(),
-            };
-            // Save immediately
-            fs::create_dir_all(app_dir.join("postgresql")).ok();
-            fs::write(&config_file_path, serde_json::to_string_pretty(&new_config).unwrap()).ok();
-            new_config
+            let new_config = DbConfig::embedded(
+                rand::thread_rng().gen_range(15000..25000),
+                Self::generate_strong_password(),
+                Self::generate_strong_password(),
+            );
+            fs::create_dir_all(app_dir.join("postgresql"))
+                .map_err(|e| format!("Impossible de créer {:?}: {}", app_dir.join("postgresql"), e))?;
+            (new_config, None)
         };
         
-        Ok(Self {
+        let mut pm = Self {
             child: None,
             postgres_bin_dir,
+            resources_dir,
             data_dir,
             config_file_path,
+            leases: LeaseRegistry::new(&app_dir.join("postgresql"), cluster_lease::APP_ID),
+            joined: false,
             config,
+            protect_secrets: true,
             db_name: "wiki".to_string(),
-        })
+        };
+        if let Err(e) = pm.ensure_logging_config() {
+            eprintln!("⚠️ {}", e);
+        }
+        if is_new_config {
+            // Save immediately
+            pm.save_config()?;
+        } else if pm.config.credentials.is_none() || migrated_from.is_some() {
+            // Ancien format, ou mots de passe en clair : migration transparente
+            match pm.save_config() {
+                Ok(()) => println!("🔐 {:?} mis à jour (format {}, mots de passe dans le stockage sécurisé).", pm.config_file_path, db_config::CONFIG_VERSION),
+                Err(e) => eprintln!("⚠️ Migration de {:?} impossible, fichier laissé tel quel: {}", pm.config_file_path, e),
+            }
+        }
+        Ok(pm)
     }
 
     /// Load an existing PostgreSQL configuration (e.g., from Core)
     pub fn from_existing_config(config_path: PathBuf, resources_dir: PathBuf, db_name: &str) -> Result<Self, String> {
         println!("📖 Loading existing config from: {:?}", config_path);
         
-        let config_str = fs::read_to_string(&config_path)
-            .map_err(|e| format!("Failed to read config: {}", e))?;
-        let config: DbConfig = serde_json::from_str(&config_str)
-            .map_err(|e| format!("Failed to parse config: {}", e))?;
+        // Fichier partagé : migré en mémoire seulement, CollabTools reste maître de son format
+        // (voir `save_config`, qui le réécrit dans sa version d'origine)
+        let (config, _) = DbConfig::load(&config_path)?;
         
-        let postgres_root = resources_dir.join("postgresql");
-        let postgres_bin_dir = postgres_root.join("bin");
+        let postgres_bin_dir = platform::resolve_postgres_bin_dir(&resources_dir);
         
         // Use the data_dir from the config's parent directory
         let data_dir = config_path.parent()
             .ok_or("Invalid config path")?
             .join("data");
-        
-        // Nettoyage du chemin Windows (suppression du préfixe \\?\) pour plaire à Postgres
-        let postgres_bin_dir_str = postgres_bin_dir.to_string_lossy().replace("\\\\?\\", "");
-        let postgres_bin_dir = PathBuf::from(postgres_bin_dir_str);
 
         println!("📂 Using existing DB: bin={:?}, data={:?}, port={}", postgres_bin_dir, data_dir, config.port);
         
+        let leases = LeaseRegistry::new(config_path.parent().ok_or("Invalid config path")?, cluster_lease::APP_ID);
         Ok(Self {
             child: None,
             postgres_bin_dir,
+            resources_dir,
             data_dir,
+            leases,
+            joined: false,
             config_file_path: config_path,
             config,
+            protect_secrets: false,
             db_name: db_name.to_string(),
         })
     }
 
-    pub fn set_db_name(&mut self, name: &str) {
-        self.db_name = name.to_string();
+    /// Bascule sur un serveur distant ; le cluster local n'est plus démarré.
+    /// Prend effet au prochain `init_db`.
+    pub fn use_network_server(&mut self, settings: &NetworkDbSettings) -> Result<(), String> {
+        settings.validate()?;
+        let mut config = settings.to_config();
+        config.credentials = self.config.credentials.take();
+        config.extra = std::mem::take(&mut self.config.extra);
+        self.config = config;
+        self.save_config()
     }
-    
-    // Ajouté pour pouvoir changer le mode/host depuis l'UI
-    pub fn update_config(&mut self, mode: DatabaseMode, host: String, port: u16) -> Result<(), String> {
-        self.config.mode = mode;
-        self.config.host = host;
-        self.config.port = port;
-        fs::write(&self.config_file_path, serde_json::to_string_pretty(&self.config).unwrap())
-            .map_err(|e| e.to_string())
+
+    fn save_config(&mut self) -> Result<(), String> {
+        if self.protect_secrets {
+            self.config.save(&self.config_file_path, Some(self.config.secret_backend()))
+        } else {
+            self.config.save_shared(&self.config_file_path)
+        }
     }
     
+    /// Chemin d'un exécutable PostgreSQL, avec l'extension propre à la plateforme.
+    fn bin(&self, name: &str) -> PathBuf {
+        self.postgres_bin_dir.join(platform::exe_name(name))
+    }
+
     fn generate_strong_password() -> String {
         rand::thread_rng()
             .sample_iter(&Alphanumeric)
@@ -150,7 +433,7 @@ impl PostgresManager {
         self.data_dir.exists() && self.data_dir.join("PG_VERSION").exists()
     }
     
-    pub fn init_database(&mut self) -> Result<(), String> {
+    pub async fn init_database(&mut self, progress: &StartupProgress) -> Result<(), String> {
         if self.config.mode == DatabaseMode::Network {
             return Ok(()); // Rien à faire en mode réseau
         }
@@ -160,106 +443,424 @@ impl PostgresManager {
         }
         
         println!("==> 1/6 : Initialisation du cluster PostgreSQL...");
+        progress.step_async(StartupPhase::Initdb, async {
+            // Un dossier data incomplet n'est jamais supprimé : restes d'initdb en quarantaine,
+            // données utilisateur laissées en place en attendant une décision (`repair_data_dir`)
+            match cluster_recovery::inspect(&self.data_dir) {
+                DataDirState::Missing | DataDirState::Empty | DataDirState::Initialized => {}
+                DataDirState::PartialInit { entries } => {
+                    println!("⚠️ Initialisation précédente interrompue ({} éléments), mise en quarantaine.", entries);
+                    cluster_recovery::quarantine(&self.data_dir)?;
+                }
+                DataDirState::UserData { evidence } => {
+                    return Err(format!(
+                        "Le dossier {:?} n'est pas un cluster PostgreSQL complet (PG_VERSION manquant) mais contient des données ({}). \
+                         Rien n'a été supprimé : réparez-le manuellement, ou mettez-le en quarantaine pour repartir d'un cluster vide \
+                         puis restaurez une sauvegarde.",
+                        self.data_dir, evidence.join(", ")
+                    ));
+                }
+            }
+            self.run_initdb(&self.postgres_bin_dir, &self.data_dir).await?;
+            self.save_config()
+        }).await?;
+        
+        println!("==> 2/6 : Configuration du serveur...");
+        progress.step(StartupPhase::Configure, || self.configure_secure_postgres(&self.data_dir))?;
+        
+        // Le serveur temporaire n'est inscrit à aucun registre : personne d'autre ne l'arrêtera
+        if let Err(e) = self.prepare_on_temporary_server(progress).await {
+            if let Err(stop_error) = self.stop().await {
+                eprintln!("⚠️ Arrêt du serveur temporaire impossible: {}", stop_error);
+            }
+            return Err(e);
+        }
+        
+        println!("==> 6/6 : Finalisation...");
+        progress.step_async(StartupPhase::Finalize, self.stop()).await?;
+        
+        println!("✅ Initialisation sécurisée terminée !");
+        Ok(())
+    }
+
+    /// Etapes 3 à 5 de [`init_database`](Self::init_database) : démarre le serveur temporaire,
+    /// crée la base puis restreint les droits sur les fichiers.
+    async fn prepare_on_temporary_server(&mut self, progress: &StartupProgress) -> Result<(), String> {
+        println!("==> 3/6 : Démarrage temporaire...");
+        progress.step_async(StartupPhase::TemporaryStart, self.start_internal()).await?;
         
-        if self.data_dir.exists() {
-            fs::remove_dir_all(&self.data_dir).ok();
+        println!("==> 4/6 : Création de la base et attribution des droits...");
+        progress.step_async(StartupPhase::CreateDatabase, self.ensure_database_exists()).await?;
+        
+        println!("==> 5/6 : Sécurisation des fichiers...");
+        progress.step_async(StartupPhase::Permissions, self.secure_file_permissions(&self.data_dir)).await
+    }
+    
+    /// Dossier contenant `data` et `db_config.json`.
+    fn cluster_root(&self) -> PathBuf {
+        self.data_dir.parent().map(Path::to_path_buf).unwrap_or_else(|| self.data_dir.clone())
+    }
+
+    /// Compare la version majeure du cluster à celle des binaires, et migre le cluster
+    /// si les binaires sont plus récents. À appeler avant `start()`.
+    pub async fn upgrade_if_needed(&mut self) -> Result<(), String> {
+        if self.config.mode == DatabaseMode::Network || !self.is_initialized() {
+            return Ok(());
+        }
+
+        // Migration interrompue (plantage, coupure) : le cluster retrouve son pg_hba.conf
+        if pg_upgrade::restore_hba(&self.data_dir)? {
+            println!("🔧 pg_hba.conf d'origine restauré après une migration interrompue.");
+        }
+
+        let root = self.cluster_root();
+        let cluster_version = pg_upgrade::cluster_version(&self.data_dir)?;
+        let bin_version = pg_upgrade::binaries_version(&self.postgres_bin_dir).await?;
+
+        if cluster_version == bin_version {
+            // Binaires embarqués : on les garde pour pouvoir migrer après une mise à jour de l'application
+            if self.postgres_bin_dir.starts_with(&self.resources_dir) {
+                if let Err(e) = pg_upgrade::archive_binaries(&self.postgres_bin_dir, &self.resources_dir, bin_version).await {
+                    eprintln!("⚠️ Archivage des binaires PostgreSQL impossible: {}", e);
+                }
+            }
+            return Ok(());
+        }
+        if cluster_version > bin_version {
+            return Err(format!(
+                "Le cluster {:?} a été créé par PostgreSQL {}, plus récent que les binaires disponibles ({}). Démarrage annulé.",
+                self.data_dir, cluster_version, bin_version
+            ));
+        }
+
+        let old_bin = pg_upgrade::find_binaries(&root, &self.resources_dir, cluster_version).await.ok_or_else(|| format!(
+            "Le cluster est en PostgreSQL {} mais seuls les binaires {} sont disponibles. \
+             Installez PostgreSQL {} (ou placez ses binaires dans {:?}) pour permettre la migration.",
+            cluster_version, bin_version, cluster_version,
+            pg_upgrade::archive_dir(cluster_version).map(|dir| dir.join("bin")).unwrap_or_default()
+        ))?;
+
+        if pg_upgrade::deferred_version(&root) == Some(cluster_version) {
+            println!("⏸️ Migration vers PostgreSQL {} reportée : utilisation des binaires {:?}", bin_version, old_bin);
+            self.postgres_bin_dir = old_bin;
+            return Ok(());
+        }
+        // Verrou du registre tenu jusqu'à la fin : aucune autre application ne démarre le
+        // cluster pendant la migration
+        let _lock = self.lock_registry().await?;
+        if !matches!(self.readiness().await, Readiness::PortClosed | Readiness::WrongCluster { .. }) {
+            // Cluster partagé déjà démarré par une autre application : on ne l'arrête pas
+            println!("⚠️ PostgreSQL {} déjà en cours d'exécution, migration vers {} remise au prochain démarrage.", cluster_version, bin_version);
+            self.postgres_bin_dir = old_bin;
+            return Ok(());
+        }
+
+        self.upgrade_cluster(&old_bin, cluster_version, bin_version).await?;
+        Ok(())
+    }
+
+    /// Migre `data` vers la version des binaires courants. L'ancien dossier est conservé
+    /// jusqu'à `confirm_upgrade` ; en cas d'échec, il reste en place, intact.
+    async fn upgrade_cluster(&mut self, old_bin: &Path, from: u32, to: u32) -> Result<UpgradeRecord, String> {
+        println!("⬆️ Migration du cluster PostgreSQL {} -> {}...", from, to);
+        let root = self.cluster_root();
+        let new_data = root.join(format!("data.pg{}.upgrading", to));
+        let work_dir = root.join("upgrade-work");
+        for dir in [&new_data, &work_dir] {
+            if dir.exists() {
+                fs::remove_dir_all(dir).map_err(|e| format!("Nettoyage de {:?} impossible: {}", dir, e))?;
+            }
+        }
+        fs::create_dir_all(&work_dir).map_err(|e| e.to_string())?;
+
+        if let Err(e) = pg_upgrade::archive_binaries(old_bin, &self.resources_dir, from).await {
+            eprintln!("⚠️ Archivage des binaires PostgreSQL {} impossible: {}", from, e);
+        }
+
+        pg_upgrade::replace_hba_for_upgrade(&self.data_dir, &self.config.admin_user)?;
+        let result = self.migrate_into(old_bin, &new_data, &work_dir).await;
+        pg_upgrade::restore_hba(&self.data_dir)?;
+        let method = match result {
+            Ok(method) => method,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&new_data);
+                return Err(format!("Migration PostgreSQL {} -> {} échouée (cluster d'origine intact): {}", from, to, e));
+            }
+        };
+
+        self.configure_secure_postgres(&new_data)?;
+        self.secure_file_permissions(&new_data).await?;
+
+        let rollback_dir = root.join(format!("data.pg{}.rollback-{}", from, chrono::Local::now().format("%Y%m%d-%H%M%S")));
+        fs::rename(&self.data_dir, &rollback_dir).map_err(|e| format!("Mise de côté de l'ancien cluster impossible: {}", e))?;
+        if let Err(e) = fs::rename(&new_data, &self.data_dir) {
+            let _ = fs::rename(&rollback_dir, &self.data_dir);
+            return Err(format!("Mise en place du cluster migré impossible: {}", e));
+        }
+
+        let record = UpgradeRecord {
+            from_version: from,
+            to_version: to,
+            method,
+            rollback_dir,
+            old_bin_dir: old_bin.to_path_buf(),
+            upgraded_at: chrono::Local::now(),
+        };
+        pg_upgrade::save_pending_upgrade(&root, &record)?;
+        let _ = fs::remove_dir_all(&work_dir);
+        println!("✅ Cluster migré vers PostgreSQL {} ({:?}). Ancien cluster conservé dans {:?}", to, method, record.rollback_dir);
+        Ok(record)
+    }
+
+    /// Crée le nouveau cluster et y transfère les données : `pg_upgrade`, puis export/import en repli.
+    async fn migrate_into(&self, old_bin: &Path, new_data: &Path, work_dir: &Path) -> Result<UpgradeMethod, String> {
+        self.init_upgrade_target(new_data).await?;
+
+        let ctx = UpgradeContext {
+            old_bin,
+            new_bin: &self.postgres_bin_dir,
+            old_data: &self.data_dir,
+            new_data,
+            work_dir,
+            user: &self.config.admin_user,
+            password: &self.config.postgres_password,
+            old_port: settings::free_port()?,
+            new_port: settings::free_port()?,
+        };
+        match ctx.run_pg_upgrade().await {
+            Ok(()) => Ok(UpgradeMethod::PgUpgrade),
+            Err(e) => {
+                eprintln!("⚠️ {} ; repli sur export/import.", e);
+                // pg_upgrade a pu modifier le nouveau cluster : on repart d'un cluster vierge
+                self.init_upgrade_target(new_data).await?;
+                ctx.run_dump_restore().await?;
+                Ok(UpgradeMethod::DumpRestore)
+            }
+        }
+    }
+
+    /// Cluster vierge destiné à recevoir les données migrées.
+    async fn init_upgrade_target(&self, new_data: &Path) -> Result<(), String> {
+        if new_data.exists() {
+            fs::remove_dir_all(new_data).map_err(|e| e.to_string())?;
+        }
+        self.run_initdb(&self.postgres_bin_dir, new_data).await?;
+        pg_upgrade::write_upgrade_hba(new_data, &self.config.admin_user)?;
+        self.secure_file_permissions(new_data).await
+    }
+
+    /// Mise à niveau en attente de confirmation, le cas échéant.
+    pub fn pending_upgrade(&self) -> Option<UpgradeRecord> {
+        if self.config.mode == DatabaseMode::Network {
+            return None;
+        }
+        pg_upgrade::pending_upgrade(&self.cluster_root())
+    }
+
+    /// Valide la dernière mise à niveau et supprime l'ancien cluster (hors des threads du runtime async).
+    pub async fn confirm_upgrade(&self) -> Result<UpgradeRecord, String> {
+        let root = self.cluster_root();
+        tokio::task::spawn_blocking(move || pg_upgrade::confirm_upgrade(&root))
+            .await
+            .map_err(|e| format!("Suppression de l'ancien cluster interrompue: {}", e))?
+    }
+
+    /// Revient à l'ancien cluster et à ses binaires. Arrête puis redémarre PostgreSQL.
+    pub async fn rollback_upgrade(&mut self) -> Result<UpgradeRecord, String> {
+        let root = self.cluster_root();
+        let record = pg_upgrade::pending_upgrade(&root).ok_or("Aucune mise à niveau en attente de confirmation")?;
+        let old_bin = pg_upgrade::find_binaries(&root, &self.resources_dir, record.from_version)
+            .await
+            .or_else(|| Some(record.old_bin_dir.clone()).filter(|dir| dir.exists()))
+            .ok_or_else(|| format!("Binaires PostgreSQL {} introuvables, retour arrière impossible", record.from_version))?;
+
+        self.stop().await?;
+        let record = pg_upgrade::rollback_upgrade(&root, &self.data_dir)?;
+        self.postgres_bin_dir = old_bin;
+        self.start().await?;
+        Ok(record)
+    }
+
+    /// Relance une migration reportée après un retour arrière. Le cluster est quitté le temps
+    /// de la migration, puis redémarré ; s'il reste utilisé par une autre application, la
+    /// migration aura lieu au prochain démarrage.
+    pub async fn retry_upgrade(&mut self) -> Result<(), String> {
+        if self.config.mode == DatabaseMode::Network {
+            return Err("Aucun cluster local à migrer en mode réseau".to_string());
+        }
+        pg_upgrade::clear_deferred(&self.cluster_root())?;
+        self.leave().await?;
+        if !matches!(self.readiness().await, Readiness::PortClosed | Readiness::WrongCluster { .. }) {
+            self.start().await?;
+            return Err("PostgreSQL est encore utilisé par une autre application : la migration aura lieu au prochain démarrage.".to_string());
         }
-        fs::create_dir_all(&self.data_dir).map_err(|e| e.to_string())?;
+        self.postgres_bin_dir = platform::resolve_postgres_bin_dir(&self.resources_dir);
+        let result = self.upgrade_if_needed().await;
+        self.start().await?;
+        result
+    }
+
+    /// Crée un cluster vide dans `data_dir` avec les binaires de `bin_dir`.
+    async fn run_initdb(&self, bin_dir: &Path, data_dir: &Path) -> Result<(), String> {
+        fs::create_dir_all(data_dir).map_err(|e| e.to_string())?;
         
-        let pw_file_path = self.data_dir.parent().unwrap().join("pg_pw.tmp");
-        fs::write(&pw_file_path, &self.config.postgres_password).map_err(|e| e.to_string())?;
+        let pw_file_path = data_dir.parent()
+            .ok_or_else(|| format!("Dossier data {:?} sans dossier parent", data_dir))?
+            .join("pg_pw.tmp");
+        fsutil::write_atomic_private(&pw_file_path, self.config.postgres_password.as_bytes())?;
 
-        let output = Command::new(self.postgres_bin_dir.join("initdb.exe"))
-            .arg("-D").arg(&self.data_dir)
-            .arg("-U").arg("postgres")
+        let mut cmd = Command::new(bin_dir.join(platform::exe_name("initdb")));
+        platform::hide_console(&mut cmd)
+            .arg("-D").arg(data_dir)
+            .arg("-U").arg(&self.config.admin_user)
             .arg("--encoding=UTF8")
             .arg("--locale=C")         
             .arg("--auth=scram-sha-256")
             .arg("--pwfile").arg(&pw_file_path)
-            .arg("--no-sync")
-            .output()
-            .map_err(|e| e.to_string())?;
-        
+            .arg("--no-sync");
+        let output = tokio::process::Command::from(cmd).output().await;
+        // Le mot de passe ne doit pas survivre à initdb, même si le lancement échoue
         let _ = fs::remove_file(&pw_file_path);
-        
+        let output = output.map_err(|e| e.to_string())?;
+
         if !output.status.success() {
             return Err(format!("Initdb erreur: {}", String::from_utf8_lossy(&output.stderr)));
         }
-
-        fs::write(&self.config_file_path, serde_json::to_string_pretty(&self.config).unwrap()).ok();
-        
-        println!("==> 2/6 : Configuration du serveur...");
-        self.configure_secure_postgres()?;
-        
-        println!("==> 3/6 : Démarrage temporaire...");
-        self.start_internal()?;
-        
-        println!("==> 4/6 : Création de la base et attribution des droits...");
-        self.ensure_database_exists()?;
-        
-        println!("==> 5/6 : Sécurisation des fichiers Windows...");
-        self.secure_file_permissions()?;
-        
-        println!("==> 6/6 : Finalisation...");
-        self.stop()?;
-        
-        println!("✅ Initialisation sécurisée terminée !");
         Ok(())
     }
-    
-    fn configure_secure_postgres(&self) -> Result<(), String> {
-        let config_path = self.data_dir.join("postgresql.conf");
+
+    fn configure_secure_postgres(&self, data_dir: &Path) -> Result<(), String> {
+        let config_path = data_dir.join("postgresql.conf");
         let config = format!(
-            "port = {}\nlisten_addresses = '127.0.0.1'\nmax_connections = 50\nshared_buffers = 128MB\npassword_encryption = scram-sha-256\ndynamic_shared_memory_type = windows\n",
-            self.config.port
+            "port = {}\nlisten_addresses = '127.0.0.1'\nmax_connections = 50\nshared_buffers = 128MB\npassword_encryption = scram-sha-256\ndynamic_shared_memory_type = {}\n",
+            self.config.port,
+            platform::shared_memory_type()
         );
-        fs::write(&config_path, config).map_err(|e| e.to_string())?;
+        fs::write(&config_path, config + &self.logging_config()).map_err(|e| e.to_string())?;
         
-        let hba_path = self.data_dir.join("pg_hba.conf");
+        let hba_path = data_dir.join("pg_hba.conf");
         let hba = format!(
-            "host all postgres 127.0.0.1/32 scram-sha-256\nhost all {0} 127.0.0.1/32 scram-sha-256\n# Bloquer le reste\nhost all all 127.0.0.1/32 reject\n",
-            APP_USER
+            "host all \"{0}\" 127.0.0.1/32 scram-sha-256\nhost all \"{1}\" 127.0.0.1/32 scram-sha-256\n# Bloquer le reste\nhost all all 127.0.0.1/32 reject\n",
+            self.config.admin_user, self.config.app_user
         );
         fs::write(&hba_path, hba).map_err(|e| e.to_string())?;
         Ok(())
     }
     
-    fn start_internal(&self) -> Result<(), String> {
-        let mut cmd = Command::new(self.postgres_bin_dir.join("postgres.exe"));
+    /// Logs du serveur dans `<cluster>/logs` : un fichier par jour de la semaine, écrasé
+    /// d'une semaine sur l'autre, et rotation anticipée au-delà de 10 Mo.
+    fn logging_config(&self) -> String {
+        let log_dir = self.log_dir().to_string_lossy().replace('\\', "/").replace('\'', "''");
+        format!(
+            "logging_collector = on\nlog_directory = '{}'\nlog_filename = 'postgresql-%a.log'\nlog_truncate_on_rotation = on\nlog_rotation_age = 1d\nlog_rotation_size = 10MB\nlog_line_prefix = '%m [%p] '\n",
+            log_dir
+        )
+    }
+
+    /// Ajoute la configuration des logs aux clusters créés avant qu'elle n'existe
+    /// (prise en compte au prochain démarrage de PostgreSQL).
+    fn ensure_logging_config(&self) -> Result<(), String> {
+        let config_path = self.data_dir.join("postgresql.conf");
+        let Ok(current) = fs::read_to_string(&config_path) else {
+            return Ok(());
+        };
+        if current.contains("logging_collector") {
+            return Ok(());
+        }
+        let separator = if current.ends_with('\n') { "" } else { "\n" };
+        // Un postgresql.conf tronqué empêcherait le cluster de démarrer
+        fsutil::write_atomic(&config_path, format!("{}{}{}", current, separator, self.logging_config()).as_bytes())
+    }
+
+    fn log_dir(&self) -> PathBuf {
+        self.cluster_root().join("logs")
+    }
+
+    /// Fichiers de log du serveur local, du plus ancien au plus récent.
+    pub fn postgres_log_files(&self) -> Result<Vec<PathBuf>, String> {
+        if self.config.mode == DatabaseMode::Network {
+            return Err("Mode réseau : les logs sont sur le serveur PostgreSQL distant".into());
+        }
+        let Ok(entries) = fs::read_dir(self.log_dir()) else {
+            return Ok(Vec::new());
+        };
+        let mut files: Vec<(std::time::SystemTime, PathBuf)> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+            .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+            .collect();
+        files.sort();
+        Ok(files.into_iter().map(|(_, path)| path).collect())
+    }
+
+    /// Redirige stdout/stderr de `postgres` vers le log console (repart de zéro au-delà de 5 Mo).
+    fn capture_console(&self, cmd: &mut Command) {
+        let log_dir = self.log_dir();
+        let path = log_dir.join(CONSOLE_LOG);
+        if fs::metadata(&path).is_ok_and(|m| m.len() > CONSOLE_LOG_MAX_BYTES) {
+            let _ = fs::remove_file(&path);
+        }
+        let file = fs::create_dir_all(&log_dir)
+            .and_then(|_| fs::OpenOptions::new().create(true).append(true).open(&path));
+        match file.and_then(|file| Ok((file.try_clone()?, file))) {
+            Ok((stdout, stderr)) => {
+                cmd.stdout(stdout).stderr(stderr);
+            }
+            Err(e) => {
+                eprintln!("⚠️ Log console PostgreSQL indisponible: {}", e);
+                cmd.stdout(Stdio::null()).stderr(Stdio::null());
+            }
+        }
+    }
+
+    async fn start_internal(&mut self) -> Result<(), String> {
+        let mut cmd = Command::new(self.bin("postgres"));
         cmd.arg("-D").arg(&self.data_dir)
-            .arg("-p").arg(self.config.port.to_string())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null());
+            .arg("-p").arg(self.config.port.to_string());
+        self.capture_console(&mut cmd);
             
-        #[cfg(windows)]
-        cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+        platform::hide_console(&mut cmd);
 
-        cmd.spawn()
+        let process = tokio::process::Command::from(cmd).spawn()
             .map_err(|e| e.to_string())?;
-        
-        self.wait_for_ready_internal(60)
-    }
-
-    fn wait_for_ready_internal(&self, attempts: i32) -> Result<(), String> {
-        let isready = self.postgres_bin_dir.join("pg_isready.exe");
-        for _ in 0..attempts {
-            let mut cmd = Command::new(&isready);
-            cmd.arg("-h").arg("127.0.0.1")
-                .arg("-p").arg(self.config.port.to_string());;
-                
-            #[cfg(windows)]
-            cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
-
-            let status = cmd.status();
-            if let Ok(s) = status {
-                if s.success() { return Ok(()); }
-            }
-            thread::sleep(Duration::from_millis(500));
-        }
-        Err("Le serveur n'a pas démarré à temps".into())
+        self.child = Some(process);
+
+        self.wait_for_ready().await
+    }
+
+    /// Etat du serveur local, vérifié par une connexion du superutilisateur : distingue
+    /// port libre, démarrage en cours, mot de passe refusé et autre cluster sur le même port.
+    /// Le futur n'emprunte pas le manager (utilisable hors du verrou de l'état).
+    pub fn readiness(&self) -> impl Future<Output = Readiness> + Send + 'static {
+        let options = self.config.admin_options("postgres");
+        let expected = (self.config.mode == DatabaseMode::Embedded).then(|| self.data_dir.clone());
+        async move { pg_probe::probe(&options, expected.as_deref()).await }
+    }
+
+    /// Processus `postgres` lancé par cette instance : `Some(Ok)` s'il tourne, `Some(Err)` avec
+    /// son code de sortie s'il s'est arrêté, `None` s'il a été lancé ailleurs (ou en mode réseau).
+    pub fn child_status(&mut self) -> Option<Result<(), String>> {
+        let child = self.child.as_mut()?;
+        Some(match child.try_wait() {
+            Ok(None) => Ok(()),
+            Ok(Some(status)) => Err(status.to_string()),
+            Err(e) => Err(e.to_string()),
+        })
+    }
+
+    /// Attend que le serveur accepte les connexions ; abandonne si le processus lancé s'arrête.
+    async fn wait_for_ready(&mut self) -> Result<(), String> {
+        let options = self.config.admin_options("postgres");
+        let child = &mut self.child;
+        pg_probe::wait_until_ready(&options, Some(&self.data_dir), STARTUP_TIMEOUT, || {
+            let status = child.as_mut()?.try_wait().ok()??;
+            Some(status.to_string())
+        })
+        .await
     }
     
-    pub fn start(&mut self) -> Result<(), String> {
+    pub async fn start(&mut self) -> Result<(), String> {
         if self.config.mode == DatabaseMode::Network {
             println!("📡 Mode Réseau actif: Connexion à {}:{}", self.config.host, self.config.port);
             return Ok(());
@@ -267,14 +868,61 @@ impl PostgresManager {
 
         if self.child.is_some() { return Ok(()); }
 
-        // Check if already running (e.g. orphan process from previous session)
-        if self.wait_for_ready_internal(2).is_ok() {
-            println!("✅ PostgreSQL est déjà en cours d'exécution sur le port {}", self.config.port);
-            return Ok(());
+        // Inscription au registre avant tout : un autre consommateur qui quitte le cluster
+        // pendant ce temps voit notre bail et ne l'arrête pas
+        let lock = self.lock_registry().await?;
+        self.leases.prune_expired(&lock);
+        self.leases.acquire()?;
+
+        match self.join_cluster(lock).await {
+            Ok(()) => {
+                self.joined = true;
+                Ok(())
+            }
+            Err(e) => {
+                // Pas de bail orphelin qui empêcherait les autres applications d'arrêter le cluster
+                self.leases.release();
+                Err(e)
+            }
+        }
+    }
+
+    /// Verrou du registre des consommateurs, attendu hors des threads du runtime async.
+    async fn lock_registry(&self) -> Result<FileLock, String> {
+        let leases = self.leases.clone();
+        tokio::task::spawn_blocking(move || leases.lock())
+            .await
+            .map_err(|e| format!("Attente du verrou du registre interrompue: {}", e))?
+    }
+
+    /// Rejoint le cluster ou le démarre. `lock` (verrou du registre) est relâché dès que le
+    /// serveur est lancé, avant l'attente de sa disponibilité.
+    async fn join_cluster(&mut self, lock: FileLock) -> Result<(), String> {
+        // Déjà lancé (autre application, processus orphelin d'une session précédente) ?
+        match self.readiness().await {
+            Readiness::PortClosed => {}
+            Readiness::Ready => {
+                let others: Vec<String> = self.leases.others().iter().map(|l| format!("{} (pid {})", l.app, l.pid)).collect();
+                println!("✅ PostgreSQL est déjà en cours d'exécution sur le port {} (utilisé aussi par : {})",
+                    self.config.port,
+                    if others.is_empty() { "aucune autre application inscrite".to_string() } else { others.join(", ") });
+                return Ok(());
+            }
+            Readiness::StartingUp { .. } => {
+                drop(lock);
+                println!("⏳ PostgreSQL est en cours de démarrage sur le port {}, attente...", self.config.port);
+                return self.wait_for_ready().await;
+            }
+            other => {
+                return Err(format!(
+                    "Le port {} est occupé par un serveur inutilisable : {}. Arrêtez-le ou changez le port du cluster.",
+                    self.config.port, other.describe()
+                ));
+            }
         }
 
         println!("🚀 Démarrage de PostgreSQL sur le port {}...", self.config.port);
-        let postgres_exe = self.postgres_bin_dir.join("postgres.exe");
+        let postgres_exe = self.bin("postgres");
         if !postgres_exe.exists() {
             return Err(format!("❌ Exécutable PostgreSQL introuvable à : {:?}", postgres_exe));
         }
@@ -282,136 +930,309 @@ impl PostgresManager {
         let mut cmd = Command::new(&postgres_exe);
         cmd.arg("-D").arg(&self.data_dir)
             .arg("-p").arg(self.config.port.to_string());
-            //.stdout(Stdio::null())
-            //.stderr(std::fs::File::create("postgres_startup.log").unwrap());
+        self.capture_console(&mut cmd);
             
-        #[cfg(windows)]
-        cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+        platform::hide_console(&mut cmd);
 
-        let process = cmd.spawn()
+        let process = tokio::process::Command::from(cmd).spawn()
             .map_err(|e| format!("Echec du spawn postgres: {}", e))?;
         
         self.child = Some(process);
-        self.wait_for_ready_internal(60)?;
+        if let Err(e) = self.leases.mark_started() {
+            // Sans marqueur, la politique « si démarré par nous » laissera le cluster actif
+            eprintln!("⚠️ Marqueur de démarrage non écrit, PostgreSQL ne sera pas arrêté à la fermeture : {}", e);
+        }
+        drop(lock);
+        self.wait_for_ready().await?;
         println!("✅ Base de données prête !");
         Ok(())
     }
+
+    /// Quitte le cluster : retire notre bail, et l'arrête si nous étions son dernier utilisateur.
+    /// Un cluster démarré hors du registre (à la main, ancienne version de CollabTools) est laissé actif.
+    /// Renvoie `true` si le cluster a été arrêté.
+    pub async fn leave(&mut self) -> Result<bool, String> {
+        self.leave_with(ShutdownPolicy::OnlyIfOwned).await
+    }
+
+    /// Comme [`leave`](Self::leave), mais l'arrêt du cluster suit `policy`.
+    pub async fn leave_with(&mut self, policy: ShutdownPolicy) -> Result<bool, String> {
+        if self.config.mode == DatabaseMode::Network {
+            return Ok(false);
+        }
+        let lock = self.lock_registry().await?;
+        self.leases.prune_expired(&lock);
+        self.leases.release();
+        self.joined = false;
+        match policy {
+            ShutdownPolicy::Never => {
+                println!("ℹ️ PostgreSQL laissé actif (politique d'arrêt : jamais).");
+                return Ok(false);
+            }
+            ShutdownPolicy::Always => {
+                println!("🛑 Arrêt de PostgreSQL (politique d'arrêt : toujours).");
+            }
+            ShutdownPolicy::OnlyIfOwned => {
+                let others = self.leases.others();
+                if !others.is_empty() {
+                    let names: Vec<String> = others.iter().map(|l| format!("{} (pid {})", l.app, l.pid)).collect();
+                    println!("🤝 PostgreSQL laissé actif, toujours utilisé par : {}", names.join(", "));
+                    return Ok(false);
+                }
+                if !self.leases.started_by_consumer() {
+                    println!("ℹ️ PostgreSQL démarré hors du registre des applications, laissé actif.");
+                    return Ok(false);
+                }
+                println!("🛑 Dernier utilisateur du cluster : arrêt de PostgreSQL.");
+            }
+        }
+        self.stop().await?;
+        Ok(true)
+    }
+
+    /// Registre dont le bail doit être rafraîchi (`heartbeat`, qui le réinscrit s'il a disparu)
+    /// toutes les [`cluster_lease::HEARTBEAT_INTERVAL`] ; `None` si nous n'utilisons pas le cluster.
+    pub fn lease_to_refresh(&self) -> Option<LeaseRegistry> {
+        (self.config.mode != DatabaseMode::Network && self.joined).then(|| self.leases.clone())
+    }
+
+    /// Etat du dossier `data` et quarantaines existantes (`None` en mode réseau).
+    pub fn data_dir_report(&self) -> Option<DataDirReport> {
+        if self.config.mode == DatabaseMode::Network {
+            return None;
+        }
+        Some(DataDirReport {
+            data_dir: self.data_dir.clone(),
+            state: cluster_recovery::inspect(&self.data_dir),
+            quarantined: cluster_recovery::list_quarantined(&self.cluster_root()),
+        })
+    }
+
+    /// Met le dossier `data` en quarantaine pour permettre un nouvel initdb. Refusé si le
+    /// cluster est complet ou en cours d'utilisation.
+    pub async fn quarantine_data_dir(&mut self) -> Result<PathBuf, String> {
+        if self.config.mode == DatabaseMode::Network {
+            return Err("Aucun cluster local en mode réseau".into());
+        }
+        match cluster_recovery::inspect(&self.data_dir) {
+            DataDirState::Missing | DataDirState::Empty => Err("Le dossier data est vide, rien à mettre en quarantaine".into()),
+            DataDirState::Initialized => Err("Le cluster est complet (PG_VERSION présent) : quarantaine refusée".into()),
+            DataDirState::PartialInit { .. } | DataDirState::UserData { .. } => {
+                if matches!(self.readiness().await, Readiness::Ready | Readiness::StartingUp { .. }) {
+                    return Err("PostgreSQL répond sur ce port : arrêtez-le avant toute réparation".into());
+                }
+                cluster_recovery::quarantine(&self.data_dir)
+            }
+        }
+    }
+
+    /// Description de la base pour le paquet de diagnostic. Le futur renvoyé n'emprunte pas
+    /// le manager : il peut être attendu après avoir relâché le verrou de l'état.
+    pub fn diagnostics(&self) -> impl Future<Output = DatabaseDiagnostics> + Send + 'static {
+        let network = self.config.mode == DatabaseMode::Network;
+        let mut cmd = Command::new(self.bin("postgres"));
+        cmd.arg("--version");
+        platform::hide_console(&mut cmd);
+        let readiness = self.readiness();
+        let mut report = DatabaseDiagnostics {
+            mode: self.config.mode.clone(),
+            host: self.config.host.clone(),
+            port: self.config.port,
+            db_name: self.db_name.clone(),
+            config_file: self.config_file_path.clone(),
+            config: self.config.redacted(),
+            data_dir: self.data_dir.clone(),
+            bin_dir: self.postgres_bin_dir.clone(),
+            log_dir: self.log_dir(),
+            binaries_version: None,
+            cluster_version: fs::read_to_string(self.data_dir.join("PG_VERSION")).ok()
+                .filter(|_| !network)
+                .map(|version| version.trim().to_string()),
+            readiness: Readiness::NoResponse,
+            consumers: self.consumers(),
+        };
+        async move {
+            report.binaries_version = tokio::process::Command::from(cmd).output().await.ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+            report.readiness = readiness.await;
+            report
+        }
+    }
+
+    /// Applications utilisant actuellement le cluster local (vide en mode réseau).
+    pub fn consumers(&self) -> Vec<ClusterConsumer> {
+        if self.config.mode == DatabaseMode::Network {
+            return Vec::new();
+        }
+        self.leases.active().into_iter().map(|lease| ClusterConsumer {
+            is_self: self.leases.is_self(&lease),
+            lease,
+        }).collect()
+    }
     
-    pub fn stop(&mut self) -> Result<(), String> {
+    pub async fn stop(&mut self) -> Result<(), String> {
         if self.config.mode == DatabaseMode::Network {
             return Ok(());
         }
 
-        let mut cmd = Command::new(self.postgres_bin_dir.join("pg_ctl.exe"));
+        let mut cmd = Command::new(self.bin("pg_ctl"));
         cmd.arg("stop").arg("-D").arg(&self.data_dir).arg("-m").arg("fast");
         
-        #[cfg(windows)]
-        cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+        platform::hide_console(&mut cmd);
 
-        let _ = cmd.output();
+        let output = tokio::process::Command::from(cmd).output().await
+            .map_err(|e| format!("Lancement de pg_ctl impossible: {}", e))?;
+        // pg_ctl échoue aussi si aucun serveur ne tourne : seul un postmaster.pid restant est un échec
+        if !output.status.success() && self.data_dir.join("postmaster.pid").exists() {
+            return Err(format!("Arrêt de PostgreSQL impossible: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
         self.child = None;
+        self.leases.clear_started();
         Ok(())
     }
     
-    pub fn ensure_database_exists(&self) -> Result<(), String> {
+    pub async fn ensure_database_exists(&self) -> Result<(), String> {
         if self.config.mode == DatabaseMode::Network {
-             // En mode réseau, on suppose que la DB existe déjà (créée par l'installateur serveur)
-             // Mais on pourrait vérifier la connexion ici
-             return Ok(());
+            // Base et rôle créés par l'administrateur du serveur, sauf si la création automatique est demandée
+            if self.config.provision && self.config.has_admin_credentials() {
+                self.provision_database().await
+                    .map_err(|e| format!("Création de la base '{}' sur {}:{} impossible: {}", self.db_name, self.config.host, self.config.port, e))?;
+            }
+            let conn = pg_admin::connect(&self.config.app_options(&self.db_name)).await.map_err(|e| format!(
+                "Connexion de '{}' à la base '{}' sur {}:{} impossible: {}",
+                self.config.app_user, self.db_name, self.config.host, self.config.port, e
+            ))?;
+            pg_admin::close(conn).await;
+            return Ok(());
         }
 
-        let mut cmd = Command::new(self.postgres_bin_dir.join("createdb.exe"));
-        cmd.arg("-h").arg("127.0.0.1").arg("-p").arg(self.config.port.to_string())
-            .arg("-U").arg("postgres")
-            .arg(&self.db_name)
-            .env("PGPASSWORD", &self.config.postgres_password);
-            
-        #[cfg(windows)]
-        cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+        self.provision_database().await
+    }
 
-        let _ = cmd.output();
+    /// Crée le rôle applicatif et la base si besoin, puis lui en donne la propriété.
+    async fn provision_database(&self) -> Result<(), String> {
+        let app_user = &self.config.app_user;
+        // Le rôle doit exister avant de pouvoir lui attribuer la base
+        let mut conn = pg_admin::connect(&self.superuser_options("postgres")).await?;
+        let result = async {
+            pg_admin::ensure_login_role(&mut conn, app_user, &self.config.app_password).await?;
+            if pg_admin::ensure_database(&mut conn, &self.db_name, app_user).await? {
+                println!("🆕 Base '{}' créée.", self.db_name);
+            }
+            Ok::<_, String>(())
+        }.await;
+        pg_admin::close(conn).await;
+        result?;
         
         // Always try to authorize the user on the current db
-        self.authorize_user_on_db(&self.db_name)?;
-        Ok(())
+        self.grant_on_db(&self.db_name).await
     }
 
-    pub fn authorize_user_on_db(&self, db_name: &str) -> Result<(), String> {
-         if self.config.mode == DatabaseMode::Network { return Ok(()); }
-
-        // 1. Ensure user exists (connect to 'postgres')
-        let user_sql = format!(
-            "DO $$ BEGIN IF NOT EXISTS (SELECT FROM pg_catalog.pg_user WHERE usename = '{0}') THEN CREATE USER {0} WITH PASSWORD '{1}'; END IF; END $$;",
-            APP_USER, self.config.app_password
-        );
-        let mut cmd = Command::new(self.postgres_bin_dir.join("psql.exe"));
-        cmd.arg("-h").arg("127.0.0.1").arg("-p").arg(self.config.port.to_string())
-            .arg("-U").arg("postgres").arg("-d").arg("postgres")
-            .arg("-c").arg(&user_sql)
-            .env("PGPASSWORD", &self.config.postgres_password);
-            
-        #[cfg(windows)]
-        cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+    /// Rend l'utilisateur applicatif propriétaire de `db_name` (connexion à cette base, les droits de schéma y sont locaux).
+    async fn grant_on_db(&self, db_name: &str) -> Result<(), String> {
+        let mut conn = pg_admin::connect(&self.superuser_options(db_name)).await?;
+        let result = pg_admin::grant_database_ownership(&mut conn, db_name, &self.config.app_user).await;
+        pg_admin::close(conn).await;
+        result
+    }
 
-        let _ = cmd.output();
+    /// Prépare un renouvellement des mots de passe (voir [`CredentialRotation::run`]) sur une
+    /// copie de la configuration : le gestionnaire reste disponible pendant les échanges avec
+    /// le serveur. Le résultat s'adopte avec [`apply_rotation`](Self::apply_rotation).
+    pub fn credential_rotation(&self) -> Result<CredentialRotation, String> {
+        // Secrets en clair : fichier partagé de CollabTools, qui utilise les mêmes comptes
+        if !self.protect_secrets {
+            return Err("La base est gérée par CollabTools : renouvelez les mots de passe depuis CollabTools".into());
+        }
+        if !self.config.has_admin_credentials() {
+            return Err("Aucun compte d'administration configuré : rotation impossible".into());
+        }
+        Ok(CredentialRotation {
+            config: self.config.clone(),
+            config_file_path: self.config_file_path.clone(),
+            db_name: self.db_name.clone(),
+        })
+    }
 
-        // 2. Grant rights on the specific database (connect to that database)
-        let db_sql = format!(
-            "ALTER DATABASE {0} OWNER TO {1}; \
-             GRANT ALL ON SCHEMA public TO {1};",
-            db_name, APP_USER
-        );
-        
-        let mut cmd = Command::new(self.postgres_bin_dir.join("psql.exe"));
-        cmd.arg("-h").arg("127.0.0.1").arg("-p").arg(self.config.port.to_string())
-            .arg("-U").arg("postgres").arg("-d").arg(db_name)
-            .arg("-c").arg(&db_sql)
-            .env("PGPASSWORD", &self.config.postgres_password);
-            
-        #[cfg(windows)]
-        cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+    /// Adopte la configuration issue d'une rotation réussie (déjà enregistrée sur disque).
+    pub fn apply_rotation(&mut self, rotated: RotatedCredentials) -> Vec<String> {
+        self.config = rotated.config;
+        rotated.roles
+    }
 
-        let output = cmd.output()
-            .map_err(|e| e.to_string())?;
+    /// Options de connexion avec le compte d'administration (`postgres` en mode embarqué) sur `database`.
+    pub fn superuser_options(&self, database: &str) -> PgConnectOptions {
+        self.config.admin_options(database)
+    }
 
-        if !output.status.success() {
-            println!("Erreur d'attribution des droits sur {}: {}", db_name, String::from_utf8_lossy(&output.stderr));
+    pub fn pg_tools(&self) -> PgTools {
+        let network = self.config.mode == DatabaseMode::Network;
+        PgTools {
+            bin_dir: self.postgres_bin_dir.clone(),
+            host: self.config.host.clone(),
+            port: self.config.port,
+            user: self.config.admin_user.clone(),
+            password: self.config.postgres_password.clone(),
+            ssl_mode: if network { self.config.ssl_mode } else { SslMode::Disable },
+            ca_file: self.config.ca_file.clone().filter(|_| network),
         }
-        
-        Ok(())
+    }
+
+    /// Options de connexion du compte applicatif sur la base Wiki.js.
+    pub fn app_options(&self) -> PgConnectOptions {
+        self.config.app_options(&self.db_name)
+    }
+
+    /// Dossier `data` du cluster local (`None` en mode réseau).
+    pub fn local_data_dir(&self) -> Option<&Path> {
+        (!self.is_network()).then_some(self.data_dir.as_path())
+    }
+
+    pub fn is_network(&self) -> bool {
+        self.config.mode == DatabaseMode::Network
+    }
+
+    /// Le compte d'administration est-il utilisable (toujours vrai en mode embarqué) ?
+    pub fn has_admin_credentials(&self) -> bool {
+        self.config.has_admin_credentials()
+    }
+
+    pub fn app_user(&self) -> &str {
+        &self.config.app_user
     }
     
-    fn secure_file_permissions(&self) -> Result<(), String> {
+    async fn secure_file_permissions(&self, data_dir: &Path) -> Result<(), String> {
         #[cfg(target_os = "windows")]
         {
-            let data_path = self.data_dir.to_str().unwrap();
+            use tokio::process::Command;
+            // Chemin passé tel quel (OsStr) : un profil au nom non UTF-8 reste utilisable
             // SYSTEM et Administrateurs
-            let _ = Command::new("icacls").arg(data_path).arg("/grant").arg("SYSTEM:(OI)(CI)F").output();
-            let _ = Command::new("icacls").arg(data_path).arg("/grant").arg("*S-1-5-32-544:(OI)(CI)F").output();
+            let _ = Command::new("icacls").arg(data_dir).arg("/grant").arg("SYSTEM:(OI)(CI)F").output().await;
+            let _ = Command::new("icacls").arg(data_dir).arg("/grant").arg("*S-1-5-32-544:(OI)(CI)F").output().await;
             
             // On s'assure que l'utilisateur local actuel a aussi l'accès total pour le développement
             let username = std::env::var("USERNAME").unwrap_or_default();
             if !username.is_empty() {
-                let _ = Command::new("icacls").arg(data_path).arg("/grant").arg(format!("{}:(OI)(CI)F", username)).output();
+                let _ = Command::new("icacls").arg(data_dir).arg("/grant").arg(format!("{}:(OI)(CI)F", username)).output().await;
             }
         }
+        #[cfg(unix)]
+        {
+            // Postgres refuse de démarrer si le dossier data est lisible par d'autres utilisateurs
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(data_dir, fs::Permissions::from_mode(0o700))
+                .map_err(|e| format!("Impossible de restreindre les droits du dossier data: {}", e))?;
+        }
         Ok(())
     }
     
-    pub fn get_connection_string(&self) -> String {
-        format!("postgres://postgres:{}@{}:{}/{}?sslmode=disable", 
-            self.config.postgres_password,
-            self.config.host,
-            self.config.port,
-            self.db_name
-        )
+    /// Paramètres de connexion de l'utilisateur applicatif, pour le `config.yml` de Wiki.js.
+    pub fn wiki_db_settings(&self) -> WikiDbSettings {
+        wiki_db_settings(&self.config, &self.db_name)
     }
-}
 
-// On ne veut PAS arrêter la DB quand le manager est droppé.
-// Elle doit rester active pour les autres applis ou pour le Wiki lui-même.
-/*
-impl Drop for PostgresManager {
-    fn drop(&mut self) { let _ = self.stop(); }
 }
-*/
+
+// Pas de `Drop` : l'arrêt est asynchrone et dépend de la politique d'arrêt et des autres
+// utilisateurs du cluster. Il est orchestré à la fermeture de l'application (`lib.rs`).
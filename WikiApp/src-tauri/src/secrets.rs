@@ -0,0 +1,337 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::fsutil::{write_atomic_private, FileLock};
+use crate::platform;
+
+/// Nom du service sous lequel les secrets sont rangés dans le trousseau de l'OS.
+#[cfg(any(windows, target_os = "macos", target_os = "linux"))]
+const KEYRING_SERVICE: &str = "com.wikitools.app";
+/// Fichier chiffré, placé à côté de la configuration qu'il protège.
+const SECRETS_FILE: &str = "secrets.enc.json";
+const KEY_FILE: &str = "secrets.key";
+const NONCE_LEN: usize = 12;
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretBackend {
+    /// Trousseau de l'OS (Gestionnaire d'identification Windows, Trousseau macOS, Secret Service
+    /// sous Linux : GNOME Keyring, KWallet).
+    Keyring,
+    /// Fichier chiffré (AES-256-GCM) avec une clé locale à la machine ; fonctionne partout,
+    /// y compris sous Linux sans session graphique ni Secret Service.
+    EncryptedFile,
+}
+
+/// Stockage de secrets nommés.
+pub trait SecretStore {
+    fn get(&self, name: &str) -> Result<Option<String>, String>;
+    fn set(&self, name: &str, value: &str) -> Result<(), String>;
+    #[cfg_attr(not(any(windows, target_os = "macos", target_os = "linux")), allow(dead_code))]
+    fn delete(&self, name: &str) -> Result<(), String>;
+}
+
+/// Ouvre le stockage `backend` ; `dir` accueille le fichier chiffré.
+pub fn open(backend: SecretBackend, dir: &Path) -> Result<Box<dyn SecretStore>, String> {
+    match backend {
+        #[cfg(any(windows, target_os = "macos", target_os = "linux"))]
+        SecretBackend::Keyring => Ok(Box::new(KeyringStore)),
+        #[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+        SecretBackend::Keyring => Err("Trousseau système non pris en charge sur cette plateforme".into()),
+        SecretBackend::EncryptedFile => Ok(Box::new(EncryptedFileStore::new(dir)?)),
+    }
+}
+
+/// Trousseau de l'OS s'il répond, sinon fichier chiffré.
+pub fn preferred_backend() -> SecretBackend {
+    #[cfg(any(windows, target_os = "macos", target_os = "linux"))]
+    {
+        if KeyringStore::probe() {
+            return SecretBackend::Keyring;
+        }
+        eprintln!("⚠️ Trousseau système indisponible, secrets stockés dans un fichier chiffré.");
+    }
+    SecretBackend::EncryptedFile
+}
+
+#[cfg(any(windows, target_os = "macos", target_os = "linux"))]
+struct KeyringStore;
+
+#[cfg(any(windows, target_os = "macos", target_os = "linux"))]
+impl KeyringStore {
+    fn entry(name: &str) -> Result<keyring::Entry, String> {
+        keyring::Entry::new(KEYRING_SERVICE, name).map_err(|e| format!("Trousseau système inaccessible: {}", e))
+    }
+
+    /// Ecrit, relit puis supprime une entrée de test.
+    fn probe() -> bool {
+        let store = KeyringStore;
+        let ok = store.set("probe", "ok").is_ok() && matches!(store.get("probe"), Ok(Some(v)) if v == "ok");
+        let _ = store.delete("probe");
+        ok
+    }
+}
+
+#[cfg(any(windows, target_os = "macos", target_os = "linux"))]
+impl SecretStore for KeyringStore {
+    fn get(&self, name: &str) -> Result<Option<String>, String> {
+        match Self::entry(name)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(format!("Lecture du secret '{}' dans le trousseau impossible: {}", name, e)),
+        }
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        Self::entry(name)?
+            .set_password(value)
+            .map_err(|e| format!("Ecriture du secret '{}' dans le trousseau impossible: {}", name, e))
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        match Self::entry(name)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("Suppression du secret '{}' impossible: {}", name, e)),
+        }
+    }
+}
+
+/// Secrets chiffrés dans `<dir>/secrets.enc.json`. La clé (`secrets.key`, lisible par
+/// l'utilisateur seul) est stockée hors du dossier partagé, dans l'état local de la machine
+/// ([`platform::local_state_root`]) : `%LOCALAPPDATA%` sous Windows, `~/.local/state` sous Linux.
+/// Une sauvegarde ou une copie du dossier de données n'emporte donc pas la clé.
+///
+/// Limites : sous macOS, la clé et le fichier sont dans le même dossier (le trousseau, toujours
+/// présent, est utilisé en priorité). `<dir>` peut suivre un profil itinérant, pas la clé : sur
+/// un autre poste, le fichier est illisible et les mots de passe doivent être ressaisis ; une
+/// nouvelle clé n'est créée que si le fichier ne contient aucun secret.
+pub(crate) struct EncryptedFileStore {
+    path: PathBuf,
+    key_path: PathBuf,
+    /// Emplacement d'une clé créée par une version précédente (Linux : à côté des données),
+    /// déplacée vers `key_path` au premier accès.
+    legacy_key_path: Option<PathBuf>,
+}
+
+impl EncryptedFileStore {
+    /// Fichier de `dir`, clé dans l'état local de la machine.
+    fn new(dir: &Path) -> Result<Self, String> {
+        let key_path = platform::local_state_root()?.join("com.wikitools.app").join(KEY_FILE);
+        let legacy_key_path = platform::local_data_root()?.join("com.wikitools.app").join(KEY_FILE);
+        let legacy_key_path = (legacy_key_path != key_path).then_some(legacy_key_path);
+        Ok(Self::at(dir, key_path, legacy_key_path))
+    }
+
+    /// Fichier de `dir` et clé `key_path` choisie par l'appelant.
+    pub(crate) fn at(dir: &Path, key_path: PathBuf, legacy_key_path: Option<PathBuf>) -> Self {
+        Self { path: dir.join(SECRETS_FILE), key_path, legacy_key_path }
+    }
+
+    /// Le fichier étant voisin du `db_config.json` partagé avec CollabTools, toute
+    /// lecture-modification-écriture se fait sous verrou.
+    fn lock(&self) -> Result<FileLock, String> {
+        FileLock::acquire(&self.path.with_extension("json.lock"), LOCK_TIMEOUT)
+    }
+
+    fn adopt_legacy_key(&self) -> Result<(), String> {
+        let Some(legacy) = self.legacy_key_path.as_ref().filter(|path| path.exists()) else {
+            return Ok(());
+        };
+        if self.key_path.exists() {
+            return Ok(());
+        }
+        if let Some(parent) = self.key_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Impossible de créer {:?}: {}", parent, e))?;
+        }
+        // Copie puis suppression : les deux dossiers peuvent être sur des volumes différents
+        let key = fs::read(legacy).map_err(|e| format!("Lecture de la clé {:?} impossible: {}", legacy, e))?;
+        write_atomic_private(&self.key_path, &key)?;
+        fs::remove_file(legacy).map_err(|e| format!("Suppression de l'ancienne clé {:?} impossible: {}", legacy, e))
+    }
+
+    fn cipher(&self, create: bool) -> Result<Aes256Gcm, String> {
+        self.adopt_legacy_key()?;
+        if !self.key_path.exists() {
+            if !create {
+                return Err(format!("Clé de déchiffrement introuvable ({:?}) : les mots de passe doivent être ressaisis", self.key_path));
+            }
+            // Une nouvelle clé rendrait les secrets existants définitivement illisibles
+            let existing = self.load()?.len();
+            if existing > 0 {
+                return Err(format!(
+                    "Clé de déchiffrement introuvable ({:?}) alors que {:?} contient {} secret(s). \
+                     Restaurez la clé, ou supprimez ce fichier pour ressaisir les mots de passe.",
+                    self.key_path, self.path, existing
+                ));
+            }
+            let mut key = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut key);
+            if let Some(parent) = self.key_path.parent() {
+                fs::create_dir_all(parent).map_err(|e| format!("Impossible de créer {:?}: {}", parent, e))?;
+            }
+            write_atomic_private(&self.key_path, BASE64.encode(key).as_bytes())?;
+        }
+        let encoded = fs::read_to_string(&self.key_path).map_err(|e| format!("Lecture de la clé impossible: {}", e))?;
+        let key = BASE64.decode(encoded.trim()).map_err(|_| "Clé de chiffrement corrompue".to_string())?;
+        Aes256Gcm::new_from_slice(&key).map_err(|_| "Clé de chiffrement invalide".to_string())
+    }
+
+    fn load(&self) -> Result<BTreeMap<String, String>, String> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let content = fs::read_to_string(&self.path).map_err(|e| format!("Lecture de {:?} impossible: {}", self.path, e))?;
+        serde_json::from_str(&content).map_err(|e| format!("{:?} illisible: {}", self.path, e))
+    }
+
+    fn save(&self, entries: &BTreeMap<String, String>) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(entries).map_err(|e| e.to_string())?;
+        write_atomic_private(&self.path, content.as_bytes())
+    }
+}
+
+impl SecretStore for EncryptedFileStore {
+    fn get(&self, name: &str) -> Result<Option<String>, String> {
+        let Some(encoded) = self.load()?.remove(name) else {
+            return Ok(None);
+        };
+        let raw = BASE64.decode(encoded).map_err(|_| format!("Secret '{}' corrompu", name))?;
+        if raw.len() <= NONCE_LEN {
+            return Err(format!("Secret '{}' corrompu", name));
+        }
+        let (nonce, ciphertext) = raw.split_at(NONCE_LEN);
+        // Le nom sert de données associées : un secret ne peut pas être déplacé sous un autre nom
+        let plain = self.cipher(false)?
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: name.as_bytes() })
+            .map_err(|_| format!("Déchiffrement du secret '{}' impossible (clé différente ?)", name))?;
+        String::from_utf8(plain).map(Some).map_err(|_| format!("Secret '{}' corrompu", name))
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        let _lock = self.lock()?;
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self.cipher(true)?
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: value.as_bytes(), aad: name.as_bytes() })
+            .map_err(|_| format!("Chiffrement du secret '{}' impossible", name))?;
+
+        let mut entries = self.load()?;
+        entries.insert(name.to_string(), BASE64.encode([nonce.as_slice(), &ciphertext].concat()));
+        self.save(&entries)
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        let _lock = self.lock()?;
+        let mut entries = self.load()?;
+        if entries.remove(name).is_some() {
+            self.save(&entries)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fichier et clé dans le dossier temporaire du test.
+    fn temp_store(dir: &Path) -> EncryptedFileStore {
+        EncryptedFileStore::at(dir, dir.join(KEY_FILE), None)
+    }
+
+    #[test]
+    fn key_is_kept_in_the_local_state_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = EncryptedFileStore::new(dir.path()).unwrap();
+        assert_eq!(store.path, dir.path().join(SECRETS_FILE));
+        assert_eq!(store.key_path, platform::local_state_root().unwrap().join("com.wikitools.app").join(KEY_FILE));
+        assert!(!store.key_path.starts_with(dir.path()));
+        if let Some(legacy) = &store.legacy_key_path {
+            assert_eq!(legacy, &platform::local_data_root().unwrap().join("com.wikitools.app").join(KEY_FILE));
+        }
+    }
+
+    #[test]
+    fn set_get_and_delete_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = temp_store(dir.path());
+        assert_eq!(store.get("db.x.app_password"), Ok(None));
+
+        store.set("db.x.app_password", "p@ss: é").unwrap();
+        store.set("db.x.postgres_password", "autre").unwrap();
+        assert_eq!(store.get("db.x.app_password"), Ok(Some("p@ss: é".to_string())));
+        assert!(!fs::read_to_string(dir.path().join(SECRETS_FILE)).unwrap().contains("p@ss"));
+
+        store.delete("db.x.app_password").unwrap();
+        assert_eq!(store.get("db.x.app_password"), Ok(None));
+        assert_eq!(store.get("db.x.postgres_password"), Ok(Some("autre".to_string())));
+    }
+
+    #[test]
+    fn secret_moved_under_another_name_does_not_decrypt() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = temp_store(dir.path());
+        store.set("db.x.app_password", "secret").unwrap();
+
+        let mut entries = store.load().unwrap();
+        let moved = entries.remove("db.x.app_password").unwrap();
+        entries.insert("db.x.postgres_password".into(), moved);
+        store.save(&entries).unwrap();
+
+        let error = store.get("db.x.postgres_password").unwrap_err();
+        assert!(error.contains("Déchiffrement"), "{}", error);
+    }
+
+    #[test]
+    fn lost_key_is_not_replaced_while_secrets_remain() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = temp_store(dir.path());
+        store.set("db.x.app_password", "secret").unwrap();
+        fs::remove_file(&store.key_path).unwrap();
+
+        let Err(error) = store.cipher(true) else {
+            panic!("une nouvelle clé a été créée");
+        };
+        assert!(error.contains("1 secret(s)"), "{}", error);
+        assert!(store.set("db.x.postgres_password", "autre").is_err());
+        assert!(!store.key_path.exists());
+
+        // Une fois le fichier vidé, une nouvelle clé peut être créée
+        store.delete("db.x.app_password").unwrap();
+        store.set("db.x.postgres_password", "autre").unwrap();
+        assert_eq!(store.get("db.x.postgres_password"), Ok(Some("autre".to_string())));
+    }
+
+    #[test]
+    fn missing_key_asks_to_re_enter_passwords() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = temp_store(dir.path());
+        store.set("db.x.app_password", "secret").unwrap();
+        fs::remove_file(&store.key_path).unwrap();
+
+        let error = store.get("db.x.app_password").unwrap_err();
+        assert!(error.contains("ressaisis"), "{}", error);
+    }
+
+    #[test]
+    fn key_from_the_previous_location_is_moved() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = temp_store(dir.path());
+        legacy.set("db.x.app_password", "secret").unwrap();
+
+        let store = EncryptedFileStore::at(dir.path(), dir.path().join("state").join(KEY_FILE), Some(legacy.key_path.clone()));
+        assert_eq!(store.get("db.x.app_password"), Ok(Some("secret".to_string())));
+        assert!(store.key_path.exists());
+        assert!(!legacy.key_path.exists());
+    }
+}
@@ -0,0 +1,183 @@
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backup::BackupSettings;
+use crate::fsutil::write_atomic;
+
+/// Port historique de Wiki.js, préféré au premier lancement pour ne pas casser
+/// les installations dont la "Site URL" vaut déjà http://localhost:3000.
+const PREFERRED_WIKI_PORT: u16 = 3000;
+
+/// Réglages propres au launcher WikiTools (<AppData>/com.wikitools.app/launcher.json).
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct LauncherSettings {
+    /// Port imposé pour Wiki.js. `None` : un port libre est choisi automatiquement.
+    #[serde(default)]
+    pub wiki_port: Option<u16>,
+    /// Dernier port choisi automatiquement, réutilisé tant qu'il est libre
+    /// (la "Site URL" de Wiki.js reste ainsi stable d'un lancement à l'autre).
+    #[serde(default)]
+    pub last_wiki_port: Option<u16>,
+    #[serde(default)]
+    pub backup: BackupSettings,
+    #[serde(default)]
+    pub shutdown: ShutdownSettings,
+}
+
+/// Sort du cluster PostgreSQL embarqué à la fermeture de l'application.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownPolicy {
+    /// Toujours arrêter le cluster, même démarré ailleurs ou encore utilisé par CollabTools.
+    Always,
+    /// Ne jamais l'arrêter : seul notre bail est retiré.
+    Never,
+    /// L'arrêter s'il a été démarré via le registre et que nous sommes son dernier utilisateur.
+    #[default]
+    OnlyIfOwned,
+}
+
+/// Réglages de l'arrêt des serveurs (section `shutdown` de `launcher.json`).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ShutdownSettings {
+    pub postgres: ShutdownPolicy,
+    /// Délai laissé à Wiki.js pour s'arrêter proprement avant d'être tué.
+    pub wiki_grace_secs: u64,
+}
+
+impl Default for ShutdownSettings {
+    fn default() -> Self {
+        Self { postgres: ShutdownPolicy::OnlyIfOwned, wiki_grace_secs: 10 }
+    }
+}
+
+impl LauncherSettings {
+    fn path(app_dir: &Path) -> PathBuf {
+        app_dir.join("launcher.json")
+    }
+
+    /// Charge les réglages ; un fichier absent donne les valeurs par défaut.
+    pub fn load(app_dir: &Path) -> Result<Self, String> {
+        let path = Self::path(app_dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path).map_err(|e| format!("Echec lecture {:?}: {}", path, e))?;
+        serde_json::from_str(&content).map_err(|e| format!("Echec parse {:?}: {}", path, e))
+    }
+
+    pub fn save(&self, app_dir: &Path) -> Result<(), String> {
+        fs::create_dir_all(app_dir).map_err(|e| format!("Impossible de créer {:?}: {}", app_dir, e))?;
+        let path = Self::path(app_dir);
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        write_atomic(&path, content.as_bytes())
+    }
+
+    /// Détermine le port de Wiki.js : port configuré, sinon dernier port utilisé,
+    /// sinon 3000, sinon un port libre attribué par l'OS.
+    pub fn resolve_wiki_port(&mut self) -> Result<u16, String> {
+        if let Some(port) = self.wiki_port {
+            if !is_port_free(port) {
+                return Err(format!("Le port {} configuré pour Wiki.js est déjà utilisé", port));
+            }
+            return Ok(port);
+        }
+
+        let port = self.last_wiki_port
+            .into_iter()
+            .chain(std::iter::once(PREFERRED_WIKI_PORT))
+            .find(|port| is_port_free(*port))
+            .map_or_else(free_port, Ok)?;
+        self.last_wiki_port = Some(port);
+        Ok(port)
+    }
+}
+
+pub fn is_port_free(port: u16) -> bool {
+    TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+pub fn free_port() -> Result<u16, String> {
+    TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| format!("Aucun port libre disponible: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Port occupé tant que l'écouteur renvoyé est vivant.
+    fn busy_port() -> (TcpListener, u16) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        (listener, port)
+    }
+
+    #[test]
+    fn configured_port_is_used_or_reported_busy() {
+        let (_listener, busy) = busy_port();
+        let mut settings = LauncherSettings { wiki_port: Some(busy), ..Default::default() };
+        assert!(settings.resolve_wiki_port().unwrap_err().contains(&busy.to_string()));
+
+        let free = free_port().unwrap();
+        let mut settings = LauncherSettings { wiki_port: Some(free), ..Default::default() };
+        assert_eq!(settings.resolve_wiki_port(), Ok(free));
+        assert_eq!(settings.last_wiki_port, None);
+    }
+
+    #[test]
+    fn last_port_is_reused_while_free() {
+        let free = free_port().unwrap();
+        let mut settings = LauncherSettings { last_wiki_port: Some(free), ..Default::default() };
+        assert_eq!(settings.resolve_wiki_port(), Ok(free));
+        assert_eq!(settings.last_wiki_port, Some(free));
+    }
+
+    #[test]
+    fn busy_last_port_falls_back_to_another_port() {
+        let (_listener, busy) = busy_port();
+        let mut settings = LauncherSettings { last_wiki_port: Some(busy), ..Default::default() };
+        let port = settings.resolve_wiki_port().unwrap();
+        assert_ne!(port, busy);
+        assert_eq!(settings.last_wiki_port, Some(port));
+    }
+
+    #[test]
+    fn missing_file_gives_defaults_and_save_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = LauncherSettings::load(dir.path()).unwrap();
+        assert_eq!((settings.wiki_port, settings.last_wiki_port), (None, None));
+
+        let saved = LauncherSettings { last_wiki_port: Some(3001), ..Default::default() };
+        saved.save(dir.path()).unwrap();
+        assert_eq!(LauncherSettings::load(dir.path()).unwrap().last_wiki_port, Some(3001));
+    }
+
+    #[test]
+    fn shutdown_section_is_optional_and_partial() {
+        let settings: LauncherSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(settings.shutdown.postgres, ShutdownPolicy::OnlyIfOwned);
+        assert_eq!(settings.shutdown.wiki_grace_secs, 10);
+
+        let settings: LauncherSettings = serde_json::from_str(r#"{ "shutdown": { "postgres": "never" } }"#).unwrap();
+        assert_eq!(settings.shutdown.postgres, ShutdownPolicy::Never);
+        assert_eq!(settings.shutdown.wiki_grace_secs, 10);
+
+        let settings: LauncherSettings = serde_json::from_str(r#"{ "shutdown": { "wiki_grace_secs": 0 } }"#).unwrap();
+        assert_eq!(settings.shutdown.postgres, ShutdownPolicy::OnlyIfOwned);
+        assert_eq!(settings.shutdown.wiki_grace_secs, 0);
+    }
+
+    #[test]
+    fn unknown_shutdown_policy_is_rejected() {
+        assert!(serde_json::from_str::<LauncherSettings>(r#"{ "shutdown": { "postgres": "sometimes" } }"#).is_err());
+        let policy: ShutdownPolicy = serde_json::from_str(r#""only_if_owned""#).unwrap();
+        assert_eq!(policy, ShutdownPolicy::OnlyIfOwned);
+    }
+}
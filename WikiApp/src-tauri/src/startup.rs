@@ -0,0 +1,208 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde::Serialize;
+
+/// Etapes du démarrage, dans l'ordre où le launcher les traverse.
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupPhase {
+    LoadConfig,
+    Initdb,
+    Configure,
+    TemporaryStart,
+    CreateDatabase,
+    Permissions,
+    Finalize,
+    ClusterUpgrade,
+    DatabaseStart,
+    DatabaseCheck,
+    WikiBoot,
+    Ready,
+}
+
+impl StartupPhase {
+    /// Avancement global (en %) au début de l'étape.
+    pub fn percent(self) -> u8 {
+        match self {
+            StartupPhase::LoadConfig => 2,
+            StartupPhase::Initdb => 5,
+            StartupPhase::Configure => 15,
+            StartupPhase::TemporaryStart => 25,
+            StartupPhase::CreateDatabase => 35,
+            StartupPhase::Permissions => 45,
+            StartupPhase::Finalize => 50,
+            StartupPhase::ClusterUpgrade => 55,
+            StartupPhase::DatabaseStart => 60,
+            StartupPhase::DatabaseCheck => 70,
+            StartupPhase::WikiBoot => 80,
+            StartupPhase::Ready => 100,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StartupPhase::LoadConfig => "Lecture de la configuration",
+            StartupPhase::Initdb => "Initialisation du cluster PostgreSQL",
+            StartupPhase::Configure => "Configuration du serveur",
+            StartupPhase::TemporaryStart => "Démarrage temporaire",
+            StartupPhase::CreateDatabase => "Création de la base et attribution des droits",
+            StartupPhase::Permissions => "Sécurisation des fichiers",
+            StartupPhase::Finalize => "Finalisation",
+            StartupPhase::ClusterUpgrade => "Mise à niveau de PostgreSQL",
+            StartupPhase::DatabaseStart => "Démarrage de PostgreSQL",
+            StartupPhase::DatabaseCheck => "Vérification de la base de données",
+            StartupPhase::WikiBoot => "Démarrage de Wiki.js",
+            StartupPhase::Ready => "Prêt",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PhaseStatus {
+    Started,
+    Failed,
+}
+
+/// Evénement `startup-progress` envoyé au launcher React.
+#[derive(Serialize, Clone, Debug)]
+pub struct StartupEvent {
+    pub phase: StartupPhase,
+    pub status: PhaseStatus,
+    pub label: &'static str,
+    pub percent: u8,
+    pub elapsed_ms: u64,
+    pub error: Option<String>,
+}
+
+type Sink = Box<dyn Fn(&StartupEvent) + Send + Sync>;
+
+/// Publie l'avancement du démarrage ; le temps écoulé est mesuré depuis le début de la
+/// dernière [`StartupPhase::LoadConfig`] (nouvelle tentative, relance après un arrêt).
+pub struct StartupProgress {
+    started_at: Mutex<Instant>,
+    sink: Sink,
+}
+
+impl StartupProgress {
+    pub fn new(sink: impl Fn(&StartupEvent) + Send + Sync + 'static) -> Self {
+        Self { started_at: Mutex::new(Instant::now()), sink: Box::new(sink) }
+    }
+
+    fn emit(&self, phase: StartupPhase, status: PhaseStatus, error: Option<String>) {
+        (self.sink)(&StartupEvent {
+            phase,
+            status,
+            label: phase.label(),
+            percent: phase.percent(),
+            elapsed_ms: self.started_at.lock().unwrap().elapsed().as_millis() as u64,
+            error,
+        });
+    }
+
+    pub fn begin(&self, phase: StartupPhase) {
+        if phase == StartupPhase::LoadConfig {
+            *self.started_at.lock().unwrap() = Instant::now();
+        }
+        self.emit(phase, PhaseStatus::Started, None);
+    }
+
+    /// Signale l'échec d'une étape et renvoie l'erreur pour la propager avec `?`.
+    pub fn fail(&self, phase: StartupPhase, error: String) -> String {
+        self.emit(phase, PhaseStatus::Failed, Some(error.clone()));
+        error
+    }
+
+    /// Exécute une étape : événement de début, puis événement d'échec si `f` échoue.
+    pub fn step<T>(&self, phase: StartupPhase, f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+        self.begin(phase);
+        f().map_err(|e| self.fail(phase, e))
+    }
+
+    /// Variante asynchrone de [`StartupProgress::step`].
+    pub async fn step_async<T>(&self, phase: StartupPhase, f: impl Future<Output = Result<T, String>>) -> Result<T, String> {
+        self.begin(phase);
+        f.await.map_err(|e| self.fail(phase, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const PHASES: [StartupPhase; 12] = [
+        StartupPhase::LoadConfig,
+        StartupPhase::Initdb,
+        StartupPhase::Configure,
+        StartupPhase::TemporaryStart,
+        StartupPhase::CreateDatabase,
+        StartupPhase::Permissions,
+        StartupPhase::Finalize,
+        StartupPhase::ClusterUpgrade,
+        StartupPhase::DatabaseStart,
+        StartupPhase::DatabaseCheck,
+        StartupPhase::WikiBoot,
+        StartupPhase::Ready,
+    ];
+
+    /// Progression dont les événements sont conservés pour être examinés.
+    fn recording() -> (StartupProgress, Arc<Mutex<Vec<StartupEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        (StartupProgress::new(move |event| sink.lock().unwrap().push(event.clone())), events)
+    }
+
+    #[test]
+    fn percent_increases_across_phases() {
+        for pair in PHASES.windows(2) {
+            assert!(pair[0].percent() < pair[1].percent(), "{:?} -> {:?}", pair[0], pair[1]);
+        }
+        assert_eq!(StartupPhase::Ready.percent(), 100);
+    }
+
+    #[test]
+    fn failed_step_emits_started_then_failed() {
+        let (progress, events) = recording();
+        let result: Result<(), String> = progress.step(StartupPhase::Initdb, || Err("initdb a échoué".into()));
+        assert_eq!(result, Err("initdb a échoué".to_string()));
+        assert_eq!(progress.step(StartupPhase::Configure, || Ok(5)), Ok(5));
+
+        let events = events.lock().unwrap();
+        let seen: Vec<_> = events.iter().map(|e| (e.phase, e.status, e.error.as_deref())).collect();
+        assert_eq!(seen, [
+            (StartupPhase::Initdb, PhaseStatus::Started, None),
+            (StartupPhase::Initdb, PhaseStatus::Failed, Some("initdb a échoué")),
+            (StartupPhase::Configure, PhaseStatus::Started, None),
+        ]);
+        assert_eq!(events[0].label, StartupPhase::Initdb.label());
+        assert_eq!(events[0].percent, StartupPhase::Initdb.percent());
+    }
+
+    #[tokio::test]
+    async fn failed_async_step_emits_started_then_failed() {
+        let (progress, events) = recording();
+        let result: Result<(), String> = progress
+            .step_async(StartupPhase::DatabaseStart, async { Err("port occupé".to_string()) })
+            .await;
+        assert!(result.is_err());
+
+        let seen: Vec<_> = events.lock().unwrap().iter().map(|e| (e.status, e.error.clone())).collect();
+        assert_eq!(seen, [(PhaseStatus::Started, None), (PhaseStatus::Failed, Some("port occupé".to_string()))]);
+    }
+
+    #[test]
+    fn elapsed_time_restarts_with_the_configuration_phase() {
+        let (progress, events) = recording();
+        std::thread::sleep(Duration::from_millis(200));
+        progress.begin(StartupPhase::WikiBoot);
+        progress.begin(StartupPhase::LoadConfig);
+
+        let events = events.lock().unwrap();
+        assert!(events[0].elapsed_ms >= 200);
+        assert!(events[1].elapsed_ms < 200);
+    }
+}
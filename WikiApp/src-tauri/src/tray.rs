@@ -0,0 +1,103 @@
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager, Wry};
+
+const TRAY_ID: &str = "wikitools";
+
+/// Entrées du menu de l'icône de notification.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrayAction {
+    OpenWiki,
+    RestartWiki,
+    StopAll,
+    BackupNow,
+    Quit,
+}
+
+impl TrayAction {
+    const ALL: [TrayAction; 5] = [
+        TrayAction::OpenWiki,
+        TrayAction::RestartWiki,
+        TrayAction::StopAll,
+        TrayAction::BackupNow,
+        TrayAction::Quit,
+    ];
+
+    fn id(self) -> &'static str {
+        match self {
+            TrayAction::OpenWiki => "open_wiki",
+            TrayAction::RestartWiki => "restart_wiki",
+            TrayAction::StopAll => "stop_all",
+            TrayAction::BackupNow => "backup_now",
+            TrayAction::Quit => "quit",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            TrayAction::OpenWiki => "Ouvrir le wiki",
+            TrayAction::RestartWiki => "Redémarrer Wiki.js",
+            TrayAction::StopAll => "Tout arrêter",
+            TrayAction::BackupNow => "Sauvegarder maintenant",
+            TrayAction::Quit => "Quitter",
+        }
+    }
+}
+
+/// Ligne d'état (non cliquable) du menu, mise à jour par [`set_status`].
+struct StatusItem(MenuItem<Wry>);
+
+/// Crée l'icône de notification. Un clic gauche sur l'icône équivaut à « Ouvrir le wiki ».
+pub fn setup(app: &AppHandle, on_action: fn(&AppHandle, TrayAction)) -> tauri::Result<()> {
+    let status = MenuItem::with_id(app, "status", "Démarrage...", false, None::<&str>)?;
+    let items: Vec<MenuItem<Wry>> = TrayAction::ALL
+        .iter()
+        .map(|action| MenuItem::with_id(app, action.id(), action.label(), true, None::<&str>))
+        .collect::<tauri::Result<_>>()?;
+    let separator = PredefinedMenuItem::separator(app)?;
+    let separator_quit = PredefinedMenuItem::separator(app)?;
+    let menu = Menu::with_items(
+        app,
+        &[&items[0], &status, &separator, &items[1], &items[2], &items[3], &separator_quit, &items[4]],
+    )?;
+
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("WikiTools")
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .on_menu_event(move |app, event| {
+            if let Some(action) = TrayAction::ALL.into_iter().find(|action| event.id.as_ref() == action.id()) {
+                on_action(app, action);
+            }
+        })
+        .on_tray_icon_event(move |tray, event| {
+            if let TrayIconEvent::Click { button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event {
+                on_action(tray.app_handle(), TrayAction::OpenWiki);
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+    app.manage(StatusItem(status));
+    Ok(())
+}
+
+/// Met à jour la ligne d'état du menu et l'infobulle de l'icône.
+pub fn set_status(app: &AppHandle, text: &str) {
+    if let Some(status) = app.try_state::<StatusItem>() {
+        let _ = status.0.set_text(text);
+    }
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let _ = tray.set_tooltip(Some(format!("WikiTools — {}", text)));
+    }
+}
+
+/// Réaffiche la fenêtre principale (masquée à la fermeture).
+pub fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
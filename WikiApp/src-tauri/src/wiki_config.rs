@@ -0,0 +1,293 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_yaml::{Mapping, Value};
+
+use crate::fsutil::write_atomic;
+
+/// Variable d'environnement du processus Wiki.js portant le mot de passe de la base :
+/// `config.yml` (copié dans les sauvegardes) n'en contient que le motif `$(DB_PASS)`.
+pub const DB_PASS_ENV: &str = "DB_PASS";
+const DB_PASS_PLACEHOLDER: &str = "$(DB_PASS)";
+
+/// Paramètres de la base PostgreSQL utilisée par Wiki.js.
+pub struct WikiDbSettings {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub pass: String,
+    pub db: String,
+    /// Connexion chiffrée (serveur distant).
+    pub ssl: bool,
+    /// Vérifier le certificat du serveur (`verify-ca` / `verify-full`).
+    pub ssl_verify: bool,
+    /// Autorité de certification (PEM) ; sinon celles du système.
+    pub ca_file: Option<PathBuf>,
+}
+
+impl WikiDbSettings {
+    /// Valeur de [`DB_PASS_ENV`]. Wiki.js substitue le motif dans le texte avant de lire le
+    /// YAML : le mot de passe est passé entre guillemets, échappé (une chaîne JSON est un
+    /// scalaire YAML valide).
+    pub fn pass_env_value(&self) -> String {
+        serde_json::Value::from(self.pass.as_str()).to_string()
+    }
+}
+
+/// Valeurs gérées par le launcher dans le `config.yml` de Wiki.js.
+/// Toutes les autres clés du fichier sont conservées telles quelles.
+pub struct WikiServerSettings {
+    pub port: u16,
+    pub bind_ip: String,
+    pub data_path: PathBuf,
+    pub offline: bool,
+    pub body_parser_limit: String,
+    pub db: WikiDbSettings,
+}
+
+impl WikiServerSettings {
+    pub fn new(port: u16, data_path: PathBuf, db: WikiDbSettings) -> Self {
+        Self {
+            port,
+            bind_ip: "127.0.0.1".to_string(),
+            data_path,
+            offline: false,
+            body_parser_limit: "5mb".to_string(),
+            db,
+        }
+    }
+}
+
+/// Génère (ou met à jour) le `config.yml` de Wiki.js à `config_path`.
+///
+/// Si le fichier n'existe pas encore, `seed_path` (typiquement un `config.yml` édité à la main
+/// dans le dossier `wiki`) sert de base pour conserver les réglages existants.
+/// L'écriture est atomique (fichier temporaire puis renommage) et le résultat est relu et
+/// validé : en cas d'erreur, Wiki.js ne doit pas être lancé.
+pub fn write_wiki_config(config_path: &Path, seed_path: Option<&Path>, settings: &WikiServerSettings) -> Result<(), String> {
+    let source = if config_path.exists() {
+        Some(config_path)
+    } else {
+        seed_path.filter(|p| p.exists())
+    };
+
+    let mut root = match source {
+        Some(path) => read_mapping(path)?,
+        None => Mapping::new(),
+    };
+    apply_settings(&mut root, settings);
+
+    let yaml = serde_yaml::to_string(&Value::Mapping(root))
+        .map_err(|e| format!("Echec sérialisation config.yml: {}", e))?;
+    validate_wiki_config(&yaml)?;
+
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Impossible de créer {:?}: {}", parent, e))?;
+    }
+    fs::create_dir_all(&settings.data_path)
+        .map_err(|e| format!("Impossible de créer le dossier de données Wiki.js {:?}: {}", settings.data_path, e))?;
+
+    write_atomic(config_path, yaml.as_bytes())?;
+
+    // Relecture depuis le disque : on valide ce que Wiki.js lira réellement
+    let written = fs::read_to_string(config_path).map_err(|e| format!("Echec relecture config.yml: {}", e))?;
+    validate_wiki_config(&written)
+}
+
+fn read_mapping(path: &Path) -> Result<Mapping, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Echec lecture {:?}: {}", path, e))?;
+    if content.trim().is_empty() {
+        return Ok(Mapping::new());
+    }
+    match serde_yaml::from_str(&content) {
+        Ok(Value::Mapping(map)) => Ok(map),
+        Ok(Value::Null) => Ok(Mapping::new()),
+        Ok(_) => Err(format!("{:?} n'est pas un document YAML de type clé/valeur", path)),
+        Err(e) => Err(format!("{:?} est invalide, corrigez-le ou supprimez-le: {}", path, e)),
+    }
+}
+
+fn apply_settings(root: &mut Mapping, settings: &WikiServerSettings) {
+    root.insert("port".into(), settings.port.into());
+    root.insert("bindIP".into(), settings.bind_ip.clone().into());
+    root.insert("dataPath".into(), settings.data_path.to_string_lossy().to_string().into());
+    root.insert("offline".into(), settings.offline.into());
+    root.insert("bodyParserLimit".into(), settings.body_parser_limit.clone().into());
+
+    if !root.get("db").is_some_and(Value::is_mapping) {
+        root.insert("db".into(), Value::Mapping(Mapping::new()));
+    }
+    let Some(Value::Mapping(db)) = root.get_mut("db") else {
+        return;
+    };
+    db.insert("type".into(), "postgres".into());
+    db.insert("host".into(), settings.db.host.clone().into());
+    db.insert("port".into(), settings.db.port.into());
+    db.insert("user".into(), settings.db.user.clone().into());
+    db.insert("pass".into(), DB_PASS_PLACEHOLDER.into());
+    db.insert("db".into(), settings.db.db.clone().into());
+    db.insert("ssl".into(), settings.db.ssl.into());
+    if settings.db.ssl {
+        // `auto: false` : Wiki.js utilise ces options telles quelles pour le client pg
+        let mut ssl_options = Mapping::new();
+        ssl_options.insert("auto".into(), false.into());
+        ssl_options.insert("rejectUnauthorized".into(), settings.db.ssl_verify.into());
+        if let Some(ca_file) = &settings.db.ca_file {
+            ssl_options.insert("ca".into(), ca_file.to_string_lossy().to_string().into());
+        }
+        db.insert("sslOptions".into(), Value::Mapping(ssl_options));
+    } else {
+        // Options d'un précédent réglage TLS : Wiki.js les appliquerait malgré `ssl: false`
+        db.remove("sslOptions");
+    }
+    db.entry("schema".into()).or_insert("public".into());
+}
+
+/// Vérifie qu'un `config.yml` contient tout ce dont Wiki.js a besoin pour démarrer.
+pub fn validate_wiki_config(yaml: &str) -> Result<(), String> {
+    let root: Value = serde_yaml::from_str(yaml).map_err(|e| format!("config.yml invalide: {}", e))?;
+
+    let port = root.get("port").and_then(Value::as_u64).unwrap_or(0);
+    if !(1..=65535).contains(&port) {
+        return Err("config.yml invalide: 'port' doit être compris entre 1 et 65535".into());
+    }
+    for key in ["bindIP", "dataPath"] {
+        if root.get(key).and_then(Value::as_str).is_none_or(str::is_empty) {
+            return Err(format!("config.yml invalide: '{}' manquant", key));
+        }
+    }
+    let limit = root.get("bodyParserLimit").and_then(Value::as_str).unwrap_or("");
+    if !is_valid_size(limit) {
+        return Err(format!("config.yml invalide: 'bodyParserLimit' incorrect ({:?})", limit));
+    }
+
+    let db = root.get("db").ok_or("config.yml invalide: section 'db' manquante")?;
+    if db.get("type").and_then(Value::as_str) != Some("postgres") {
+        return Err("config.yml invalide: 'db.type' doit valoir 'postgres'".into());
+    }
+    for key in ["host", "user", "pass", "db"] {
+        if db.get(key).and_then(Value::as_str).is_none_or(str::is_empty) {
+            return Err(format!("config.yml invalide: 'db.{}' manquant", key));
+        }
+    }
+    let db_port = db.get("port").and_then(Value::as_u64).unwrap_or(0);
+    if !(1..=65535).contains(&db_port) {
+        return Err("config.yml invalide: 'db.port' doit être compris entre 1 et 65535".into());
+    }
+    Ok(())
+}
+
+/// Format accepté par body-parser : nombre suivi d'une unité optionnelle (`5mb`, `512kb`...).
+fn is_valid_size(value: &str) -> bool {
+    let lower = value.to_ascii_lowercase();
+    let digits = lower.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = &lower[digits.len()..];
+    !digits.is_empty()
+        && digits.chars().all(|c| c.is_ascii_digit())
+        && ["", "b", "kb", "mb", "gb"].contains(&unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(data_path: PathBuf, pass: &str, ssl: bool) -> WikiServerSettings {
+        WikiServerSettings::new(3001, data_path, WikiDbSettings {
+            host: "127.0.0.1".into(),
+            port: 5433,
+            user: "app_user".into(),
+            pass: pass.into(),
+            db: "wiki".into(),
+            ssl,
+            ssl_verify: false,
+            ca_file: None,
+        })
+    }
+
+    fn read_yaml(path: &Path) -> Value {
+        serde_yaml::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn seed_keys_are_kept_and_managed_keys_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let seed = dir.path().join("seed.yml");
+        fs::write(&seed, "port: 3000\nlogLevel: warn\ndb:\n  type: sqlite\n  schema: wiki\n  pass: ancien\n").unwrap();
+        let config = dir.path().join("config.yml");
+
+        write_wiki_config(&config, Some(&seed), &settings(dir.path().join("data"), "secret", false)).unwrap();
+        let written = read_yaml(&config);
+        assert_eq!(written["port"], 3001);
+        assert_eq!(written["logLevel"], "warn");
+        assert_eq!(written["db"]["type"], "postgres");
+        assert_eq!(written["db"]["schema"], "wiki");
+        assert_eq!(written["db"]["sslOptions"], Value::Null);
+        assert!(dir.path().join("data").is_dir());
+
+        // Fichier existant : il l'emporte sur le modèle
+        fs::write(&seed, "logLevel: debug\n").unwrap();
+        write_wiki_config(&config, Some(&seed), &settings(dir.path().join("data"), "secret", true)).unwrap();
+        let written = read_yaml(&config);
+        assert_eq!(written["logLevel"], "warn");
+        assert_eq!(written["db"]["sslOptions"]["rejectUnauthorized"], false);
+
+        // TLS désactivé ensuite : les anciennes options disparaissent
+        write_wiki_config(&config, Some(&seed), &settings(dir.path().join("data"), "secret", false)).unwrap();
+        let written = read_yaml(&config);
+        assert_eq!(written["db"]["ssl"], false);
+        assert_eq!(written["db"]["sslOptions"], Value::Null);
+    }
+
+    #[test]
+    fn password_stays_out_of_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.yml");
+        let settings = settings(dir.path().join("data"), "p@ss: #\"x\\'é", false);
+        write_wiki_config(&config, None, &settings).unwrap();
+
+        let content = fs::read_to_string(&config).unwrap();
+        assert!(!content.contains("p@ss"));
+        // Substitution faite par Wiki.js avant la lecture du YAML
+        let loaded: Value = serde_yaml::from_str(&content.replace(DB_PASS_PLACEHOLDER, &settings.db.pass_env_value())).unwrap();
+        assert_eq!(loaded["db"]["pass"], "p@ss: #\"x\\'é");
+    }
+
+    #[test]
+    fn invalid_seed_is_reported_and_nothing_is_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let seed = dir.path().join("seed.yml");
+        fs::write(&seed, "port: [3000\n").unwrap();
+        let config = dir.path().join("config.yml");
+
+        let error = write_wiki_config(&config, Some(&seed), &settings(dir.path().join("data"), "secret", false)).unwrap_err();
+        assert!(error.contains("invalide"), "{}", error);
+        assert!(!config.exists());
+    }
+
+    #[test]
+    fn validation_reports_missing_or_invalid_values() {
+        let valid = "port: 3001\nbindIP: 127.0.0.1\ndataPath: ./data\nbodyParserLimit: 5mb\ndb:\n  type: postgres\n  host: 127.0.0.1\n  port: 5433\n  user: app_user\n  pass: $(DB_PASS)\n  db: wiki\n";
+        assert_eq!(validate_wiki_config(valid), Ok(()));
+        for (from, to, expected) in [
+            ("port: 3001", "port: 0", "'port'"),
+            ("bindIP: 127.0.0.1\n", "", "'bindIP'"),
+            ("5mb", "5 mo", "'bodyParserLimit'"),
+            ("type: postgres", "type: sqlite", "'db.type'"),
+            ("user: app_user", "user: ''", "'db.user'"),
+            ("port: 5433", "port: 70000", "'db.port'"),
+        ] {
+            let error = validate_wiki_config(&valid.replace(from, to)).unwrap_err();
+            assert!(error.contains(expected), "{} : {}", expected, error);
+        }
+    }
+
+    #[test]
+    fn body_parser_sizes() {
+        for valid in ["5mb", "512kb", "1GB", "100", "10b"] {
+            assert!(is_valid_size(valid), "{}", valid);
+        }
+        for invalid in ["", "mb", "5 mb", "5tb", "-1mb", "1.5mb"] {
+            assert!(!is_valid_size(invalid), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,602 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::logs::RotatingLog;
+use crate::platform;
+
+const LOG_MAX_BYTES: u64 = 5 * 1024 * 1024;
+const LOG_MAX_FILES: usize = 5;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const DEFAULT_STOP_GRACE: Duration = Duration::from_secs(10);
+
+/// Fabrique la commande `node server` ; rappelée à chaque (re)démarrage pour
+/// régénérer la configuration.
+pub type Launcher = Box<dyn Fn() -> Result<Command, String> + Send + Sync>;
+type Listener = Box<dyn Fn(&WikiStatus) + Send + Sync>;
+
+/// Politique de redémarrage automatique de Wiki.js.
+#[derive(Clone)]
+pub struct RestartPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Nombre maximal de crashs tolérés dans `crash_window` avant abandon.
+    pub max_crashes: usize,
+    pub crash_window: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            max_crashes: 5,
+            crash_window: Duration::from_secs(120),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WikiState {
+    Stopped,
+    Starting,
+    Running,
+    Backoff,
+    CrashLoop,
+    /// Premier lancement impossible (Node.js introuvable, configuration invalide) : aucun
+    /// redémarrage n'est tenté.
+    Failed,
+}
+
+impl WikiState {
+    /// Processus lancé ou sur le point de l'être (y compris en attente de redémarrage).
+    pub fn is_active(self) -> bool {
+        matches!(self, WikiState::Starting | WikiState::Running | WikiState::Backoff)
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct WikiStatus {
+    pub state: WikiState,
+    pub pid: Option<u32>,
+    pub restarts: u32,
+    pub last_exit_code: Option<i32>,
+    /// Horodatage Unix (secondes) de la dernière sortie du processus.
+    pub last_exit_at: Option<u64>,
+    pub last_error: Option<String>,
+    pub log_file: PathBuf,
+}
+
+struct Inner {
+    policy: RestartPolicy,
+    status: Mutex<WikiStatus>,
+    child: Mutex<Option<Child>>,
+    launcher: Mutex<Option<Arc<Launcher>>>,
+    listener: Mutex<Option<Listener>>,
+    log: Mutex<Option<RotatingLog>>,
+    /// Délai laissé à Node par [`WikiSupervisor::stop`] avant l'arrêt forcé.
+    stop_grace: Mutex<Duration>,
+    /// Incrémenté à chaque start/stop : un thread de surveillance d'une génération
+    /// précédente s'arrête de lui-même.
+    generation: AtomicU64,
+}
+
+/// Supervise le processus Node de Wiki.js : capture des logs, redémarrage avec
+/// backoff exponentiel, détection de boucle de crash et arrêt propre.
+#[derive(Clone)]
+pub struct WikiSupervisor {
+    inner: Arc<Inner>,
+}
+
+impl WikiSupervisor {
+    pub fn new(log_dir: PathBuf, policy: RestartPolicy) -> Self {
+        let log_file = log_dir.join("wiki.log");
+        let log = RotatingLog::open(log_file.clone(), LOG_MAX_BYTES, LOG_MAX_FILES)
+            .map_err(|e| eprintln!("⚠️ Logs Wiki.js désactivés: {}", e))
+            .ok();
+
+        Self {
+            inner: Arc::new(Inner {
+                policy,
+                status: Mutex::new(WikiStatus {
+                    state: WikiState::Stopped,
+                    pid: None,
+                    restarts: 0,
+                    last_exit_code: None,
+                    last_exit_at: None,
+                    last_error: None,
+                    log_file,
+                }),
+                child: Mutex::new(None),
+                launcher: Mutex::new(None),
+                listener: Mutex::new(None),
+                log: Mutex::new(log),
+                stop_grace: Mutex::new(DEFAULT_STOP_GRACE),
+                generation: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Appelé à chaque changement d'état (pour émettre un événement vers l'UI).
+    pub fn set_listener(&self, listener: impl Fn(&WikiStatus) + Send + Sync + 'static) {
+        *self.inner.listener.lock().unwrap() = Some(Box::new(listener));
+    }
+
+    /// Délai d'arrêt propre utilisé par [`stop`](Self::stop) (réglages d'arrêt).
+    pub fn set_stop_grace(&self, grace: Duration) {
+        *self.inner.stop_grace.lock().unwrap() = grace;
+    }
+
+    pub fn status(&self) -> WikiStatus {
+        self.inner.status.lock().unwrap().clone()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.status().state.is_active()
+    }
+
+    /// Démarre la supervision ; `false` (sans effet) si Wiki.js est déjà actif. Deux appels
+    /// simultanés ne lancent qu'un seul processus.
+    pub fn start(&self, launcher: Launcher) -> Result<bool, String> {
+        if !self.inner.claim_start() {
+            return Ok(false);
+        }
+        *self.inner.launcher.lock().unwrap() = Some(Arc::new(launcher));
+        self.spawn_monitor().map(|()| true)
+    }
+
+    /// Arrête (voir [`stop`](Self::stop)) puis relance Wiki.js avec le dernier lanceur connu.
+    pub fn restart(&self) -> Result<(), String> {
+        if self.inner.launcher.lock().unwrap().is_none() {
+            return Err("Wiki.js n'a jamais été démarré".into());
+        }
+        self.stop();
+        if !self.inner.claim_start() {
+            return Ok(()); // Relancé entre-temps par un autre appel
+        }
+        self.spawn_monitor()
+    }
+
+    /// Arrête Wiki.js et la supervision (aucun redémarrage automatique ensuite), en
+    /// laissant à Node le délai configuré par [`set_stop_grace`](Self::set_stop_grace).
+    /// Bloquant : à appeler hors du runtime async.
+    pub fn stop(&self) {
+        let grace = *self.inner.stop_grace.lock().unwrap();
+        self.shutdown(grace);
+    }
+
+    /// Laisse jusqu'à `grace` à Node pour s'arrêter proprement (SIGTERM) avant de le
+    /// tuer ; `Duration::ZERO` le tue immédiatement.
+    pub fn shutdown(&self, grace: Duration) {
+        self.inner.generation.fetch_add(1, Ordering::SeqCst);
+        let child = self.inner.child.lock().unwrap().take();
+        if let Some(mut child) = child {
+            self.inner.log_line(&format!("=== Arrêt de Wiki.js demandé (pid {})", child.id()));
+            if grace.is_zero() || !platform::request_termination(child.id()) || !wait_with_timeout(&mut child, grace) {
+                if !grace.is_zero() {
+                    self.inner.log_line(&format!("=== Wiki.js toujours actif après {} s, arrêt forcé", grace.as_secs()));
+                }
+                let _ = child.kill();
+            }
+            let _ = child.wait();
+        }
+        self.inner.update(|s| {
+            s.state = WikiState::Stopped;
+            s.pid = None;
+        });
+    }
+
+    /// Lance le premier processus et sa surveillance ; l'état doit avoir été réservé par
+    /// [`Inner::claim_start`].
+    fn spawn_monitor(&self) -> Result<(), String> {
+        let generation = self.inner.generation.fetch_add(1, Ordering::SeqCst) + 1;
+
+        // Premier lancement synchrone pour remonter immédiatement les erreurs de configuration
+        let first = self.inner.launch(generation).inspect_err(|e| {
+            if self.inner.is_current(generation) {
+                self.inner.update(|s| {
+                    s.state = WikiState::Failed;
+                    s.last_error = Some(e.clone());
+                });
+            }
+        })?;
+        let inner = self.inner.clone();
+        thread::spawn(move || inner.monitor(generation, first));
+        Ok(())
+    }
+}
+
+/// Attend la fin du processus au plus `timeout` ; `true` s'il s'est terminé.
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) | Err(_) => return true,
+            Ok(None) if Instant::now() >= deadline => return false,
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+impl Inner {
+    fn is_current(&self, generation: u64) -> bool {
+        self.generation.load(Ordering::SeqCst) == generation
+    }
+
+    fn update(&self, f: impl FnOnce(&mut WikiStatus)) {
+        let snapshot = {
+            let mut status = self.status.lock().unwrap();
+            f(&mut status);
+            status.clone()
+        };
+        self.notify(&snapshot);
+    }
+
+    /// Passe à `Starting` si Wiki.js n'est pas actif, vérification et changement d'état se
+    /// faisant sous le même verrou ; `false` si un autre démarrage a déjà eu lieu.
+    fn claim_start(&self) -> bool {
+        let snapshot = {
+            let mut status = self.status.lock().unwrap();
+            if status.state.is_active() {
+                return false;
+            }
+            status.state = WikiState::Starting;
+            status.restarts = 0;
+            status.last_error = None;
+            status.clone()
+        };
+        self.notify(&snapshot);
+        true
+    }
+
+    fn notify(&self, status: &WikiStatus) {
+        if let Some(listener) = self.listener.lock().unwrap().as_ref() {
+            listener(status);
+        }
+    }
+
+    fn log_line(&self, line: &str) {
+        if let Some(log) = self.log.lock().unwrap().as_mut() {
+            log.write_line(line);
+        }
+    }
+
+    /// Lance le processus et branche la capture de stdout/stderr.
+    fn launch(self: &Arc<Self>, generation: u64) -> Result<Instant, String> {
+        let launcher = self.launcher.lock().unwrap().clone()
+            .ok_or("Aucun lanceur Wiki.js configuré")?;
+
+        let result = launcher().and_then(|mut cmd| {
+            cmd.stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| format!("Impossible de lancer Node.js: {}", e))
+        });
+        let mut child = match result {
+            Ok(child) => child,
+            Err(e) => {
+                self.log_line(&format!("=== Echec du lancement: {}", e));
+                return Err(e);
+            }
+        };
+
+        let pid = child.id();
+        self.log_line(&format!("=== Wiki.js démarré (pid {})", pid));
+        if let Some(stdout) = child.stdout.take() {
+            self.capture(stdout, "");
+        }
+        if let Some(stderr) = child.stderr.take() {
+            self.capture(stderr, "[stderr] ");
+        }
+
+        {
+            // Vérification sous le verrou : stop() incrémente la génération avant de le prendre
+            let mut guard = self.child.lock().unwrap();
+            if !self.is_current(generation) {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err("Démarrage de Wiki.js annulé".into());
+            }
+            *guard = Some(child);
+        }
+        self.update(|s| {
+            s.state = WikiState::Running;
+            s.pid = Some(pid);
+        });
+        Ok(Instant::now())
+    }
+
+    fn capture(self: &Arc<Self>, stream: impl Read + Send + 'static, prefix: &'static str) {
+        let inner = self.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                match line {
+                    Ok(line) => inner.log_line(&format!("{}{}", prefix, line)),
+                    Err(_) => break,
+                }
+            }
+        });
+    }
+
+    /// Boucle de surveillance : attend la sortie du processus et applique la politique de redémarrage.
+    fn monitor(self: Arc<Self>, generation: u64, mut started_at: Instant) {
+        let mut backoff = self.policy.initial_backoff;
+        let mut crashes: VecDeque<Instant> = VecDeque::new();
+
+        loop {
+            let exit_code = match self.wait_exit(generation) {
+                Some(code) => code,
+                None => return, // stop() ou restart() a pris la main
+            };
+
+            let now = Instant::now();
+            let exited_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).ok();
+            println!("⚠️ Wiki.js s'est arrêté (code {:?})", exit_code);
+            self.log_line(&format!("=== Wiki.js s'est arrêté (code {:?})", exit_code));
+
+            // Un processus resté stable assez longtemps remet le backoff à zéro
+            if now.duration_since(started_at) >= self.policy.crash_window {
+                backoff = self.policy.initial_backoff;
+            }
+            crashes.push_back(now);
+            while crashes.front().is_some_and(|t| now.duration_since(*t) > self.policy.crash_window) {
+                crashes.pop_front();
+            }
+
+            if crashes.len() > self.policy.max_crashes {
+                let message = format!(
+                    "Wiki.js a planté {} fois en {} s, redémarrage automatique abandonné",
+                    crashes.len(),
+                    self.policy.crash_window.as_secs()
+                );
+                self.log_line(&format!("=== {}", message));
+                self.update(|s| {
+                    s.state = WikiState::CrashLoop;
+                    s.pid = None;
+                    s.last_exit_code = exit_code;
+                    s.last_exit_at = exited_at;
+                    s.last_error = Some(message.clone());
+                });
+                return;
+            }
+
+            self.update(|s| {
+                s.state = WikiState::Backoff;
+                s.pid = None;
+                s.last_exit_code = exit_code;
+                s.last_exit_at = exited_at;
+            });
+            self.log_line(&format!("=== Redémarrage dans {} ms", backoff.as_millis()));
+            if !self.sleep_unless_cancelled(generation, backoff) {
+                return;
+            }
+            backoff = (backoff * 2).min(self.policy.max_backoff);
+
+            self.update(|s| {
+                s.state = WikiState::Starting;
+                s.restarts += 1;
+            });
+            match self.launch(generation) {
+                Ok(at) => started_at = at,
+                Err(_) if !self.is_current(generation) => return,
+                // Echec de lancement : compté comme un crash au tour suivant, qui sera retenté
+                Err(e) => {
+                    started_at = Instant::now();
+                    self.update(|s| {
+                        s.state = WikiState::Backoff;
+                        s.last_error = Some(e);
+                    });
+                }
+            }
+        }
+    }
+
+    /// Attend la fin du processus courant. `None` si la supervision a été annulée.
+    /// `Some(None)` si le processus s'est terminé sans code (tué par un signal ou lancement échoué).
+    fn wait_exit(&self, generation: u64) -> Option<Option<i32>> {
+        loop {
+            if !self.is_current(generation) {
+                return None;
+            }
+            {
+                let mut guard = self.child.lock().unwrap();
+                match guard.as_mut() {
+                    Some(child) => match child.try_wait() {
+                        Ok(Some(status)) => {
+                            guard.take();
+                            return Some(status.code());
+                        }
+                        Ok(None) => {}
+                        Err(_) => {
+                            guard.take();
+                            return Some(None);
+                        }
+                    },
+                    None => return Some(None),
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn sleep_unless_cancelled(&self, generation: u64, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while Instant::now() < deadline {
+            if !self.is_current(generation) {
+                return false;
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - Instant::now()));
+        }
+        self.is_current(generation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Processus qui se termine aussitôt avec `code`.
+    fn exit_command(code: i32) -> Command {
+        let mut cmd = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+        cmd.arg(if cfg!(windows) { "/C" } else { "-c" }).arg(format!("exit {}", code));
+        cmd
+    }
+
+    fn sleep_command() -> Command {
+        let mut cmd = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+        if cfg!(windows) {
+            cmd.args(["/C", "ping -n 30 127.0.0.1 > NUL"]);
+        } else {
+            cmd.args(["-c", "sleep 30"]);
+        }
+        cmd
+    }
+
+    /// Lanceur comptant ses appels ; `command` reçoit le numéro de l'appel (à partir de 0).
+    fn counting(command: impl Fn(usize) -> Result<Command, String> + Send + Sync + 'static) -> (Launcher, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        (Box::new(move || command(counter.fetch_add(1, Ordering::SeqCst))), calls)
+    }
+
+    fn policy(initial_ms: u64, max_ms: u64, max_crashes: usize) -> RestartPolicy {
+        RestartPolicy {
+            initial_backoff: Duration::from_millis(initial_ms),
+            max_backoff: Duration::from_millis(max_ms),
+            max_crashes,
+            crash_window: Duration::from_secs(60),
+        }
+    }
+
+    fn wait_for(supervisor: &WikiSupervisor, state: WikiState) -> WikiStatus {
+        let deadline = Instant::now() + Duration::from_secs(15);
+        loop {
+            let status = supervisor.status();
+            if status.state == state {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "état {:?} attendu, {:?} observé", state, status.state);
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit_then_gives_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let supervisor = WikiSupervisor::new(dir.path().to_path_buf(), policy(10, 40, 4));
+        let (launcher, calls) = counting(|_| Ok(exit_command(3)));
+        supervisor.start(launcher).unwrap();
+
+        let status = wait_for(&supervisor, WikiState::CrashLoop);
+        assert_eq!(status.last_exit_code, Some(3));
+        assert!(status.last_exit_at.is_some());
+        assert_eq!(status.restarts, 4);
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+        assert!(!supervisor.is_active());
+
+        let log = std::fs::read_to_string(&status.log_file).unwrap();
+        let delays: Vec<&str> = log.lines().filter_map(|line| line.split("Redémarrage dans ").nth(1)).collect();
+        assert_eq!(delays, ["10 ms", "20 ms", "40 ms", "40 ms"]);
+    }
+
+    #[test]
+    fn stop_cancels_a_pending_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let supervisor = WikiSupervisor::new(dir.path().to_path_buf(), policy(1000, 1000, 5));
+        let (launcher, calls) = counting(|_| Ok(exit_command(1)));
+        supervisor.start(launcher).unwrap();
+        wait_for(&supervisor, WikiState::Backoff);
+
+        supervisor.stop();
+        thread::sleep(Duration::from_millis(1500));
+        let status = supervisor.status();
+        assert_eq!(status.state, WikiState::Stopped);
+        assert_eq!(status.last_exit_code, Some(1));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn concurrent_starts_launch_a_single_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let supervisor = WikiSupervisor::new(dir.path().to_path_buf(), policy(10, 10, 5));
+        supervisor.set_stop_grace(Duration::ZERO);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let starts: Vec<_> = (0..2)
+            .map(|_| {
+                let (supervisor, calls, barrier) = (supervisor.clone(), calls.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    supervisor.start(Box::new(move || {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        Ok(sleep_command())
+                    }))
+                })
+            })
+            .collect();
+        let mut launched: Vec<bool> = starts.into_iter().map(|start| start.join().unwrap().unwrap()).collect();
+        launched.sort();
+        assert_eq!(launched, [false, true]);
+
+        wait_for(&supervisor, WikiState::Running);
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        supervisor.stop();
+    }
+
+    #[test]
+    fn restart_replaces_the_running_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let supervisor = WikiSupervisor::new(dir.path().to_path_buf(), policy(10, 10, 5));
+        supervisor.set_stop_grace(Duration::ZERO);
+        let (launcher, calls) = counting(|_| Ok(sleep_command()));
+        supervisor.start(launcher).unwrap();
+        let first = wait_for(&supervisor, WikiState::Running).pid;
+
+        supervisor.restart().unwrap();
+        thread::sleep(Duration::from_millis(600));
+        let status = supervisor.status();
+        assert_eq!(status.state, WikiState::Running);
+        assert_ne!(status.pid, first);
+        assert_eq!(status.restarts, 0);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        supervisor.stop();
+    }
+
+    #[test]
+    fn failed_relaunch_is_retried_and_failed_first_launch_is_final() {
+        let dir = tempfile::tempdir().unwrap();
+        let supervisor = WikiSupervisor::new(dir.path().to_path_buf(), policy(10, 10, 3));
+        let states = Arc::new(Mutex::new(Vec::new()));
+        let seen = states.clone();
+        supervisor.set_listener(move |status| seen.lock().unwrap().push(status.state));
+        let (launcher, _) = counting(|call| match call {
+            0 => Ok(exit_command(2)),
+            _ => Err("node introuvable".to_string()),
+        });
+        supervisor.start(launcher).unwrap();
+
+        let status = wait_for(&supervisor, WikiState::CrashLoop);
+        assert!(!states.lock().unwrap().contains(&WikiState::Stopped));
+        assert!(states.lock().unwrap().contains(&WikiState::Backoff));
+        assert_eq!(status.restarts, 3);
+
+        let supervisor = WikiSupervisor::new(dir.path().join("first"), policy(10, 10, 3));
+        let (launcher, calls) = counting(|_| Err("node introuvable".to_string()));
+        assert!(supervisor.start(launcher).is_err());
+        let status = supervisor.status();
+        assert_eq!(status.state, WikiState::Failed);
+        assert_eq!(status.last_error.as_deref(), Some("node introuvable"));
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
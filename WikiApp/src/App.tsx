@@ -1,14 +1,55 @@
 import { useState, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { Loader2, Server, CheckCircle, AlertCircle } from "lucide-react";
 import "./App.css";
 
+// Evénement émis par le backend (voir src-tauri/src/startup.rs)
+type StartupEvent = {
+  phase: string;
+  status: "started" | "failed";
+  label: string;
+  percent: number;
+  elapsed_ms: number;
+  error: string | null;
+};
+
+// Bilan renvoyé par check_health et émis avec "health-changed" (voir src-tauri/src/health.rs)
+type HealthStatus = "ok" | "unknown" | "degraded" | "down";
+type HealthCheck = { status: HealthStatus; latency_ms: number | null; detail: string | null };
+type HealthReport = {
+  checked_at: string;
+  status: HealthStatus;
+  database: HealthCheck;
+  wiki_http: HealthCheck;
+  wiki_graphql: HealthCheck;
+  postgres_process: HealthCheck;
+  wiki_process: HealthCheck;
+};
+
 function App() {
   const [status, setStatus] = useState<"checking" | "starting" | "ready" | "error">("checking");
   const [message, setMessage] = useState("Vérification du moteur Docker...");
+  const [percent, setPercent] = useState(0);
+  const [failedStep, setFailedStep] = useState<string | null>(null);
 
   useEffect(() => {
+    const unlisten = listen<StartupEvent>("startup-progress", (event) => {
+      const e = event.payload;
+      const seconds = (e.elapsed_ms / 1000).toFixed(1);
+      if (e.status === "failed") {
+        setFailedStep(e.label);
+        setStatus("error");
+        setMessage(e.error ?? "Erreur inconnue");
+      } else {
+        setPercent(e.percent);
+        setMessage(`${e.label}... (${seconds}s)`);
+      }
+    });
     initSystem();
+    return () => {
+      unlisten.then((f) => f());
+    };
   }, []);
 
   async function initSystem() {
@@ -36,9 +77,9 @@ function App() {
 
         try {
           // On demande à Rust si le serveur répond 200 OK
-          const isHealthy = await invoke("check_health");
+          const report = await invoke<HealthReport>("check_health");
 
-          if (isHealthy) {
+          if (report.wiki_http.status === "ok" || report.wiki_http.status === "degraded") {
             setMessage("Serveur prêt ! Lancement...");
             await new Promise(r => setTimeout(r, 1000));
             break;
@@ -55,8 +96,12 @@ function App() {
         throw new Error("Délai dépassé. Wiki.js ne répond pas.");
       }
 
+      // Le port de Wiki.js est choisi dynamiquement par le launcher
+      const wikiUrl = await invoke<string>("wiki_url");
+      // Lancé depuis un lien wikitools:// : ouvrir directement la page demandée
+      const pendingPage = await invoke<string | null>("take_pending_page");
       setStatus("ready");
-      window.location.href = "http://localhost:3000";
+      window.location.href = pendingPage ?? wikiUrl;
 
     } catch (e) {
       console.error(e);
@@ -98,6 +143,10 @@ function App() {
 
         <h1 style={{ fontSize: "1.5rem", marginBottom: "1rem" }}>WikiTools Launcher</h1>
 
+        {status === "error" && failedStep && (
+          <p style={{ color: "#ef4444", fontWeight: "bold", marginBottom: "0.5rem" }}>Echec à l'étape : {failedStep}</p>
+        )}
+
         <div style={{ display: "flex", alignItems: "center", justifyContent: "center", gap: "10px", marginBottom: "0.5rem" }}>
           {status === "starting" || status === "checking" ? (
             <Loader2 className="spin" size={24} />
@@ -107,6 +156,12 @@ function App() {
           <span style={{ fontSize: "1.1rem" }}>{message}</span>
         </div>
 
+        {status !== "error" && (
+          <div style={{ width: "100%", height: "6px", background: "#1e293b", borderRadius: "3px", overflow: "hidden" }}>
+            <div style={{ width: `${percent}%`, height: "100%", background: "#3b82f6", transition: "width 0.3s" }} />
+          </div>
+        )}
+
         <p style={{ color: "#64748b", marginTop: "2rem", fontSize: "0.9rem" }}>
           Propulsé par Wiki.js & PostgreSQL
         </p>